@@ -0,0 +1,839 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Run commands in a persistent Python interpreter.
+
+Initializing a Python interpreter and importing an application's modules can
+dominate the run time of a frequently invoked command line application.
+
+A *command server* is a process which initializes a Python interpreter once
+and then runs commands on behalf of *clients*. A client is an invocation of
+the same executable which sends its arguments, environment variables, current
+working directory, and standard input, output, and error file descriptors to
+the server over a Unix domain socket and exits with the exit code of the
+command.
+
+The server forks a process to run each command. That process inherits the
+initialized interpreter and modules preloaded by the server, adopts the
+state of the client, and runs the configured code as if it were the client.
+Commands therefore don't see state mutated by other commands. Signals
+received by the client are forwarded to the process running its command.
+
+A client finding no server listening on the socket spawns one in a new
+session and sends its command to it once it is listening. If the server
+can't be spawned or doesn't accept the command, the client runs the command
+itself. The server exits after being idle for `IDLE_TIMEOUT` or when it
+notices its executable was modified.
+
+# Protocol
+
+The client sends a little-endian `u32` holding the length of the request
+along with its standard input, output, and error file descriptors as
+`SCM_RIGHTS` ancillary data, followed by the request. The request consists
+of the number of arguments and the arguments, the current working directory,
+and the number of environment variables and their names and values. Counts
+are little-endian `u32`. Other values are raw bytes prefixed by their length
+as a little-endian `u32`.
+
+Once a process is forked to run the command, the server sends its process ID
+as a little-endian `i32`. When the command finishes, the server sends its
+exit code as a little-endian `i32`. If the connection is closed before the
+process ID is sent, the command was not run.
+
+# Security
+
+The socket is only accessible by the user running the server and the server
+rejects connections from processes running as other users. Clients run
+commands as the user of the server, with the arguments, environment and
+working directory they choose.
+*/
+
+use {
+    super::config::PythonConfig,
+    super::pyinterp::MainPythonInterpreter,
+    super::pystr::{osstr_to_pyobject, osstring_to_bytes},
+    cpython::{
+        NoArgs, ObjectProtocol, PyBytes, PyClone, PyDict, PyList, PyObject, PyResult, Python,
+    },
+    std::env,
+    std::ffi::OsString,
+    std::fs,
+    std::io::{Read, Write},
+    std::mem::size_of,
+    std::os::unix::ffi::{OsStrExt, OsStringExt},
+    std::os::unix::fs::MetadataExt,
+    std::os::unix::io::{AsRawFd, RawFd},
+    std::os::unix::net::{UnixListener, UnixStream},
+    std::os::unix::process::CommandExt,
+    std::path::{Path, PathBuf},
+    std::process::{Command, Stdio},
+    std::sync::atomic::{AtomicI32, Ordering},
+    std::time::{Duration, Instant, SystemTime},
+};
+
+/// Environment variable holding the socket path of a command server to run.
+///
+/// Clients set it when spawning a server. It can also be set to start a
+/// server explicitly.
+pub const COMMAND_SERVER_SOCKET_ENV: &str = "PYEMBED_COMMAND_SERVER_SOCKET";
+
+/// How long a server waits for a command before exiting.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// How long a client waits for a server it spawned to listen on the socket.
+const SPAWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a server waits for a client to send its request.
+///
+/// The server accepts one request at a time. So this bounds how long a
+/// stalled client delays other clients.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long a client waits for the server to start running its command.
+///
+/// This exceeds `REQUEST_TIMEOUT` so clients queued behind a stalled client
+/// don't give up first.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Maximum size of a request, in bytes.
+const MAX_REQUEST_SIZE: usize = 16 * 1024 * 1024;
+
+/// Signals a client forwards to the process running its command.
+const FORWARDED_SIGNALS: &[libc::c_int] = &[
+    libc::SIGHUP,
+    libc::SIGINT,
+    libc::SIGQUIT,
+    libc::SIGTERM,
+    libc::SIGUSR1,
+    libc::SIGUSR2,
+    libc::SIGWINCH,
+];
+
+/// Process ID of the process running the command of this client.
+static COMMAND_PID: AtomicI32 = AtomicI32::new(0);
+
+/// Describes why `run_command_server()` returned.
+pub(crate) enum CommandServerExit {
+    /// The server stopped accepting commands.
+    Stopped,
+    /// The current process was forked to run a command.
+    ///
+    /// The process adopted the state of the client. It should run the
+    /// configured code and exit with its exit code.
+    RunCommand,
+}
+
+/// File descriptors received from a client, which are closed when dropped.
+struct ReceivedFds(Vec<RawFd>);
+
+impl Drop for ReceivedFds {
+    fn drop(&mut self) {
+        for fd in &self.0 {
+            unsafe {
+                libc::close(*fd);
+            }
+        }
+    }
+}
+
+/// A command sent by a client.
+struct CommandRequest {
+    args: Vec<OsString>,
+    cwd: PathBuf,
+    env: Vec<(OsString, OsString)>,
+    /// Standard input, output, and error of the client.
+    stdio: ReceivedFds,
+}
+
+fn write_u32(dest: &mut Vec<u8>, value: usize) {
+    dest.extend_from_slice(&(value as u32).to_le_bytes());
+}
+
+fn write_bytes(dest: &mut Vec<u8>, value: &[u8]) {
+    write_u32(dest, value.len());
+    dest.extend_from_slice(value);
+}
+
+fn read_u32(reader: &mut &[u8]) -> Result<usize, &'static str> {
+    let mut buf = [0; 4];
+    reader
+        .read_exact(&mut buf)
+        .or_else(|_| Err("truncated command request"))?;
+
+    Ok(u32::from_le_bytes(buf) as usize)
+}
+
+fn read_bytes<'b>(reader: &mut &'b [u8]) -> Result<&'b [u8], &'static str> {
+    let len = read_u32(reader)?;
+
+    if reader.len() < len {
+        return Err("truncated command request");
+    }
+
+    let (value, remaining) = reader.split_at(len);
+    *reader = remaining;
+
+    Ok(value)
+}
+
+fn read_os_string(reader: &mut &[u8]) -> Result<OsString, &'static str> {
+    Ok(OsString::from_vec(read_bytes(reader)?.to_vec()))
+}
+
+/// Send data along with file descriptors over a Unix domain socket.
+fn send_with_fds(stream: &UnixStream, data: &[u8], fds: &[RawFd]) -> std::io::Result<()> {
+    let fds_len = (fds.len() * size_of::<RawFd>()) as u32;
+    let control_len = unsafe { libc::CMSG_SPACE(fds_len) } as usize;
+    // A u64 buffer ensures the control messages are suitably aligned.
+    let mut control = vec![0u64; (control_len + 7) / 8];
+
+    let mut iov = libc::iovec {
+        iov_base: data.as_ptr() as *mut libc::c_void,
+        iov_len: data.len(),
+    };
+
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = control_len as _;
+
+    let sent = unsafe {
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(fds_len) as _;
+        std::ptr::copy_nonoverlapping(fds.as_ptr(), libc::CMSG_DATA(cmsg) as *mut RawFd, fds.len());
+
+        libc::sendmsg(stream.as_raw_fd(), &msg, 0)
+    };
+
+    if sent < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    // The file descriptors were sent with the first byte. Send any remaining data.
+    let mut stream = stream;
+    stream.write_all(&data[sent as usize..])
+}
+
+/// Receive data along with file descriptors over a Unix domain socket.
+///
+/// Returns the number of bytes received and the received file descriptors.
+fn receive_with_fds(
+    stream: &UnixStream,
+    data: &mut [u8],
+    max_fds: usize,
+) -> std::io::Result<(usize, ReceivedFds)> {
+    let control_len = unsafe { libc::CMSG_SPACE((max_fds * size_of::<RawFd>()) as u32) } as usize;
+    let mut control = vec![0u64; (control_len + 7) / 8];
+
+    let mut iov = libc::iovec {
+        iov_base: data.as_mut_ptr() as *mut libc::c_void,
+        iov_len: data.len(),
+    };
+
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = control_len as _;
+
+    let received = unsafe { libc::recvmsg(stream.as_raw_fd(), &mut msg, 0) };
+
+    if received < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    let mut fds = ReceivedFds(Vec::new());
+
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                let data = libc::CMSG_DATA(cmsg) as *const RawFd;
+                let count =
+                    ((*cmsg).cmsg_len as usize - libc::CMSG_LEN(0) as usize) / size_of::<RawFd>();
+
+                for i in 0..count {
+                    fds.0.push(std::ptr::read_unaligned(data.add(i)));
+                }
+            }
+
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+
+    if msg.msg_flags & libc::MSG_CTRUNC != 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "too many file descriptors received",
+        ));
+    }
+
+    Ok((received as usize, fds))
+}
+
+impl CommandRequest {
+    /// Send the command of the current process to a server.
+    fn send_current(stream: &UnixStream) -> std::io::Result<()> {
+        let mut request = Vec::new();
+
+        let args = env::args_os().collect::<Vec<_>>();
+        write_u32(&mut request, args.len());
+        for arg in &args {
+            write_bytes(&mut request, arg.as_bytes());
+        }
+
+        write_bytes(&mut request, env::current_dir()?.as_os_str().as_bytes());
+
+        let vars = env::vars_os().collect::<Vec<_>>();
+        write_u32(&mut request, vars.len());
+        for (key, value) in &vars {
+            write_bytes(&mut request, key.as_bytes());
+            write_bytes(&mut request, value.as_bytes());
+        }
+
+        send_with_fds(stream, &(request.len() as u32).to_le_bytes(), &[0, 1, 2])?;
+
+        let mut stream = stream;
+        stream.write_all(&request)
+    }
+
+    /// Receive a command from a client.
+    fn receive(stream: &UnixStream) -> Result<CommandRequest, &'static str> {
+        let mut header = [0; 4];
+        let (received, stdio) = receive_with_fds(stream, &mut header, 3)
+            .or_else(|_| Err("unable to receive command request"))?;
+
+        if stdio.0.len() != 3 {
+            return Err("command request did not contain stdio file descriptors");
+        }
+
+        let mut stream = stream;
+        stream
+            .read_exact(&mut header[received..])
+            .or_else(|_| Err("truncated command request"))?;
+
+        let len = u32::from_le_bytes(header) as usize;
+        if len > MAX_REQUEST_SIZE {
+            return Err("command request is too large");
+        }
+
+        let mut data = vec![0; len];
+        stream
+            .read_exact(&mut data)
+            .or_else(|_| Err("truncated command request"))?;
+
+        let mut reader = &data[..];
+
+        let mut args = Vec::new();
+        for _ in 0..read_u32(&mut reader)? {
+            args.push(read_os_string(&mut reader)?);
+        }
+
+        let cwd = PathBuf::from(read_os_string(&mut reader)?);
+
+        let mut env = Vec::new();
+        for _ in 0..read_u32(&mut reader)? {
+            let key = read_os_string(&mut reader)?;
+            let value = read_os_string(&mut reader)?;
+            env.push((key, value));
+        }
+
+        Ok(CommandRequest {
+            args,
+            cwd,
+            env,
+            stdio,
+        })
+    }
+
+    /// Make the current process adopt the state of the client.
+    fn adopt(self, py: Python, config: &PythonConfig) -> Result<(), &'static str> {
+        let CommandRequest {
+            args,
+            cwd,
+            mut env,
+            mut stdio,
+        } = self;
+
+        for (target, fd) in stdio.0.drain(..).enumerate() {
+            let target = target as RawFd;
+
+            if fd != target {
+                let res = unsafe { libc::dup2(fd, target) };
+                unsafe {
+                    libc::close(fd);
+                }
+
+                if res < 0 {
+                    return Err("unable to install stdio file descriptors");
+                }
+            }
+        }
+
+        reopen_stdio(py, config).or_else(|_| Err("unable to reopen stdio streams"))?;
+
+        env::set_current_dir(&cwd)
+            .or_else(|_| Err("unable to change to the working directory of the command"))?;
+
+        // TERMINFO_DIRS is resolved by the server. Keep it unless the client
+        // defines it.
+        if let Some(value) = env::var_os("TERMINFO_DIRS") {
+            if !env.iter().any(|(key, _)| key == "TERMINFO_DIRS") {
+                env.push((OsString::from("TERMINFO_DIRS"), value));
+            }
+        }
+
+        // Modify os.environ so it agrees with the process environment.
+        let os = py.import("os").or_else(|_| Err("unable to import os"))?;
+        let environ = os
+            .get(py, "environb")
+            .or_else(|_| Err("unable to obtain os.environb"))?;
+        environ
+            .call_method(py, "clear", NoArgs, None)
+            .or_else(|_| Err("unable to clear os.environb"))?;
+
+        for (key, value) in &env {
+            // Like at process startup, variables Python can't set are ignored.
+            let _ = environ.set_item(
+                py,
+                PyBytes::new(py, key.as_bytes()),
+                PyBytes::new(py, value.as_bytes()),
+            );
+        }
+
+        let sys = py.import("sys").or_else(|_| Err("unable to import sys"))?;
+
+        let args_objs = args
+            .iter()
+            .map(|arg| osstr_to_pyobject(py, arg, None))
+            .collect::<Result<Vec<PyObject>, &'static str>>()?;
+        sys.add(py, "argv", PyList::new(py, &args_objs))
+            .or_else(|_| Err("unable to set sys.argv"))?;
+
+        if config.argvb {
+            let args_objs = args
+                .into_iter()
+                .map(|arg| osstring_to_bytes(py, arg))
+                .collect::<Vec<PyObject>>();
+            sys.add(py, "argvb", PyList::new(py, &args_objs))
+                .or_else(|_| Err("unable to set sys.argvb"))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Replace `sys.stdin`, `sys.stdout`, and `sys.stderr` with streams for the
+/// client's file descriptors.
+///
+/// The streams created during interpreter initialization are buffered
+/// according to the stdio of the server. So create new streams like Python
+/// does at startup.
+fn reopen_stdio(py: Python, config: &PythonConfig) -> PyResult<()> {
+    let io = py.import("io")?;
+    let os = py.import("os")?;
+    let sys = py.import("sys")?;
+
+    for &(fd, name, mode) in &[(0, "stdin", "r"), (1, "stdout", "w"), (2, "stderr", "w")] {
+        let current = sys.get(py, name)?;
+        let isatty: bool = os.call(py, "isatty", (fd,), None)?.extract(py)?;
+
+        let line_buffered = fd != 0 && (fd == 2 || isatty || config.unbuffered_stdio);
+
+        let kwargs = PyDict::new(py);
+        kwargs.set_item(py, "buffering", if line_buffered { 1 } else { -1 })?;
+        kwargs.set_item(py, "encoding", current.getattr(py, "encoding")?)?;
+        kwargs.set_item(py, "errors", current.getattr(py, "errors")?)?;
+        kwargs.set_item(py, "closefd", false)?;
+
+        let stream = io.call(py, "open", (fd, mode), Some(&kwargs))?;
+        sys.add(py, &format!("__{}__", name), stream.clone_ref(py))?;
+        sys.add(py, name, stream)?;
+    }
+
+    Ok(())
+}
+
+/// Flush `sys.stdout` and `sys.stderr`, ignoring errors.
+fn flush_stdio(py: Python) {
+    if let Ok(sys) = py.import("sys") {
+        for name in &["stdout", "stderr"] {
+            if let Ok(stream) = sys.get(py, name) {
+                let _ = stream.call_method(py, "flush", NoArgs, None);
+            }
+        }
+    }
+}
+
+/// Fork the current process.
+///
+/// `os.fork()` is used so fork handlers registered with Python run.
+fn fork(py: Python) -> Result<libc::pid_t, &'static str> {
+    py.import("os")
+        .and_then(|os| os.call(py, "fork", NoArgs, None))
+        .and_then(|pid| pid.extract(py))
+        .or_else(|_| Err("unable to fork"))
+}
+
+/// Wait for a process to exit and resolve its exit code like a shell would.
+fn wait_for_exit_code(pid: libc::pid_t) -> i32 {
+    let mut status = 0;
+
+    unsafe {
+        while libc::waitpid(pid, &mut status, 0) < 0 {
+            if std::io::Error::last_os_error().kind() != std::io::ErrorKind::Interrupted {
+                return 255;
+            }
+        }
+
+        if libc::WIFEXITED(status) {
+            libc::WEXITSTATUS(status)
+        } else if libc::WIFSIGNALED(status) {
+            128 + libc::WTERMSIG(status)
+        } else {
+            255
+        }
+    }
+}
+
+/// Send the process ID and, once it exits, the exit code of a command to its client.
+fn relay_command(stream: &UnixStream, pid: libc::pid_t) {
+    let mut stream = stream;
+
+    // The command keeps running if the client went away.
+    let _ = stream.write_all(&pid.to_le_bytes());
+    let code = wait_for_exit_code(pid);
+    let _ = stream.write_all(&code.to_le_bytes());
+}
+
+/// Resolve the user ID of the process connected to a socket.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn peer_uid(stream: &UnixStream) -> Option<libc::uid_t> {
+    let mut cred: libc::ucred = unsafe { std::mem::zeroed() };
+    let mut len = size_of::<libc::ucred>() as libc::socklen_t;
+
+    let res = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+
+    if res == 0 {
+        Some(cred.uid)
+    } else {
+        None
+    }
+}
+
+/// Resolve the user ID of the process connected to a socket.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn peer_uid(stream: &UnixStream) -> Option<libc::uid_t> {
+    extern "C" {
+        fn getpeereid(
+            socket: libc::c_int,
+            euid: *mut libc::uid_t,
+            egid: *mut libc::gid_t,
+        ) -> libc::c_int;
+    }
+
+    let mut uid = 0;
+    let mut gid = 0;
+
+    if unsafe { getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) } == 0 {
+        Some(uid)
+    } else {
+        None
+    }
+}
+
+fn executable_mtime() -> Option<SystemTime> {
+    fs::metadata(env::current_exe().ok()?).ok()?.modified().ok()
+}
+
+fn socket_ino(path: &Path) -> Option<u64> {
+    fs::metadata(path).ok().map(|metadata| metadata.ino())
+}
+
+/// Resolve the socket path to listen on if this process is a command server.
+pub(crate) fn command_server_socket(config: &PythonConfig) -> Option<PathBuf> {
+    config.command_server_socket_env.as_ref()?;
+
+    env::var_os(COMMAND_SERVER_SOCKET_ENV).map(PathBuf::from)
+}
+
+impl<'a> MainPythonInterpreter<'a> {
+    /// Run a command server listening on a Unix domain socket.
+    ///
+    /// Modules in `command_server_preload_modules` are imported. Then commands
+    /// are accepted until the server is idle for `IDLE_TIMEOUT` or its
+    /// executable is modified. Each command is run in a forked process, in
+    /// which this returns `CommandServerExit::RunCommand`.
+    pub(crate) fn run_command_server(
+        &mut self,
+        path: &Path,
+    ) -> Result<CommandServerExit, &'static str> {
+        // Commands run with the environment of their client.
+        env::remove_var(COMMAND_SERVER_SOCKET_ENV);
+
+        // Forked processes don't inherit the sampling thread. Commands start
+        // their own profiler instead.
+        self.stop_sampling_profiler();
+
+        let py = self.acquire_gil();
+
+        for name in self.config.command_server_preload_modules.clone() {
+            py.import(&name)
+                .or_else(|_| Err("unable to preload module"))?;
+        }
+
+        // Buffered output would otherwise be written by every command.
+        flush_stdio(py);
+
+        // Defer to a server already listening on the socket.
+        if UnixStream::connect(path).is_ok() {
+            return Ok(CommandServerExit::Stopped);
+        }
+
+        // The socket of a server which didn't exit cleanly may linger.
+        let _ = fs::remove_file(path);
+
+        // Only the current user may connect to the socket.
+        let listener = unsafe {
+            let mask = libc::umask(0o177);
+            let listener = UnixListener::bind(path);
+            libc::umask(mask);
+
+            listener
+        }
+        .or_else(|_| Err("unable to listen on command server socket"))?;
+        let ino = socket_ino(path);
+
+        // Processes forked to relay commands are reaped automatically.
+        unsafe {
+            libc::signal(libc::SIGCHLD, libc::SIG_IGN);
+        }
+
+        let res = self.serve_commands(py, listener);
+
+        if let Ok(CommandServerExit::RunCommand) = res {
+            self.start_sampling_profiler();
+            return res;
+        }
+
+        // Don't remove the socket of a server which replaced this one.
+        if socket_ino(path) == ino {
+            let _ = fs::remove_file(path);
+        }
+
+        res
+    }
+
+    fn serve_commands(
+        &mut self,
+        py: Python,
+        listener: UnixListener,
+    ) -> Result<CommandServerExit, &'static str> {
+        let mtime = executable_mtime();
+
+        loop {
+            let mut pollfd = libc::pollfd {
+                fd: listener.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+
+            let res =
+                unsafe { libc::poll(&mut pollfd, 1, IDLE_TIMEOUT.as_millis() as libc::c_int) };
+
+            if res < 0 {
+                if std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted {
+                    continue;
+                }
+
+                return Err("unable to wait for commands");
+            } else if res == 0 {
+                return Ok(CommandServerExit::Stopped);
+            }
+
+            let stream = match listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(_) => continue,
+            };
+
+            // Closing the connection without running the command lets the
+            // client run it with the new executable.
+            if executable_mtime() != mtime {
+                return Ok(CommandServerExit::Stopped);
+            }
+
+            if peer_uid(&stream) != Some(unsafe { libc::geteuid() }) {
+                continue;
+            }
+
+            if stream.set_read_timeout(Some(REQUEST_TIMEOUT)).is_err() {
+                continue;
+            }
+
+            let request = match CommandRequest::receive(&stream) {
+                Ok(request) => request,
+                Err(_) => continue,
+            };
+
+            // Fork a process relaying the exit code of the command, so the
+            // server can accept other commands while it runs.
+            match fork(py) {
+                Ok(0) => {}
+                _ => continue,
+            }
+
+            // Neither the relay nor the command accept connections.
+            drop(listener);
+
+            unsafe {
+                libc::signal(libc::SIGCHLD, libc::SIG_DFL);
+            }
+
+            let pid = match fork(py) {
+                Ok(pid) => pid,
+                Err(_) => unsafe { libc::_exit(1) },
+            };
+
+            if pid == 0 {
+                drop(stream);
+
+                if let Err(msg) = request.adopt(py, &self.config) {
+                    eprintln!("error running command: {}", msg);
+                    unsafe { libc::_exit(1) }
+                }
+
+                return Ok(CommandServerExit::RunCommand);
+            }
+
+            drop(request);
+            relay_command(&stream, pid);
+
+            unsafe { libc::_exit(0) }
+        }
+    }
+}
+
+extern "C" fn forward_signal(signum: libc::c_int) {
+    let pid = COMMAND_PID.load(Ordering::SeqCst);
+
+    if pid > 0 {
+        unsafe {
+            libc::kill(pid, signum);
+        }
+    }
+}
+
+/// Spawn a command server and connect to it once it is listening.
+fn spawn_server(path: &Path) -> Option<UnixStream> {
+    let mut command = Command::new(env::current_exe().ok()?);
+    command
+        .env(COMMAND_SERVER_SOCKET_ENV, path)
+        .current_dir("/")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+
+    // Detach the server from the terminal and process group of the client.
+    unsafe {
+        command.pre_exec(|| {
+            libc::setsid();
+            Ok(())
+        });
+    }
+
+    let mut server = command.spawn().ok()?;
+    let start = Instant::now();
+
+    loop {
+        if let Ok(stream) = UnixStream::connect(path) {
+            return Some(stream);
+        }
+
+        // The server exits if it fails to initialize.
+        if server.try_wait().ok()?.is_some() || start.elapsed() > SPAWN_TIMEOUT {
+            return None;
+        }
+
+        std::thread::sleep(Duration::from_millis(5));
+    }
+}
+
+/// Run the command of the current process with a command server.
+///
+/// If `command_server_socket_env` is set in the config and the environment
+/// variable it refers to is set, the arguments, environment variables,
+/// current working directory, and stdio file descriptors of the current
+/// process are sent to the command server listening on the Unix domain socket
+/// at the path held by the environment variable. If no server is listening,
+/// one is spawned by executing the current executable with
+/// `PYEMBED_COMMAND_SERVER_SOCKET` set. The server runs the configured code in a
+/// process forked from its initialized interpreter. Signals received by the
+/// current process are forwarded to that process.
+///
+/// This should be called before constructing a `MainPythonInterpreter`.
+///
+/// Returns the exit code of the command. Returns `None` if the command wasn't
+/// run, in which case the current process should run it.
+pub fn run_command_client(config: &PythonConfig) -> Option<i32> {
+    let key = config.command_server_socket_env.as_ref()?;
+
+    // Servers are executions of the same executable and run commands themselves.
+    if env::var_os(COMMAND_SERVER_SOCKET_ENV).is_some() {
+        return None;
+    }
+
+    let path = env::var_os(key).filter(|path| !path.is_empty())?;
+    // Servers have a different working directory.
+    let path = env::current_dir().ok()?.join(path);
+
+    let stream = match UnixStream::connect(&path) {
+        Ok(stream) => stream,
+        Err(_) => spawn_server(&path)?,
+    };
+
+    CommandRequest::send_current(&stream).ok()?;
+
+    // Give up on a server which doesn't run the command, like one which is
+    // stuck. The command is run by the current process instead.
+    stream.set_read_timeout(Some(RESPONSE_TIMEOUT)).ok()?;
+
+    let mut stream = &stream;
+    let mut buf = [0; 4];
+
+    stream.read_exact(&mut buf).ok()?;
+
+    // Commands may run for any amount of time.
+    let _ = stream.set_read_timeout(None);
+
+    COMMAND_PID.store(i32::from_le_bytes(buf), Ordering::SeqCst);
+
+    for signum in FORWARDED_SIGNALS {
+        unsafe {
+            libc::signal(
+                *signum,
+                forward_signal as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            );
+        }
+    }
+
+    match stream.read_exact(&mut buf) {
+        Ok(()) => Some(i32::from_le_bytes(buf)),
+        Err(_) => {
+            eprintln!("lost connection to command server");
+            Some(255)
+        }
+    }
+}
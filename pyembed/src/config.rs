@@ -4,7 +4,7 @@
 
 //! Data structures for configuring a Python interpreter.
 
-use {python3_sys as pyffi, std::ffi::CString};
+use {python3_sys as pyffi, std::ffi::CString, std::path::PathBuf};
 
 /// Defines which allocator to use for the raw domain.
 #[derive(Clone, Debug)]
@@ -145,7 +145,7 @@ pub struct PythonConfig {
     /// Bytecode for the importlib._bootstrap_external / _frozen_importlib_external module.
     pub frozen_importlib_external_bytecode: &'static [u8],
 
-    /// Reference to packed resources data.
+    /// References to packed resources data.
     ///
     /// The referenced data contains Python module data. It likely comes from an
     /// `include_bytes!(...)` of a file generated by PyOxidizer.
@@ -153,7 +153,80 @@ pub struct PythonConfig {
     /// The format of the data is defined by the ``python-packed-resources``
     /// crate. The data will be parsed as part of initializing the custom
     /// meta path importer during interpreter initialization.
-    pub packed_resources: &'static [u8],
+    ///
+    /// Data is layered in order: a resource in later data shadows a resource
+    /// of the same name in earlier data. e.g. the standard library can be
+    /// followed by vendored packages, the application, and fixes to any of
+    /// these, without having to rebuild the data of the others.
+    pub packed_resources: Vec<&'static [u8]>,
+
+    /// Whether to load packed resources data appended to the executable.
+    ///
+    /// If set, the executable is memory mapped and packed resources data in
+    /// its trailer is layered after `packed_resources`. The trailer can be
+    /// added or replaced without rebuilding the executable via
+    /// ``pyoxidizer packed-resources-trailer``. Interpreter initialization
+    /// fails if the executable doesn't have a trailer.
+    pub packed_resources_trailer: bool,
+
+    /// Paths to files containing packed resources data.
+    ///
+    /// Files are memory mapped when the interpreter is initialized and remain
+    /// mapped for the lifetime of the interpreter. This allows resources to be
+    /// updated without rebuilding the application.
+    ///
+    /// A leading ``$ORIGIN`` component will resolve to the directory of the
+    /// application at run-time.
+    ///
+    /// Files are layered in order after `packed_resources` and the packed
+    /// resources data in the executable's trailer. If a resource is
+    /// defined by multiple sources, the source loaded last wins.
+    pub packed_resources_paths: Vec<PathBuf>,
+
+    /// Whether to verify digests of packed resources data.
+    ///
+    /// If set, the payload digest of all packed resources data is verified when
+    /// the data is parsed and the content digest of each module is verified
+    /// before its code is loaded. Data without digests fails verification.
+    ///
+    /// Verifying the payload digest requires reading all packed resources
+    /// data, which adds overhead to interpreter startup.
+    pub verify_resources_digests: bool,
+
+    /// Whether to extract in-memory package resources to serve filesystem paths.
+    ///
+    /// ``importlib.abc.ResourceReader.resource_path()`` and APIs built on it
+    /// require resources to exist on the filesystem. If set, in-memory
+    /// resources are extracted on demand to a per-process temporary directory,
+    /// which is deleted when the interpreter exits. Otherwise,
+    /// ``FileNotFoundError`` is raised for in-memory resources.
+    pub resource_path_extraction: bool,
+
+    /// Whether to defer executing modules until their first attribute access.
+    ///
+    /// If set, modules imported from bytecode by the custom meta path importer
+    /// are executed the first time one of their attributes is accessed, like
+    /// ``importlib.util.LazyLoader``. This avoids the cost of executing modules
+    /// which are imported but never used. Errors raised by module code are
+    /// raised on first attribute access instead of on import.
+    ///
+    /// Modules imported during interpreter initialization are always executed
+    /// eagerly.
+    pub lazy_module_loading: bool,
+
+    /// Names of modules to load lazily.
+    ///
+    /// An entry also matches submodules of the named module. If empty, all
+    /// modules are eligible for lazy loading. Has no effect unless
+    /// `lazy_module_loading` is set.
+    pub lazy_modules_allow: Vec<String>,
+
+    /// Names of modules to always execute eagerly.
+    ///
+    /// An entry also matches submodules of the named module. Takes precedence
+    /// over `lazy_modules_allow`. Modules relying on import-time side-effects,
+    /// such as registering plugins, should be listed here.
+    pub lazy_modules_deny: Vec<String>,
 
     /// Extra extension modules to make available to the interpreter.
     ///
@@ -192,6 +265,55 @@ pub struct PythonConfig {
     /// loaded in ``sys.modules``.
     pub write_modules_directory_env: Option<String>,
 
+    /// Whether loaded modules files list modules in the order they were loaded.
+    ///
+    /// If set, modules files written due to `write_modules_directory_env`
+    /// list modules in the order they were first executed by the custom meta
+    /// path importer, followed by other modules in ``sys.modules`` in
+    /// alphabetical order. Otherwise, all modules are listed in alphabetical
+    /// order. Such files can be used to lay out resources data in load order.
+    pub write_modules_load_order: bool,
+
+    /// Environment variable holding the directory to write an import trace.
+    ///
+    /// If this value is set and the environment variable it refers to is set
+    /// when the interpreter is initialized, modules imported by the custom
+    /// meta path importer are traced. The trace records which module imported
+    /// each module, whether it came from memory, the filesystem, or is a
+    /// built-in or frozen module, and the wall time spent obtaining its code
+    /// and executing it. On interpreter shutdown, we will write the trace to
+    /// ``imports-<random>.json`` and, as folded stacks suitable for generating
+    /// flame graphs, to ``imports-<random>.folded`` in the directory specified.
+    pub write_import_trace_directory_env: Option<String>,
+
+    /// Environment variable holding the path of a command server socket.
+    ///
+    /// If this value is set and the environment variable it refers to is set,
+    /// ``run_command_client()`` sends the command of the current process to a
+    /// command server listening on a Unix domain socket at that path, spawning
+    /// the server if needed. The server runs the command in a process forked
+    /// from its already initialized interpreter. Only supported on Unix.
+    pub command_server_socket_env: Option<String>,
+
+    /// Names of modules a command server imports before accepting commands.
+    ///
+    /// Commands are run in processes forked from the server. So they don't
+    /// need to import these modules themselves.
+    pub command_server_preload_modules: Vec<String>,
+
+    /// Environment variable holding the directory to write a sampling profile.
+    ///
+    /// If this value is set and the environment variable it refers to is set
+    /// when the interpreter is initialized, a background thread records the
+    /// Python stacks of all threads every `profile_sampling_interval_ms`
+    /// milliseconds. On interpreter shutdown, we will write the samples as
+    /// folded stacks suitable for generating flame graphs to
+    /// ``profile-<random>.folded`` in the directory specified.
+    pub write_profile_directory_env: Option<String>,
+
+    /// Interval in milliseconds between samples of the sampling profiler.
+    pub profile_sampling_interval_ms: u64,
+
     /// Defines what code to run by default.
     ///
     pub run: PythonRunMode,
@@ -225,7 +347,14 @@ impl Default for PythonConfig {
             verbose: 0,
             frozen_importlib_bytecode: &[],
             frozen_importlib_external_bytecode: &[],
-            packed_resources: &[],
+            packed_resources: vec![],
+            packed_resources_trailer: false,
+            packed_resources_paths: vec![],
+            verify_resources_digests: false,
+            resource_path_extraction: false,
+            lazy_module_loading: false,
+            lazy_modules_allow: vec![],
+            lazy_modules_deny: vec![],
             extra_extension_modules: vec![],
             argvb: false,
             sys_frozen: false,
@@ -237,6 +366,12 @@ impl Default for PythonConfig {
             },
             terminfo_resolution: TerminfoResolution::Dynamic,
             write_modules_directory_env: None,
+            write_modules_load_order: false,
+            write_import_trace_directory_env: None,
+            command_server_socket_env: None,
+            command_server_preload_modules: vec![],
+            write_profile_directory_env: None,
+            profile_sampling_interval_ms: 10,
             run: PythonRunMode::None,
         }
     }
@@ -0,0 +1,329 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Tracing of module imports.
+
+When enabled, the custom meta path importer records every module it executes:
+which module was executing when it was imported, where its code came from,
+and how much wall time was spent obtaining its code object and executing it.
+
+Traces can be written as JSON and as *folded stacks*, the input format of
+flame graph tools such as `flamegraph.pl` and `inferno`.
+
+The importer can also record just the order modules are first executed in,
+which can be used to lay out resources data in load order.
+*/
+
+use {
+    std::collections::{HashMap, HashSet},
+    std::fs,
+    std::io::Write,
+    std::path::Path,
+    std::sync::Mutex,
+    std::thread::ThreadId,
+    std::time::{Duration, Instant},
+};
+
+/// Describes where the code of an imported module comes from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImportSource {
+    /// Module data is embedded in memory.
+    Memory,
+    /// Module data is read from the filesystem.
+    Filesystem,
+    /// Module is a built-in extension module.
+    Builtin,
+    /// Module is a frozen module.
+    Frozen,
+}
+
+impl ImportSource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ImportSource::Memory => "memory",
+            ImportSource::Filesystem => "filesystem",
+            ImportSource::Builtin => "builtin",
+            ImportSource::Frozen => "frozen",
+        }
+    }
+}
+
+/// Describes the import of a single module.
+#[derive(Clone, Debug)]
+struct ImportRecord {
+    /// Name of the imported module.
+    name: String,
+    /// Names of modules being executed when this module was executed.
+    ///
+    /// Outermost module first. The last entry is the importing module.
+    stack: Vec<String>,
+    /// Where the module's code comes from.
+    source: Option<ImportSource>,
+    /// Wall time spent in `exec_module()`, including importing other modules.
+    exec_module: Duration,
+    /// Wall time spent in `exec_module()`, excluding executing other modules.
+    exec_module_self: Duration,
+}
+
+/// A module whose `exec_module()` is in progress.
+struct ActiveImport {
+    /// Index of the module's record.
+    index: usize,
+    start: Instant,
+    /// Wall time spent executing other modules.
+    children: Duration,
+}
+
+#[derive(Default)]
+struct ImportTraceState {
+    /// Records of executed modules, in order of execution start.
+    records: Vec<ImportRecord>,
+    /// Stacks of in-progress module executions, per thread.
+    active: HashMap<ThreadId, Vec<ActiveImport>>,
+    /// Wall time spent obtaining code objects, per module.
+    get_code: HashMap<String, (ImportSource, Duration)>,
+}
+
+/// Records modules imported by the custom meta path importer.
+///
+/// Modules can be imported from multiple threads. Executions are tracked per
+/// thread, so the importing module is the module whose execution is in
+/// progress on the same thread.
+#[derive(Default)]
+pub struct ImportTracer {
+    state: Mutex<ImportTraceState>,
+}
+
+impl ImportTracer {
+    pub fn new() -> Self {
+        ImportTracer::default()
+    }
+
+    /// Record that `exec_module()` of a module started.
+    ///
+    /// Must be followed by a call to `finish_exec_module()` on the same thread.
+    pub fn start_exec_module(&self, name: &str) {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+
+        let active = state
+            .active
+            .entry(std::thread::current().id())
+            .or_insert_with(Vec::new);
+
+        let stack = active
+            .iter()
+            .map(|import| state.records[import.index].name.clone())
+            .collect();
+
+        active.push(ActiveImport {
+            index: state.records.len(),
+            start: Instant::now(),
+            children: Duration::default(),
+        });
+
+        state.records.push(ImportRecord {
+            name: name.to_string(),
+            stack,
+            source: None,
+            exec_module: Duration::default(),
+            exec_module_self: Duration::default(),
+        });
+    }
+
+    /// Record that the most recently started `exec_module()` on this thread finished.
+    pub fn finish_exec_module(&self, source: ImportSource) {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+
+        let active = match state.active.get_mut(&std::thread::current().id()) {
+            Some(active) => active,
+            None => return,
+        };
+
+        let import = match active.pop() {
+            Some(import) => import,
+            None => return,
+        };
+
+        let elapsed = import.start.elapsed();
+
+        if let Some(parent) = active.last_mut() {
+            parent.children += elapsed;
+        }
+
+        let record = &mut state.records[import.index];
+        record.source = Some(source);
+        record.exec_module = elapsed;
+        record.exec_module_self = elapsed.checked_sub(import.children).unwrap_or_default();
+    }
+
+    /// Record wall time spent obtaining the code object of a module.
+    pub fn record_get_code(&self, name: &str, source: ImportSource, duration: Duration) {
+        let mut state = self.state.lock().unwrap();
+
+        let entry = state
+            .get_code
+            .entry(name.to_string())
+            .or_insert((source, Duration::default()));
+        entry.1 += duration;
+    }
+
+    /// Write the trace as JSON.
+    ///
+    /// The JSON is an array of objects describing imported modules, in the
+    /// order modules started executing. Modules whose code was obtained but
+    /// which weren't executed by the importer follow. Durations are in
+    /// nanoseconds.
+    pub fn write_json<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let state = self.state.lock().unwrap();
+
+        let mut entries = Vec::new();
+
+        for record in &state.records {
+            let get_code = state.get_code.get(&record.name);
+
+            entries.push(format!(
+                "{{\"name\": {}, \"importer\": {}, \"source\": {}, \"get_code_ns\": {}, \"exec_module_ns\": {}, \"exec_module_self_ns\": {}}}",
+                json_string(&record.name),
+                match record.stack.last() {
+                    Some(importer) => json_string(importer),
+                    None => "null".to_string(),
+                },
+                match record.source.or_else(|| get_code.map(|(source, _)| *source)) {
+                    Some(source) => json_string(source.as_str()),
+                    None => "null".to_string(),
+                },
+                get_code.map_or(0, |(_, duration)| duration.as_nanos()),
+                record.exec_module.as_nanos(),
+                record.exec_module_self.as_nanos(),
+            ));
+        }
+
+        let mut get_code_only = state
+            .get_code
+            .iter()
+            .filter(|(name, _)| !state.records.iter().any(|record| &record.name == *name))
+            .collect::<Vec<_>>();
+        get_code_only.sort_by(|a, b| a.0.cmp(b.0));
+
+        for (name, (source, duration)) in get_code_only {
+            entries.push(format!(
+                "{{\"name\": {}, \"importer\": null, \"source\": {}, \"get_code_ns\": {}, \"exec_module_ns\": null, \"exec_module_self_ns\": null}}",
+                json_string(name),
+                json_string(source.as_str()),
+                duration.as_nanos(),
+            ));
+        }
+
+        writer.write_fmt(format_args!("[\n  {}\n]\n", entries.join(",\n  ")))
+    }
+
+    /// Write the trace as folded stacks.
+    ///
+    /// Each line holds the `;` delimited names of nested executing modules,
+    /// outermost first, followed by the microseconds spent executing the
+    /// innermost module, excluding executing other modules.
+    pub fn write_folded_stacks<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let state = self.state.lock().unwrap();
+
+        for record in &state.records {
+            let micros = record.exec_module_self.as_micros();
+            if micros == 0 {
+                continue;
+            }
+
+            let mut frames = record.stack.clone();
+            frames.push(record.name.clone());
+
+            writer.write_fmt(format_args!("{} {}\n", frames.join(";"), micros))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct ModuleLoadOrderState {
+    names: Vec<String>,
+    seen: HashSet<String>,
+}
+
+/// Records the order modules are first executed in by the custom meta path importer.
+#[derive(Default)]
+pub struct ModuleLoadOrder {
+    state: Mutex<ModuleLoadOrderState>,
+}
+
+impl ModuleLoadOrder {
+    pub fn new() -> Self {
+        ModuleLoadOrder::default()
+    }
+
+    /// Record that a module is being executed.
+    ///
+    /// Only the first execution of a module is recorded.
+    pub fn record(&self, name: &str) {
+        let mut state = self.state.lock().unwrap();
+
+        if !state.seen.contains(name) {
+            state.seen.insert(name.to_string());
+            state.names.push(name.to_string());
+        }
+    }
+
+    /// Obtain names of executed modules, in the order they were first executed.
+    pub fn names(&self) -> Vec<String> {
+        self.state.lock().unwrap().names.clone()
+    }
+}
+
+/// Format a string as a JSON string literal.
+fn json_string(value: &str) -> String {
+    let mut res = String::with_capacity(value.len() + 2);
+    res.push('"');
+
+    for c in value.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            '\r' => res.push_str("\\r"),
+            '\t' => res.push_str("\\t"),
+            c if (c as u32) < 0x20 => res.push_str(&format!("\\u{:04x}", c as u32)),
+            c => res.push(c),
+        }
+    }
+
+    res.push('"');
+    res
+}
+
+/// Write an import trace to a directory.
+///
+/// This will create files named ``imports-<UUID>.json`` and
+/// ``imports-<UUID>.folded`` in the directory holding the trace as JSON and as
+/// folded stacks, respectively.
+pub fn write_import_trace_to_directory(
+    tracer: &ImportTracer,
+    path: &Path,
+) -> Result<(), &'static str> {
+    fs::create_dir_all(path).or_else(|_| Err("could not create directory for import trace"))?;
+
+    let rand = uuid::Uuid::new_v4();
+
+    let mut f = fs::File::create(path.join(format!("imports-{}.json", rand)))
+        .or_else(|_| Err("could not open file for writing"))?;
+    tracer
+        .write_json(&mut f)
+        .or_else(|_| Err("could not write"))?;
+
+    let mut f = fs::File::create(path.join(format!("imports-{}.folded", rand)))
+        .or_else(|_| Err("could not open file for writing"))?;
+    tracer
+        .write_folded_stacks(&mut f)
+        .or_else(|_| Err("could not write"))?;
+
+    Ok(())
+}
@@ -9,24 +9,31 @@ This module defines a Python meta path importer and associated functionality
 for importing Python modules from memory.
 */
 
-#[cfg(windows)]
-use {
-    super::memory_dll::{free_library_memory, get_proc_address_memory, load_library_memory},
-    cpython::exc::SystemError,
-    std::ffi::{c_void, CString},
-};
 use {
+    super::import_trace::{ImportTracer, ModuleLoadOrder},
     super::pyinterp::PYOXIDIZER_IMPORTER_NAME,
-    super::python_resources::{OptimizeLevel, PythonResourcesState},
-    cpython::exc::{FileNotFoundError, ImportError, RuntimeError, ValueError},
+    super::pystr::path_to_pyobject,
+    super::python_resources::{ImportablePythonModule, OptimizeLevel, PythonResourcesState},
+    cpython::exc::{
+        FileNotFoundError, ImportError, IsADirectoryError, NotADirectoryError, OSError,
+        RuntimeError, ValueError,
+    },
     cpython::{
-        py_class, py_fn, NoArgs, ObjectProtocol, PyClone, PyDict, PyErr, PyList, PyModule,
-        PyObject, PyResult, PyString, PyTuple, Python, PythonObject,
+        py_class, py_class_prop_getter, py_fn, NoArgs, ObjectProtocol, PyBytes, PyClone, PyDict,
+        PyErr, PyList, PyModule, PyObject, PyResult, PyString, PyTuple, Python, PythonObject,
+        ToPyObject,
     },
     python3_sys as pyffi,
     python_packed_resources::data::ResourceFlavor,
     std::path::PathBuf,
     std::sync::Arc,
+    std::time::Instant,
+};
+#[cfg(windows)]
+use {
+    super::memory_dll::{free_library_memory, get_proc_address_memory, load_library_memory},
+    cpython::exc::SystemError,
+    std::ffi::{c_void, CString},
 };
 
 #[cfg(windows)]
@@ -242,6 +249,36 @@ fn load_dynamic_library(
     }
 }
 
+/// Defines which modules are loaded lazily.
+#[derive(Debug)]
+pub(crate) struct LazyModules {
+    /// Names of modules eligible for lazy loading. All modules if empty.
+    allow: Vec<String>,
+    /// Names of modules never loaded lazily.
+    deny: Vec<String>,
+}
+
+impl LazyModules {
+    pub fn new(allow: Vec<String>, deny: Vec<String>) -> Self {
+        LazyModules { allow, deny }
+    }
+
+    /// Whether a module is a candidate for lazy loading.
+    ///
+    /// Entries in the allow and deny lists match the named module and its
+    /// submodules.
+    fn is_lazy(&self, name: &str) -> bool {
+        let matches = |names: &[String]| {
+            names.iter().any(|entry| {
+                name == entry
+                    || (name.starts_with(entry.as_str()) && name[entry.len()..].starts_with('.'))
+            })
+        };
+
+        (self.allow.is_empty() || matches(&self.allow)) && !matches(&self.deny)
+    }
+}
+
 /// Holds state for the custom MetaPathFinder.
 pub(crate) struct ImporterState {
     /// `imp` Python module.
@@ -264,19 +301,29 @@ pub(crate) struct ImporterState {
     exec_fn: PyObject,
     /// Bytecode optimization level currently in effect.
     optimize_level: OptimizeLevel,
+    /// Which modules to load lazily. `None` if lazy loading is disabled.
+    lazy_modules: Option<LazyModules>,
+    /// Records imported modules. `None` if import tracing is disabled.
+    import_tracer: Option<Arc<ImportTracer>>,
+    /// Records the order modules are loaded in. `None` if disabled.
+    module_load_order: Option<Arc<ModuleLoadOrder>>,
     /// Holds state about importable resources.
-    pub resources_state: PythonResourcesState<'static, u8>,
+    ///
+    /// Shared by the importers of the main interpreter and its sub-interpreters.
+    pub resources_state: Arc<PythonResourcesState<'static, u8>>,
 }
 
 impl ImporterState {
+    #[allow(clippy::too_many_arguments)]
     fn new(
         py: Python,
         bootstrap_module: &PyModule,
         marshal_module: &PyModule,
         decode_source: PyObject,
-        resources_data: &'static [u8],
-        current_exe: PathBuf,
-        origin: PathBuf,
+        resources_state: Arc<PythonResourcesState<'static, u8>>,
+        lazy_modules: Option<LazyModules>,
+        import_tracer: Option<Arc<ImportTracer>>,
+        module_load_order: Option<Arc<ModuleLoadOrder>>,
     ) -> Result<Self, PyErr> {
         let imp_module = bootstrap_module.get(py, "_imp")?;
         let imp_module = imp_module.cast_into::<PyModule>(py)?;
@@ -301,16 +348,6 @@ impl ImporterState {
         let builtin_importer = meta_path.get_item(py, 0);
         let frozen_importer = meta_path.get_item(py, 1);
 
-        let mut resources_state = PythonResourcesState {
-            current_exe,
-            origin,
-            ..PythonResourcesState::default()
-        };
-
-        if let Err(e) = resources_state.load(resources_data) {
-            return Err(PyErr::new::<ValueError, _>(py, e));
-        }
-
         let marshal_loads = marshal_module.get(py, "loads")?;
         let call_with_frames_removed = bootstrap_module.get(py, "_call_with_frames_removed")?;
         let module_spec_type = bootstrap_module.get(py, "ModuleSpec")?;
@@ -361,6 +398,9 @@ impl ImporterState {
             decode_source,
             exec_fn,
             optimize_level,
+            lazy_modules,
+            import_tracer,
+            module_load_order,
             resources_state,
         })
     }
@@ -444,6 +484,15 @@ py_class!(class PyOxidizerFinder |py| {
     def find_distributions(&self, context: Option<PyObject> = None) -> PyResult<PyObject> {
         self.find_distributions_impl(py, context)
     }
+
+    // Data files interface.
+    def read_data_file(&self, name: &PyString) -> PyResult<PyObject> {
+        self.read_data_file_impl(py, name)
+    }
+
+    def data_file_names(&self) -> PyResult<PyObject> {
+        self.data_file_names_impl(py)
+    }
 });
 
 // importlib.abc.MetaPathFinder interface.
@@ -518,7 +567,7 @@ impl PyOxidizerFinder {
         let name = spec.getattr(py, "name")?;
         let key = name.extract::<String>(py)?;
 
-        let entry = match state.resources_state.resources.get(&*key) {
+        let entry = match state.resources_state.get_resource(&key) {
             Some(entry) => entry,
             None => return Ok(py.None()),
         };
@@ -535,6 +584,18 @@ impl PyOxidizerFinder {
                 // potentially work around this and move all extension module
                 // initialization into `exec_module()`.
                 if let Some(library_data) = &entry.in_memory_extension_module_shared_library {
+                    if state.resources_state.verify_digests {
+                        entry.verify_content_digest().or_else(|e| {
+                            Err(PyErr::new::<ImportError, _>(
+                                py,
+                                (
+                                    format!("error verifying module integrity: {}", e),
+                                    key.clone(),
+                                ),
+                            ))
+                        })?;
+                    }
+
                     let sys_modules = state.sys_module.as_object().getattr(py, "modules")?;
 
                     extension_module_shared_library_create_module(
@@ -577,22 +638,62 @@ impl PyOxidizerFinder {
             }
         };
 
+        if entry.flavor == ResourceFlavor::Module
+            && self.defer_module_execution(py, &key, module)?
+        {
+            return Ok(py.None());
+        }
+
+        if let Some(module_load_order) = &state.module_load_order {
+            module_load_order.record(&key);
+        }
+
+        if let Some(tracer) = &state.import_tracer {
+            tracer.start_exec_module(&key);
+            let res = self.exec_resolved_module(py, module, &key, &mut entry);
+            tracer.finish_exec_module(entry.import_source(state.optimize_level));
+
+            res
+        } else {
+            self.exec_resolved_module(py, module, &key, &mut entry)
+        }
+    }
+
+    /// Execute a module whose resource has been resolved.
+    fn exec_resolved_module(
+        &self,
+        py: Python,
+        module: &PyObject,
+        key: &str,
+        entry: &mut ImportablePythonModule<u8>,
+    ) -> PyResult<PyObject> {
+        let state = self.state(py);
+        let start = Instant::now();
+
         if let Some(bytecode) = entry.resolve_bytecode(py, state.optimize_level)? {
             let code = state.marshal_loads.call(py, (bytecode,), None)?;
             let dict = module.getattr(py, "__dict__")?;
 
+            if let Some(tracer) = &state.import_tracer {
+                tracer.record_get_code(
+                    key,
+                    entry.import_source(state.optimize_level),
+                    start.elapsed(),
+                );
+            }
+
             state
                 .call_with_frames_removed
                 .call(py, (&state.exec_fn, code, dict), None)
-        } else if entry.flavor == &ResourceFlavor::BuiltinExtensionModule {
+        } else if entry.flavor == ResourceFlavor::BuiltinExtensionModule {
             state
                 .builtin_importer
                 .call_method(py, "exec_module", (module,), None)
-        } else if entry.flavor == &ResourceFlavor::FrozenModule {
+        } else if entry.flavor == ResourceFlavor::FrozenModule {
             state
                 .frozen_importer
                 .call_method(py, "exec_module", (module,), None)
-        } else if entry.flavor == &ResourceFlavor::Extension {
+        } else if entry.flavor == ResourceFlavor::Extension {
             // `ExtensionFileLoader.exec_module()` simply calls `imp.exec_dynamic()`.
             let exec_dynamic = state.imp_module.as_object().getattr(py, "exec_dynamic")?;
 
@@ -603,6 +704,48 @@ impl PyOxidizerFinder {
             Ok(py.None())
         }
     }
+
+    /// Defer execution of a module until its first attribute access.
+    ///
+    /// Returns whether execution was deferred. `importlib.util.LazyLoader`
+    /// replaces the module's class with one calling `exec_module()` again on
+    /// first attribute access. It sets `__spec__.loader_state`, which is how
+    /// we know to execute the module at that time.
+    ///
+    /// Modules are executed eagerly until `importlib.util` has been imported,
+    /// as `LazyLoader` isn't available before then. This covers modules
+    /// imported during interpreter initialization and modules `importlib.util`
+    /// itself depends on.
+    fn defer_module_execution(&self, py: Python, key: &str, module: &PyObject) -> PyResult<bool> {
+        let state = self.state(py);
+
+        match &state.lazy_modules {
+            Some(lazy_modules) if lazy_modules.is_lazy(key) => {}
+            _ => return Ok(false),
+        }
+
+        let spec = module.getattr(py, "__spec__")?;
+        if spec == py.None() || spec.getattr(py, "loader_state")? != py.None() {
+            return Ok(false);
+        }
+
+        let sys_modules = state.sys_module.as_object().getattr(py, "modules")?;
+        let util_module = sys_modules.call_method(py, "get", ("importlib.util",), None)?;
+        if util_module == py.None() {
+            return Ok(false);
+        }
+
+        // importlib.util may be partially initialized if it is being imported.
+        let lazy_loader_type = match util_module.getattr(py, "LazyLoader") {
+            Ok(value) => value,
+            Err(_) => return Ok(false),
+        };
+
+        let lazy_loader = lazy_loader_type.call(py, (self.as_object(),), None)?;
+        lazy_loader.call_method(py, "exec_module", (module,), None)?;
+
+        Ok(true)
+    }
 }
 
 // importlib.abc.ResourceLoader interface.
@@ -635,9 +778,21 @@ impl PyOxidizerFinder {
             None => return Ok(py.None()),
         };
 
+        let start = Instant::now();
+
         if let Some(bytecode) = module.resolve_bytecode(py, state.optimize_level)? {
-            state.marshal_loads.call(py, (bytecode,), None)
-        } else if module.flavor == &ResourceFlavor::FrozenModule {
+            let code = state.marshal_loads.call(py, (bytecode,), None)?;
+
+            if let Some(tracer) = &state.import_tracer {
+                tracer.record_get_code(
+                    &key,
+                    module.import_source(state.optimize_level),
+                    start.elapsed(),
+                );
+            }
+
+            Ok(code)
+        } else if module.flavor == ResourceFlavor::FrozenModule {
             state
                 .imp_module
                 .call(py, "get_frozen_object", (fullname,), None)
@@ -737,17 +892,62 @@ impl PyOxidizerFinder {
     fn find_distributions_impl(&self, py: Python, context: Option<PyObject>) -> PyResult<PyObject> {
         let state = self.state(py);
 
-        let (path, name) = if let Some(context) = context {
-            // The passed object should have `path` and `name` attributes.
-            let path = context.getattr(py, "path")?;
-            let name = context.getattr(py, "name")?;
-            (Some(path), Some(name))
+        let (name, path) = if let Some(context) = context {
+            // The passed object should have `path` and `name` attributes. A
+            // `None` value means not to filter on that attribute.
+            let none = py.None();
+            let attribute = |attribute: &str| -> PyResult<Option<PyObject>> {
+                let value = context.getattr(py, attribute)?;
+
+                Ok(if value.as_ptr() == none.as_ptr() {
+                    None
+                } else {
+                    Some(value)
+                })
+            };
+
+            (attribute("name")?, attribute("path")?)
         } else {
             // No argument = default Context = find everything.
             (None, None)
         };
 
-        super::package_metadata::find_distributions(py, state.clone(), path, name)
+        super::package_metadata::find_distributions(py, state.clone(), name, path)
+    }
+}
+
+// Data files support.
+impl PyOxidizerFinder {
+    /// def read_data_file(name) -> Optional[bytes]
+    ///
+    /// Read the content of a data file, or return None if there is no data
+    /// file with the given name.
+    fn read_data_file_impl(&self, py: Python, name: &PyString) -> PyResult<PyObject> {
+        let state = self.state(py);
+        let key = name.to_string(py)?;
+
+        match state.resources_state.get_data_file(&key) {
+            Ok(Some(data)) => Ok(PyBytes::new(py, &data).into_object()),
+            Ok(None) => Ok(py.None()),
+            Err(e) => Err(PyErr::new::<OSError, _>(
+                py,
+                format!("error reading data file {}: {}", key, e),
+            )),
+        }
+    }
+
+    /// def data_file_names() -> List[str]
+    ///
+    /// Obtain the names of all data files.
+    fn data_file_names_impl(&self, py: Python) -> PyResult<PyObject> {
+        let state = self.state(py);
+
+        let names = state
+            .resources_state
+            .data_file_names()
+            .or_else(|e| Err(PyErr::new::<OSError, _>(py, e.to_string())))?;
+
+        Ok(names.to_py_object(py).into_object())
     }
 }
 
@@ -774,6 +974,10 @@ py_class!(class PyOxidizerResourceReader |py| {
     def contents(&self) -> PyResult<PyObject> {
         self.contents_impl(py)
     }
+
+    def files(&self) -> PyResult<PyObject> {
+        self.files_impl(py)
+    }
 });
 
 impl PyOxidizerResourceReader {
@@ -798,25 +1002,41 @@ impl PyOxidizerResourceReader {
     /// Returns the file system path to the resource.
     ///
     /// If the resource does not concretely exist on the file system, raise
-    /// FileNotFoundError.
-    fn resource_path_impl(&self, py: Python, _resource: &PyString) -> PyResult<PyObject> {
-        Err(PyErr::new::<FileNotFoundError, _>(
+    /// FileNotFoundError. In-memory resources are extracted to the filesystem
+    /// if resource path extraction is enabled.
+    fn resource_path_impl(&self, py: Python, resource: &PyString) -> PyResult<PyObject> {
+        let state = self.state(py);
+        let package = self.package(py);
+
+        match state.resources_state.resolve_package_resource_path(
             py,
-            "in-memory resources do not have filesystem paths",
-        ))
+            &package,
+            &resource.to_string(py)?,
+        )? {
+            Some(path) => path_to_pyobject(py, &path),
+            None => Err(PyErr::new::<FileNotFoundError, _>(
+                py,
+                "resource does not have a filesystem path",
+            )),
+        }
     }
 
     /// Returns True if the named name is considered a resource. FileNotFoundError
     /// is raised if name does not exist.
+    ///
+    /// Directories exist but aren't resources.
     fn is_resource_impl(&self, py: Python, name: &PyString) -> PyResult<PyObject> {
         let state = self.state(py);
         let package = self.package(py);
+        let name = name.to_string(py)?;
 
-        if state
+        if state.resources_state.is_package_resource(&package, &name) {
+            Ok(py.True().as_object().clone_ref(py))
+        } else if state
             .resources_state
-            .is_package_resource(&package, &name.to_string(py)?)
+            .is_package_resource_directory(&package, &name)
         {
-            Ok(py.True().as_object().clone_ref(py))
+            Ok(py.False().as_object().clone_ref(py))
         } else {
             Err(PyErr::new::<FileNotFoundError, _>(py, "resource not found"))
         }
@@ -838,16 +1058,43 @@ impl PyOxidizerResourceReader {
 
         state.resources_state.package_resource_names(py, &package)
     }
+
+    /// Returns a Traversable for the package's resources.
+    ///
+    /// This is used by `importlib.resources.files()`.
+    fn files_impl(&self, py: Python) -> PyResult<PyObject> {
+        let state = self.state(py);
+        let package = self.package(py);
+
+        let traversable = PyOxidizerTraversable::new_instance(
+            py,
+            state.clone(),
+            package.clone(),
+            "".to_string(),
+        )?;
+
+        Ok(traversable.into_object())
+    }
 }
 
 #[allow(unused_doc_comments)]
 /// Path-like object facilitating Python resource access.
 ///
 /// This implements importlib.abc.Traversable.
+///
+/// Instances refer to a path in the resources of a Python package. Paths use
+/// `/` as a separator and the empty path refers to the package itself.
+/// Directories within package resources are virtual: they exist if resources
+/// exist beneath them.
 py_class!(class PyOxidizerTraversable |py| {
     data state: Arc<Box<ImporterState>>;
+    data package: String;
     data path: String;
 
+    @property def name(&self) -> PyResult<PyObject> {
+        self.name_impl(py)
+    }
+
     /// Yield Traversable objects in self.
     def iterdir(&self) -> PyResult<PyObject> {
         self.iterdir_impl(py)
@@ -859,8 +1106,8 @@ py_class!(class PyOxidizerTraversable |py| {
     }
 
     /// Read contents of self as text.
-    def read_text(&self) -> PyResult<PyObject> {
-        self.read_text_impl(py)
+    def read_text(&self, encoding: Option<PyObject> = None) -> PyResult<PyObject> {
+        self.read_text_impl(py, encoding)
     }
 
     /// Return True if self is a dir.
@@ -874,13 +1121,13 @@ py_class!(class PyOxidizerTraversable |py| {
     }
 
     /// Return Traversable child in self.
-    def joinpath(&self, child: &PyObject) -> PyResult<PyObject> {
-        self.joinpath_impl(py, child)
+    def joinpath(&self, *args) -> PyResult<PyObject> {
+        self.joinpath_impl(py, args)
     }
 
     /// Return Traversable child in self.
     def __truediv__(&self, child: &PyObject) -> PyResult<PyObject> {
-        self.joinpath_impl(py, child)
+        self.joinpath_impl(py, &PyTuple::new(py, &[child.clone_ref(py)]))
     }
 
     /// mode may be 'r' or 'rb' to open as text or binary. Return a handle
@@ -894,54 +1141,317 @@ py_class!(class PyOxidizerTraversable |py| {
 });
 
 impl PyOxidizerTraversable {
-    fn iterdir_impl(&self, _py: Python) -> PyResult<PyObject> {
-        unimplemented!();
+    /// Create an instance for a path in the resources of a package.
+    ///
+    /// Leading path components naming packages within `package` descend into
+    /// those packages, just like package directories on the filesystem.
+    fn new_instance(
+        py: Python,
+        state: Arc<Box<ImporterState>>,
+        package: String,
+        path: String,
+    ) -> PyResult<PyOxidizerTraversable> {
+        let mut package = package;
+        let mut path = path;
+
+        loop {
+            let (first, rest) = match path.find('/') {
+                Some(index) => (path[0..index].to_string(), path[index + 1..].to_string()),
+                None => (path.clone(), "".to_string()),
+            };
+
+            if first.is_empty() {
+                break;
+            }
+
+            let candidate = format!("{}.{}", package, first);
+
+            match state.resources_state.get_resource(&candidate) {
+                Some(entry) if entry.is_package => {}
+                _ => break,
+            }
+
+            package = candidate;
+            path = rest;
+        }
+
+        PyOxidizerTraversable::create_instance(py, state, package, path)
     }
 
-    fn read_bytes_impl(&self, _py: Python) -> PyResult<PyObject> {
-        unimplemented!();
+    /// Describes self in error messages.
+    fn display_path(&self, py: Python) -> String {
+        let package = self.package(py).replace('.', "/");
+        let path = self.path(py);
+
+        if path.is_empty() {
+            package
+        } else {
+            format!("{}/{}", package, path)
+        }
     }
 
-    fn read_text_impl(&self, _py: Python) -> PyResult<PyObject> {
-        unimplemented!();
+    /// Open self for binary reading.
+    fn open_binary(&self, py: Python) -> PyResult<PyObject> {
+        let state = self.state(py);
+        let package = self.package(py);
+        let path = self.path(py);
+
+        if !path.is_empty() {
+            if let Some(file) = state
+                .resources_state
+                .get_package_resource_file(py, package, path)?
+            {
+                return Ok(file);
+            }
+        }
+
+        if state
+            .resources_state
+            .is_package_resource_directory(package, path)
+        {
+            Err(PyErr::new::<IsADirectoryError, _>(
+                py,
+                format!("resource is a directory: {}", self.display_path(py)),
+            ))
+        } else {
+            Err(PyErr::new::<FileNotFoundError, _>(
+                py,
+                format!("resource not found: {}", self.display_path(py)),
+            ))
+        }
     }
 
-    fn is_dir_impl(&self, py: Python) -> PyResult<PyObject> {
+    fn name_impl(&self, py: Python) -> PyResult<PyObject> {
+        let path = self.path(py);
+
+        let name = if path.is_empty() {
+            self.package(py).rsplit('.').next()
+        } else {
+            path.rsplit('/').next()
+        };
+
+        Ok(name.unwrap_or("").to_py_object(py).into_object())
+    }
+
+    fn iterdir_impl(&self, py: Python) -> PyResult<PyObject> {
         let state = self.state(py);
+        let package = self.package(py);
         let path = self.path(py);
 
-        // We are a directory if the current path is a known package.
-        // TODO We may need to expand this definition in the future to cover
-        // virtual subdirectories in addressable resources. But this will require
-        // changes to the resources data format to capture said annotations.
-        if let Some(entry) = state
+        if !state
             .resources_state
-            .resolve_importable_module(&path, state.optimize_level)
+            .is_package_resource_directory(package, path)
         {
-            if entry.is_package {
-                return Ok(py.True().into_object());
+            return Err(PyErr::new::<NotADirectoryError, _>(
+                py,
+                format!("resource is not a directory: {}", self.display_path(py)),
+            ));
+        }
+
+        let names = state
+            .resources_state
+            .package_resource_directory_entries(package, path)
+            .or_else(|e| Err(PyErr::new::<OSError, _>(py, e.to_string())))?;
+
+        let children = names
+            .into_iter()
+            .map(|name| {
+                let child = if path.is_empty() {
+                    name
+                } else {
+                    format!("{}/{}", path, name)
+                };
+
+                Ok(
+                    PyOxidizerTraversable::new_instance(py, state.clone(), package.clone(), child)?
+                        .into_object(),
+                )
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+
+        let builtins = py.import("builtins")?;
+
+        builtins.call(py, "iter", (PyList::new(py, &children),), None)
+    }
+
+    fn read_bytes_impl(&self, py: Python) -> PyResult<PyObject> {
+        let fh = self.open_binary(py)?;
+
+        let data = fh.call_method(py, "read", NoArgs, None)?;
+        fh.call_method(py, "close", NoArgs, None)?;
+
+        Ok(data)
+    }
+
+    fn read_text_impl(&self, py: Python, encoding: Option<PyObject>) -> PyResult<PyObject> {
+        let io_module = py.import("io")?;
+
+        let fh = io_module.call(
+            py,
+            "TextIOWrapper",
+            (self.open_binary(py)?, encoding.unwrap_or_else(|| py.None())),
+            None,
+        )?;
+
+        let data = fh.call_method(py, "read", NoArgs, None)?;
+        fh.call_method(py, "close", NoArgs, None)?;
+
+        Ok(data)
+    }
+
+    fn is_dir_impl(&self, py: Python) -> PyResult<PyObject> {
+        let state = self.state(py);
+
+        Ok(state
+            .resources_state
+            .is_package_resource_directory(self.package(py), self.path(py))
+            .to_py_object(py)
+            .into_object())
+    }
+
+    fn is_file_impl(&self, py: Python) -> PyResult<PyObject> {
+        let state = self.state(py);
+        let path = self.path(py);
+
+        Ok((!path.is_empty()
+            && state
+                .resources_state
+                .is_package_resource(self.package(py), path))
+        .to_py_object(py)
+        .into_object())
+    }
+
+    fn joinpath_impl(&self, py: Python, args: &PyTuple) -> PyResult<PyObject> {
+        let mut path = self.path(py).clone();
+
+        for child in args.iter(py) {
+            let child = child.str(py)?.to_string(py)?.to_string();
+
+            // Internally resources are normalized to POSIX separators.
+            for component in child.split('/') {
+                match component {
+                    "" | "." => {}
+                    ".." => {
+                        return Err(PyErr::new::<ValueError, _>(
+                            py,
+                            "parent directory references are not supported",
+                        ));
+                    }
+                    component => {
+                        if !path.is_empty() {
+                            path.push('/');
+                        }
+                        path.push_str(component);
+                    }
+                }
             }
         }
 
-        Ok(py.False().into_object())
+        let traversable = PyOxidizerTraversable::new_instance(
+            py,
+            self.state(py).clone(),
+            self.package(py).clone(),
+            path,
+        )?;
+
+        Ok(traversable.into_object())
     }
 
-    fn is_file_impl(&self, _py: Python) -> PyResult<PyObject> {
-        unimplemented!();
+    fn open_impl(&self, py: Python, args: &PyTuple, kwargs: Option<&PyDict>) -> PyResult<PyObject> {
+        let kwargs = match kwargs {
+            Some(kwargs) => Some(kwargs.copy(py)?),
+            None => None,
+        };
+
+        let mut args = args
+            .iter(py)
+            .map(|arg| arg.clone_ref(py))
+            .collect::<Vec<_>>();
+
+        let mode = if !args.is_empty() {
+            Some(args.remove(0))
+        } else if let Some(kwargs) = &kwargs {
+            let mode = kwargs.get_item(py, "mode");
+            kwargs.del_item(py, "mode").ok();
+            mode
+        } else {
+            None
+        };
+
+        let mode = match mode {
+            Some(mode) => mode.extract::<String>(py)?,
+            None => "r".to_string(),
+        };
+
+        match mode.as_str() {
+            "rb" => self.open_binary(py),
+            "r" => {
+                let mut wrapper_args = vec![self.open_binary(py)?];
+                wrapper_args.extend(args);
+
+                let io_module = py.import("io")?;
+                let wrapper = io_module.get(py, "TextIOWrapper")?;
+
+                wrapper.call(py, PyTuple::new(py, &wrapper_args), kwargs.as_ref())
+            }
+            _ => Err(PyErr::new::<ValueError, _>(
+                py,
+                format!("invalid mode: {}; only 'r' and 'rb' are supported", mode),
+            )),
+        }
     }
+}
 
-    fn joinpath_impl(&self, _py: Python, _child: &PyObject) -> PyResult<PyObject> {
-        unimplemented!();
+/// Obtain the resources for the importer of an interpreter.
+///
+/// Resources already loaded by another interpreter, like the main interpreter
+/// of a sub-interpreter, are shared. Otherwise the packed resources data is
+/// loaded.
+#[allow(clippy::too_many_arguments)]
+fn importer_resources(
+    shared: Option<&Arc<PythonResourcesState<'static, u8>>>,
+    resources_datas: &[&'static [u8]],
+    resources_trailer: bool,
+    resources_paths: &[PathBuf],
+    verify_resources_digests: bool,
+    resource_path_extraction: bool,
+    current_exe: PathBuf,
+    origin: PathBuf,
+) -> Result<Arc<PythonResourcesState<'static, u8>>, String> {
+    if let Some(resources_state) = shared {
+        return Ok(resources_state.clone());
     }
 
-    fn open_impl(
-        &self,
-        _py: Python,
-        _args: &PyTuple,
-        _kwargs: Option<&PyDict>,
-    ) -> PyResult<PyObject> {
-        unimplemented!();
+    let mut resources_state = PythonResourcesState {
+        current_exe,
+        origin,
+        verify_digests: verify_resources_digests,
+        resource_path_extraction,
+        ..PythonResourcesState::default()
+    };
+
+    resources_state.load(resources_datas, resources_trailer, resources_paths)?;
+
+    Ok(Arc::new(resources_state))
+}
+
+/// Obtain the resources of PyOxidizer's importer in the current interpreter.
+///
+/// Returns `None` if the importer isn't registered on `sys.meta_path`.
+pub(crate) fn importer_resources_state(
+    py: Python,
+) -> PyResult<Option<Arc<PythonResourcesState<'static, u8>>>> {
+    let meta_path = py.import("sys")?.get(py, "meta_path")?;
+
+    for importer in meta_path.iter(py)? {
+        let importer = importer?;
+
+        if let Ok(finder) = importer.cast_as::<PyOxidizerFinder>(py) {
+            return Ok(Some(finder.state(py).resources_state.clone()));
+        }
     }
+
+    Ok(None)
 }
 
 const DOC: &[u8] = b"Binary representation of Python modules\0";
@@ -961,8 +1471,41 @@ pub struct InitModuleState {
     /// Values to set on sys.path.
     pub sys_paths: Vec<String>,
 
-    /// Raw data describing embedded resources.
-    pub packed_resources: &'static [u8],
+    /// Resources already loaded by another interpreter.
+    ///
+    /// If set, the importer uses these resources instead of loading the
+    /// packed resources data described by the fields below.
+    pub(crate) resources_state: Option<Arc<PythonResourcesState<'static, u8>>>,
+
+    /// Raw data describing embedded resources, in layering order.
+    pub packed_resources: Vec<&'static [u8]>,
+
+    /// Whether to load packed resources data appended to the executable.
+    pub packed_resources_trailer: bool,
+
+    /// Paths to files containing packed resources data.
+    pub packed_resources_paths: Vec<PathBuf>,
+
+    /// Whether to verify digests of embedded resources.
+    pub verify_resources_digests: bool,
+
+    /// Whether to extract in-memory package resources to serve filesystem paths.
+    pub resource_path_extraction: bool,
+
+    /// Whether to defer executing modules until their first attribute access.
+    pub lazy_module_loading: bool,
+
+    /// Names of modules to load lazily.
+    pub lazy_modules_allow: Vec<String>,
+
+    /// Names of modules to always execute eagerly.
+    pub lazy_modules_deny: Vec<String>,
+
+    /// Records imported modules, if import tracing is enabled.
+    pub import_tracer: Option<Arc<ImportTracer>>,
+
+    /// Records the order modules are loaded in, if enabled.
+    pub module_load_order: Option<Arc<ModuleLoadOrder>>,
 }
 
 /// Holds reference to next module state struct.
@@ -990,8 +1533,38 @@ struct ModuleState {
     /// Values to set on sys.path.
     sys_paths: Vec<String>,
 
-    /// Raw data constituting embedded resources.
-    packed_resources: &'static [u8],
+    /// Resources already loaded by another interpreter.
+    resources_state: Option<Arc<PythonResourcesState<'static, u8>>>,
+
+    /// Raw data constituting embedded resources, in layering order.
+    packed_resources: Vec<&'static [u8]>,
+
+    /// Whether to load packed resources data appended to the executable.
+    packed_resources_trailer: bool,
+
+    /// Paths to files containing packed resources data.
+    packed_resources_paths: Vec<PathBuf>,
+
+    /// Whether to verify digests of embedded resources.
+    verify_resources_digests: bool,
+
+    /// Whether to extract in-memory package resources to serve filesystem paths.
+    resource_path_extraction: bool,
+
+    /// Whether to defer executing modules until their first attribute access.
+    lazy_module_loading: bool,
+
+    /// Names of modules to load lazily.
+    lazy_modules_allow: Vec<String>,
+
+    /// Names of modules to always execute eagerly.
+    lazy_modules_deny: Vec<String>,
+
+    /// Records imported modules, if import tracing is enabled.
+    import_tracer: Option<Arc<ImportTracer>>,
+
+    /// Records the order modules are loaded in, if enabled.
+    module_load_order: Option<Arc<ModuleLoadOrder>>,
 
     /// Whether setup() has been called.
     setup_called: bool,
@@ -1028,6 +1601,15 @@ fn get_module_state<'a>(py: Python, m: &'a PyModule) -> Result<&'a mut ModuleSta
 fn module_init(py: Python, m: &PyModule) -> PyResult<()> {
     let mut state = get_module_state(py, m)?;
 
+    // The module is initialized again for each sub-interpreter. That is only
+    // supported for sub-interpreters created by pyembed.
+    if unsafe { NEXT_MODULE_STATE.is_null() } {
+        return Err(PyErr::new::<ImportError, _>(
+            py,
+            "_pyoxidizer_importer can only be initialized by pyembed",
+        ));
+    }
+
     unsafe {
         // TODO we could move the value if we wanted to avoid the clone().
         state.current_exe = (*NEXT_MODULE_STATE).current_exe.clone();
@@ -1035,7 +1617,21 @@ fn module_init(py: Python, m: &PyModule) -> PyResult<()> {
         state.register_filesystem_importer = (*NEXT_MODULE_STATE).register_filesystem_importer;
         // TODO we could move the value if we wanted to avoid the clone().
         state.sys_paths = (*NEXT_MODULE_STATE).sys_paths.clone();
-        state.packed_resources = (*NEXT_MODULE_STATE).packed_resources;
+        state.resources_state = (*NEXT_MODULE_STATE).resources_state.clone();
+        // TODO we could move the value if we wanted to avoid the clone().
+        state.packed_resources = (*NEXT_MODULE_STATE).packed_resources.clone();
+        state.packed_resources_trailer = (*NEXT_MODULE_STATE).packed_resources_trailer;
+        // TODO we could move the value if we wanted to avoid the clone().
+        state.packed_resources_paths = (*NEXT_MODULE_STATE).packed_resources_paths.clone();
+        state.verify_resources_digests = (*NEXT_MODULE_STATE).verify_resources_digests;
+        state.resource_path_extraction = (*NEXT_MODULE_STATE).resource_path_extraction;
+        state.lazy_module_loading = (*NEXT_MODULE_STATE).lazy_module_loading;
+        // TODO we could move the value if we wanted to avoid the clone().
+        state.lazy_modules_allow = (*NEXT_MODULE_STATE).lazy_modules_allow.clone();
+        // TODO we could move the value if we wanted to avoid the clone().
+        state.lazy_modules_deny = (*NEXT_MODULE_STATE).lazy_modules_deny.clone();
+        state.import_tracer = (*NEXT_MODULE_STATE).import_tracer.clone();
+        state.module_load_order = (*NEXT_MODULE_STATE).module_load_order.clone();
     }
 
     state.setup_called = false;
@@ -1085,6 +1681,27 @@ fn module_setup(
     let sys_module = sys_module.cast_into::<PyModule>(py)?;
     let sys_module_ref = sys_module.clone_ref(py);
 
+    let resources_state = importer_resources(
+        state.resources_state.as_ref(),
+        &state.packed_resources,
+        state.packed_resources_trailer,
+        &state.packed_resources_paths,
+        state.verify_resources_digests,
+        state.resource_path_extraction,
+        state.current_exe.clone(),
+        state.origin.clone(),
+    )
+    .map_err(|e| PyErr::new::<ValueError, _>(py, e))?;
+
+    let lazy_modules = if state.lazy_module_loading {
+        Some(LazyModules::new(
+            state.lazy_modules_allow.clone(),
+            state.lazy_modules_deny.clone(),
+        ))
+    } else {
+        None
+    };
+
     // Construct and register our custom meta path importer. Because our meta path
     // importer is able to handle builtin and frozen modules, the existing meta path
     // importers are removed. The assumption here is that we're called very early
@@ -1096,9 +1713,10 @@ fn module_setup(
             &bootstrap_module,
             &marshal_module,
             decode_source,
-            &state.packed_resources,
-            state.current_exe.clone(),
-            state.origin.clone(),
+            resources_state,
+            lazy_modules,
+            state.import_tracer.clone(),
+            state.module_load_order.clone(),
         )?)),
     )?;
 
@@ -1210,3 +1828,48 @@ pub extern "C" fn PyInit__pyoxidizer_importer() -> *mut pyffi::PyObject {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::super::python_resources::mapped_files_count,
+        super::*,
+        python_packed_resources::writer::{write_embedded_resources_v2, WriteOptions},
+    };
+
+    #[test]
+    fn test_importer_resources_shared_with_sub_interpreters() -> Result<(), String> {
+        let directory = std::env::temp_dir().join(format!("pyembed-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&directory).map_err(|e| e.to_string())?;
+        let path = directory.join("resources");
+        let mut data = Vec::new();
+        write_embedded_resources_v2(&[], &mut data, &WriteOptions::default())
+            .map_err(|e| e.to_string())?;
+        std::fs::write(&path, data).map_err(|e| e.to_string())?;
+        let resources_paths = vec![path];
+
+        let load = |shared: Option<&Arc<PythonResourcesState<'static, u8>>>| {
+            importer_resources(
+                shared,
+                &[],
+                false,
+                &resources_paths,
+                false,
+                false,
+                directory.clone(),
+                directory.clone(),
+            )
+        };
+
+        let main = load(None)?;
+        assert_eq!(mapped_files_count(&directory), 1);
+
+        for _ in 0..3 {
+            let sub = load(Some(&main))?;
+            assert!(Arc::ptr_eq(&main, &sub));
+        }
+        assert_eq!(mapped_files_count(&directory), 1);
+
+        Ok(())
+    }
+}
@@ -39,7 +39,8 @@ interfacing.
 **It is an explicit goal of this crate to rely on as few external dependencies
 as possible.** This is because we want to minimize bloat in produced binaries.
 At this time, we have required direct dependencies on published versions of the
-`anyhow`, `lazy_static`, `libc`, `python-packed-resources`, and `uuid` crates.
+`anyhow`, `lazy_static`, `libc`, `memmap`, `python-packed-resources`, and `uuid`
+crates.
 On Windows, this list is extended by `memory-module-sys` and `winapi`, which are
 required to support loading DLLs from memory. We also have an optional direct
 dependency on the `jemalloc-sys` crate.
@@ -83,16 +84,21 @@ That crate's build script will attempt to find a `libpython` from the
 
 */
 
+#[cfg(unix)]
+mod command_server;
 mod config;
+mod import_trace;
 mod importer;
 #[cfg(windows)]
 mod memory_dll;
 mod osutils;
 mod package_metadata;
+mod profiler;
 mod pyalloc;
 mod pyinterp;
 mod pystr;
 mod python_resources;
+mod subinterp;
 pub mod technotes;
 
 #[allow(unused_imports)]
@@ -101,4 +107,13 @@ pub use crate::config::{
 };
 
 #[allow(unused_imports)]
-pub use crate::pyinterp::MainPythonInterpreter;
+pub use crate::pyinterp::{MainPythonInterpreter, PythonError, PythonRunResult};
+
+#[allow(unused_imports)]
+pub use crate::profiler::{PythonFrameInfo, PythonTraceEvent};
+
+#[allow(unused_imports)]
+pub use crate::subinterp::PythonSubInterpreter;
+
+#[cfg(unix)]
+pub use crate::command_server::{run_command_client, COMMAND_SERVER_SOCKET_ENV};
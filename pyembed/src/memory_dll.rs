@@ -149,7 +149,7 @@ extern "C" fn custom_load_library(filename: LPCSTR, user_data: *mut c_void) -> H
             .unwrap()
     };
 
-    if let Some(entry) = resources_state.resources.get(name.as_ref()) {
+    if let Some(entry) = resources_state.get_resource(name.as_ref()) {
         if let Some(library_data) = &entry.in_memory_shared_library {
             let res = unsafe { load_library_memory(resources_state, library_data) };
 
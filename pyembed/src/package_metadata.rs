@@ -4,15 +4,15 @@
 
 use {
     super::importer::ImporterState,
-    cpython::exc::{IOError, NotImplementedError},
+    super::pystr::path_to_pyobject,
+    super::python_resources::PythonResourcesState,
+    cpython::exc::{IOError, ValueError},
     cpython::{
-        py_class, py_class_prop_getter, NoArgs, ObjectProtocol, PyBytes, PyErr, PyList, PyObject,
-        PyResult, PyString, Python, PythonObject,
+        py_class, py_class_prop_getter, ObjectProtocol, PyBytes, PyErr, PyList, PyModule, PyObject,
+        PyResult, PyString, Python, PythonObject, ToPyObject,
     },
-    python_packed_resources::data::Resource,
-    std::borrow::Cow,
-    std::collections::HashMap,
-    std::path::Path,
+    python_packed_resources::data::{Resource, ResourceField},
+    std::path::PathBuf,
     std::sync::Arc,
 };
 
@@ -45,38 +45,43 @@ py_class!(class PyOxidizerDistribution |py| {
     @property def requires(&self) -> PyResult<PyObject> {
         self.requires_impl(py)
     }
+
+    def locate_file(&self, path: PyObject) -> PyResult<PyObject> {
+        self.locate_file_impl(py, path)
+    }
 });
 
 impl PyOxidizerDistribution {
-    fn read_text_impl(&self, py: Python, filename: &PyString) -> PyResult<PyObject> {
+    /// Read a distribution file as text.
+    ///
+    /// Returns `Ok(None)` if the file doesn't exist.
+    fn read_distribution_text(&self, py: Python, filename: &str) -> PyResult<Option<String>> {
         let state: &Arc<Box<ImporterState>> = self.state(py);
         let package: &str = self.package(py);
         let resources_state = &state.resources_state;
 
-        let filename = filename.to_string_lossy(py);
-
-        let data = resolve_package_distribution_resource(
-            &resources_state.resources,
-            &resources_state.origin,
-            package,
-            &filename,
-        )
-        .or_else(|e| {
-            Err(PyErr::new::<IOError, _>(
-                py,
-                format!("error when resolving resource: {}", e),
-            ))
-        })?
-        .ok_or_else(|| PyErr::new::<IOError, _>(py, format!("resource not found: {}", filename)))?;
-
-        let data = PyBytes::new(py, &data);
+        let data = resolve_package_distribution_resource(resources_state, package, filename)
+            .or_else(|e| {
+                Err(PyErr::new::<IOError, _>(
+                    py,
+                    format!("error when resolving resource: {}", e),
+                ))
+            })?;
 
-        let io = py.import("io")?;
+        Ok(data.map(|data| String::from_utf8_lossy(&data).into_owned()))
+    }
 
-        let bytes_io = io.call(py, "BytesIO", (data,), None)?;
-        let text_wrapper = io.call(py, "TextIOWrapper", (bytes_io, "utf-8"), None)?;
+    /// Attempt to load the distribution file with the given name.
+    ///
+    /// Returns None if the file doesn't exist, like the `Distribution`
+    /// implementations in `importlib.metadata`.
+    fn read_text_impl(&self, py: Python, filename: &PyString) -> PyResult<PyObject> {
+        let filename = filename.to_string_lossy(py);
 
-        text_wrapper.call_method(py, "read", NoArgs, None)
+        Ok(match self.read_distribution_text(py, &filename)? {
+            Some(text) => text.to_py_object(py).into_object(),
+            None => py.None(),
+        })
     }
 
     /// Return the parsed metadata for this Distribution.
@@ -88,35 +93,25 @@ impl PyOxidizerDistribution {
         let package: &str = self.package(py);
         let resources_state = &state.resources_state;
 
-        let data = resolve_package_distribution_resource(
-            &resources_state.resources,
-            &resources_state.origin,
-            package,
-            "METADATA",
-        )
-        .or_else(|e| {
-            Err(PyErr::new::<IOError, _>(
-                py,
-                format!("error when resolving resource: {}", e),
-            ))
-        })?;
-
-        let data = if let Some(data) = data {
-            data
-        } else {
-            resolve_package_distribution_resource(
-                &resources_state.resources,
-                &resources_state.origin,
-                package,
-                "PKG-INFO",
-            )
+        let data = resolve_package_distribution_resource(resources_state, package, "METADATA")
             .or_else(|e| {
                 Err(PyErr::new::<IOError, _>(
                     py,
                     format!("error when resolving resource: {}", e),
                 ))
-            })?
-            .ok_or_else(|| PyErr::new::<IOError, _>(py, ("package metadata not found",)))?
+            })?;
+
+        let data = if let Some(data) = data {
+            data
+        } else {
+            resolve_package_distribution_resource(resources_state, package, "PKG-INFO")
+                .or_else(|e| {
+                    Err(PyErr::new::<IOError, _>(
+                        py,
+                        format!("error when resolving resource: {}", e),
+                    ))
+                })?
+                .ok_or_else(|| PyErr::new::<IOError, _>(py, ("package metadata not found",)))?
         };
 
         let data = PyBytes::new(py, &data);
@@ -133,89 +128,399 @@ impl PyOxidizerDistribution {
         metadata.get_item(py, "Version")
     }
 
+    /// Return the entry points of this Distribution.
+    ///
+    /// Entry points are parsed from `entry_points.txt` and returned as a list
+    /// of `importlib.metadata.EntryPoint`.
     fn entry_points_impl(&self, py: Python) -> PyResult<PyObject> {
-        Err(PyErr::new::<NotImplementedError, _>(py, NoArgs))
+        let text = self.read_distribution_text(py, "entry_points.txt")?;
+
+        let entry_point = import_metadata_module(py)?.get(py, "EntryPoint")?;
+
+        let entry_points = parse_entry_points(text.as_deref().unwrap_or(""))
+            .into_iter()
+            .map(|(group, name, value)| entry_point.call(py, (name, value, group), None))
+            .collect::<PyResult<Vec<_>>>()?;
+
+        Ok(PyList::new(py, &entry_points).into_object())
     }
 
+    /// Return the files of this Distribution.
+    ///
+    /// Files are read from `RECORD` of `.dist-info` metadata or `SOURCES.txt`
+    /// of `.egg-info` metadata and returned as a list of
+    /// `importlib.metadata.PackagePath`. None is returned if neither file
+    /// exists.
     fn files_impl(&self, py: Python) -> PyResult<PyObject> {
-        Err(PyErr::new::<NotImplementedError, _>(py, NoArgs))
+        let rows = if let Some(text) = self.read_distribution_text(py, "RECORD")? {
+            let csv = py.import("csv")?;
+            let lines = text.lines().collect::<Vec<_>>().to_py_object(py);
+
+            csv.call(py, "reader", (lines,), None)?
+                .iter(py)?
+                .map(|row| row?.extract::<Vec<String>>(py))
+                .collect::<PyResult<Vec<_>>>()?
+        } else if let Some(text) = self.read_distribution_text(py, "SOURCES.txt")? {
+            text.lines()
+                .filter(|line| !line.is_empty())
+                .map(|line| vec![line.to_string()])
+                .collect::<Vec<_>>()
+        } else {
+            return Ok(py.None());
+        };
+
+        let metadata = import_metadata_module(py)?;
+        let package_path = metadata.get(py, "PackagePath")?;
+        let file_hash = metadata.get(py, "FileHash")?;
+
+        let files = rows
+            .into_iter()
+            .filter(|row| !row.is_empty())
+            .map(|row| {
+                let path = package_path.call(py, (&row[0],), None)?;
+
+                let hash = match row.get(1) {
+                    Some(hash) if !hash.is_empty() => file_hash.call(py, (hash,), None)?,
+                    _ => py.None(),
+                };
+
+                let size = match row.get(2) {
+                    Some(size) if !size.is_empty() => size
+                        .parse::<u64>()
+                        .or_else(|_| {
+                            Err(PyErr::new::<ValueError, _>(
+                                py,
+                                format!("invalid file size in RECORD: {}", size),
+                            ))
+                        })?
+                        .to_py_object(py)
+                        .into_object(),
+                    _ => py.None(),
+                };
+
+                path.setattr(py, "hash", hash)?;
+                path.setattr(py, "size", size)?;
+                path.setattr(py, "dist", self.as_object())?;
+
+                Ok(path)
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+
+        Ok(PyList::new(py, &files).into_object())
     }
 
+    /// Resolve the filesystem path of a file of this Distribution.
+    ///
+    /// This backs `PackagePath.locate()` of paths returned by `files`. Paths
+    /// of filesystem-relative distributions are resolved relative to the
+    /// directory containing their metadata directory. Paths of in-memory
+    /// distributions are resolved relative to the current executable and
+    /// don't exist on the filesystem.
+    fn locate_file_impl(&self, py: Python, path: PyObject) -> PyResult<PyObject> {
+        let state: &Arc<Box<ImporterState>> = self.state(py);
+        let package: &str = self.package(py);
+        let resources_state = &state.resources_state;
+
+        let path = py.import("os")?.call(py, "fspath", (path,), None)?;
+        let path = path.str(py)?.to_string(py)?.to_string();
+
+        let path = match resources_state.get_resource(package) {
+            Some(resource) => locate_distribution_file(resources_state, &resource, &path),
+            None => resources_state.current_exe.join(&path),
+        };
+
+        py.import("pathlib")?
+            .call(py, "Path", (path_to_pyobject(py, &path)?,), None)
+    }
+
+    /// Return the requirements of this Distribution.
+    ///
+    /// Requirements are read from `Requires-Dist` of the metadata or from
+    /// `requires.txt` of `.egg-info` metadata. None is returned if neither
+    /// defines requirements.
     fn requires_impl(&self, py: Python) -> PyResult<PyObject> {
-        Err(PyErr::new::<NotImplementedError, _>(py, NoArgs))
+        let metadata = self.as_object().getattr(py, "metadata")?;
+
+        let requires = metadata.call_method(py, "get_all", ("Requires-Dist",), None)?;
+        if requires.is_true(py)? {
+            return Ok(requires);
+        }
+
+        Ok(match self.read_distribution_text(py, "requires.txt")? {
+            Some(text) => parse_egg_info_requires(&text)
+                .to_py_object(py)
+                .into_object(),
+            None => py.None(),
+        })
+    }
+}
+
+/// Import the module providing `importlib.metadata` types.
+///
+/// The `importlib_metadata` backport is used on Python versions lacking
+/// `importlib.metadata`.
+fn import_metadata_module(py: Python) -> PyResult<PyModule> {
+    py.import("importlib.metadata")
+        .or_else(|_| py.import("importlib_metadata"))
+}
+
+/// Parse the content of an `entry_points.txt` file.
+///
+/// Returns `(group, name, value)` tuples in file order. Sections name groups
+/// and each `name = value` line in a section defines an entry point.
+fn parse_entry_points(text: &str) -> Vec<(String, String, String)> {
+    let mut group = None;
+    let mut entry_points = vec![];
+
+    for line in text.lines() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+
+        if line.starts_with('[') && line.ends_with(']') {
+            group = Some(line[1..line.len() - 1].trim().to_string());
+            continue;
+        }
+
+        if let (Some(group), Some(index)) = (&group, line.find('=')) {
+            entry_points.push((
+                group.clone(),
+                line[0..index].trim().to_string(),
+                line[index + 1..].trim().to_string(),
+            ));
+        }
     }
+
+    entry_points
+}
+
+/// Parse the content of a `requires.txt` file of `.egg-info` metadata.
+///
+/// Sections of the form `[extra:markers]` are converted to environment
+/// markers, mirroring what `importlib.metadata` does.
+fn parse_egg_info_requires(text: &str) -> Vec<String> {
+    let mut section: Option<&str> = None;
+    let mut requires = vec![];
+
+    for line in text.lines().filter(|line| !line.is_empty()) {
+        if line.starts_with('[') && line.ends_with(']') {
+            section = Some(&line[1..line.len() - 1]);
+            continue;
+        }
+
+        let (extra, markers) = match section {
+            Some(section) => match section.find(':') {
+                Some(index) => (&section[0..index], &section[index + 1..]),
+                None => (section, ""),
+            },
+            None => ("", ""),
+        };
+
+        let mut conditions = vec![];
+        if !markers.is_empty() {
+            if extra.is_empty() {
+                conditions.push(markers.to_string());
+            } else {
+                conditions.push(format!("({})", markers));
+            }
+        }
+        if !extra.is_empty() {
+            conditions.push(format!("extra == \"{}\"", extra));
+        }
+
+        if conditions.is_empty() {
+            requires.push(line.to_string());
+        } else {
+            requires.push(format!("{}; {}", line, conditions.join(" and ")));
+        }
+    }
+
+    requires
 }
 
 /// Find package metadata distributions given search criteria.
-pub(crate) fn find_distributions<'a>(
+///
+/// `name` is the name of the distribution to find. All distributions are
+/// found if it is `None`.
+///
+/// `path` is the sequence of locations to search. In-memory distributions
+/// are located at the current executable and filesystem-relative
+/// distributions at the directory containing their metadata directory. As
+/// our resources are importable regardless of `sys.path`, a `path` equal to
+/// `sys.path` - the default of `DistributionFinder.Context` - or `None`
+/// searches all locations.
+pub(crate) fn find_distributions(
     py: Python,
     state: Arc<Box<ImporterState>>,
     name: Option<PyObject>,
-    _path: Option<PyObject>,
+    path: Option<PyObject>,
 ) -> PyResult<PyObject> {
-    let resources = &state.resources_state.resources;
+    let resources_state = &state.resources_state;
+
+    let search_paths = match path {
+        Some(path) => resolve_search_paths(py, path)?,
+        None => None,
+    };
 
-    let distributions = if let Some(name) = name {
+    let resources = if let Some(name) = name {
         // Python normalizes the name. We do the same.
         let name = name.str(py)?.to_string(py)?.to_string();
         let name = name.to_lowercase().replace('-', "_");
-        let name_cow = Cow::Borrowed::<str>(&name);
 
-        if let Some(resource) = resources.get(&name_cow) {
-            if resource.is_package
-                && (resource.in_memory_distribution_resources.is_some()
-                    || resource.relative_path_distribution_resources.is_some())
-            {
-                vec![
-                    PyOxidizerDistribution::create_instance(py, state.clone(), name)?.into_object(),
-                ]
-            } else {
-                vec![]
-            }
-        } else {
-            vec![]
-        }
+        resources_state.get_resource(&name).into_iter().collect()
     } else {
-        // Return all distributions.
-        let mut distributions = Vec::new();
+        resources_state.all_resources().or_else(|e| {
+            Err(PyErr::new::<IOError, _>(
+                py,
+                format!("error when resolving resources: {}", e),
+            ))
+        })?
+    };
+
+    let mut distributions = Vec::new();
 
-        for (k, v) in resources.iter() {
-            if v.is_package
-                && (v.in_memory_distribution_resources.is_some()
-                    || v.relative_path_distribution_resources.is_some())
+    for resource in resources {
+        if !resource.is_package
+            || (resource.in_memory_distribution_resources.is_none()
+                && resource.relative_path_distribution_resources.is_none())
+        {
+            continue;
+        }
+
+        if let Some(search_paths) = &search_paths {
+            if !distribution_locations(resources_state, &resource)
+                .iter()
+                .any(|location| search_paths.contains(location))
             {
-                distributions.push(
-                    PyOxidizerDistribution::create_instance(py, state.clone(), k.to_string())?
-                        .into_object(),
-                );
+                continue;
             }
         }
 
-        distributions
-    };
+        distributions.push(
+            PyOxidizerDistribution::create_instance(py, state.clone(), resource.name.to_string())?
+                .into_object(),
+        );
+    }
 
     Ok(PyList::new(py, &distributions).into_object())
 }
 
-fn resolve_package_distribution_resource<'a>(
-    resources: &'a HashMap<Cow<'a, str>, Resource<'a, u8>>,
-    origin: &Path,
+/// Resolve the locations to search from a `DistributionFinder.Context.path`.
+///
+/// Returns `None` if all locations should be searched.
+fn resolve_search_paths(py: Python, path: PyObject) -> PyResult<Option<Vec<PathBuf>>> {
+    let sys_path = py.import("sys")?.get(py, "path")?;
+
+    if py
+        .import("operator")?
+        .call(py, "eq", (&path, sys_path), None)?
+        .is_true(py)?
+    {
+        return Ok(None);
+    }
+
+    let os = py.import("os")?;
+
+    let paths = path
+        .iter(py)?
+        .map(|entry| {
+            let entry = os.call(py, "fspath", (entry?,), None)?;
+            let entry = entry.str(py)?.to_string(py)?.to_string();
+
+            Ok(PathBuf::from(entry))
+        })
+        .collect::<PyResult<Vec<_>>>()?;
+
+    Ok(Some(paths))
+}
+
+/// Obtain the locations a distribution is found in.
+///
+/// In-memory distributions are located at the current executable.
+/// Filesystem-relative distributions are located at the directory containing
+/// their `.dist-info` or `.egg-info` directory.
+fn distribution_locations(
+    resources_state: &PythonResourcesState<u8>,
+    resource: &Resource<u8>,
+) -> Vec<PathBuf> {
+    let mut locations = vec![];
+
+    if resource.in_memory_distribution_resources.is_some() {
+        locations.push(resources_state.current_exe.clone());
+    }
+
+    if let Some(location) = relative_path_distribution_location(resources_state, resource) {
+        locations.push(location);
+    }
+
+    locations
+}
+
+/// Obtain the directory containing the metadata directory of a filesystem-relative distribution.
+fn relative_path_distribution_location(
+    resources_state: &PythonResourcesState<u8>,
+    resource: &Resource<u8>,
+) -> Option<PathBuf> {
+    resource
+        .relative_path_distribution_resources
+        .as_ref()?
+        .values()
+        .filter_map(|path| {
+            resources_state
+                .origin
+                .join(path)
+                .parent()?
+                .parent()
+                .map(|p| p.to_path_buf())
+        })
+        .next()
+}
+
+/// Resolve the path of a file listed in the `RECORD` of a distribution.
+///
+/// `RECORD` paths are relative to the directory containing the metadata
+/// directory. In-memory distributions don't have such a directory, so their
+/// paths are resolved relative to the current executable.
+fn locate_distribution_file(
+    resources_state: &PythonResourcesState<u8>,
+    resource: &Resource<u8>,
+    path: &str,
+) -> PathBuf {
+    match relative_path_distribution_location(resources_state, resource) {
+        Some(location) => location.join(path),
+        None => resources_state.current_exe.join(path),
+    }
+}
+
+fn resolve_package_distribution_resource(
+    resources_state: &PythonResourcesState<u8>,
     package: &str,
     name: &str,
-) -> anyhow::Result<Option<Cow<'a, [u8]>>> {
-    if let Some(entry) = resources.get(package) {
+) -> anyhow::Result<Option<Vec<u8>>> {
+    if let Some(entry) = resources_state.get_resource(package) {
         if let Some(resources) = &entry.in_memory_distribution_resources {
             if let Some(data) = resources.get(name) {
-                return Ok(Some(Cow::Borrowed(data.as_ref())));
+                return Ok(Some(
+                    entry
+                        .resolve_in_memory_data(
+                            ResourceField::InMemoryDistributionResource,
+                            Some(name),
+                            data,
+                        )
+                        .map_err(|e| anyhow::anyhow!(e))?
+                        .into_owned(),
+                ));
             }
         }
 
         if let Some(resources) = &entry.relative_path_distribution_resources {
             if let Some(path) = resources.get(name) {
-                let path = origin.join(path);
+                let path = resources_state.origin.join(path);
                 let data = std::fs::read(&path)?;
 
-                return Ok(Some(Cow::Owned(data)));
+                return Ok(Some(data));
             }
         }
 
@@ -224,3 +529,53 @@ fn resolve_package_distribution_resource<'a>(
         Ok(None)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::borrow::Cow, std::collections::HashMap, std::path::Path};
+
+    #[test]
+    fn test_locate_distribution_file() -> anyhow::Result<()> {
+        let origin = std::env::temp_dir().join(format!("pyembed-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(origin.join("lib").join("foo-1.0.dist-info"))?;
+        std::fs::create_dir_all(origin.join("lib").join("foo"))?;
+        std::fs::write(origin.join("lib").join("foo").join("data.txt"), b"data")?;
+
+        let mut resources_state = PythonResourcesState::<u8>::default();
+        resources_state.current_exe = origin.join("app");
+        resources_state.origin = origin.clone();
+
+        let mut distribution_resources = HashMap::new();
+        distribution_resources.insert(
+            Cow::from("RECORD"),
+            Cow::from(Path::new("lib/foo-1.0.dist-info/RECORD")),
+        );
+
+        let resource = Resource {
+            name: Cow::from("foo"),
+            is_package: true,
+            relative_path_distribution_resources: Some(distribution_resources),
+            ..Resource::default()
+        };
+
+        let path = locate_distribution_file(&resources_state, &resource, "foo/data.txt");
+        assert_eq!(path, origin.join("lib").join("foo/data.txt"));
+        assert_eq!(std::fs::read(&path)?, b"data");
+
+        let resource = Resource {
+            name: Cow::from("foo"),
+            is_package: true,
+            in_memory_distribution_resources: Some(HashMap::new()),
+            ..Resource::default()
+        };
+
+        assert_eq!(
+            locate_distribution_file(&resources_state, &resource, "foo/data.txt"),
+            origin.join("app").join("foo/data.txt")
+        );
+
+        std::fs::remove_dir_all(&origin)?;
+
+        Ok(())
+    }
+}
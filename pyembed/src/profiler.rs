@@ -0,0 +1,365 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Profiling and tracing of Python code.
+
+Rust functions can be registered as Python's profile and trace functions,
+which are called when Python code calls and returns from functions, executes
+new lines, etc. Functions receive the event and decoded information about
+the frame it occurred in.
+
+A sampling profiler can record the Python stacks of all threads at a fixed
+interval from a background thread. Samples can be written as *folded
+stacks*, the input format of flame graph tools such as `flamegraph.pl` and
+`inferno`.
+*/
+
+use {
+    cpython::{GILGuard, NoArgs, ObjectProtocol, PyDict, PyErr, PyObject, Python},
+    libc::{c_char, c_int, c_void},
+    python3_sys as pyffi,
+    std::collections::HashMap,
+    std::fs,
+    std::io::Write,
+    std::panic::{catch_unwind, AssertUnwindSafe},
+    std::path::Path,
+    std::sync::atomic::{AtomicBool, Ordering},
+    std::sync::{Arc, Mutex},
+    std::thread::JoinHandle,
+    std::time::Duration,
+};
+
+type TraceFunc = unsafe extern "C" fn(
+    obj: *mut pyffi::PyObject,
+    frame: *mut pyffi::PyFrameObject,
+    what: c_int,
+    arg: *mut pyffi::PyObject,
+) -> c_int;
+
+extern "C" {
+    fn PyEval_SetProfile(func: Option<TraceFunc>, obj: *mut pyffi::PyObject);
+    fn PyEval_SetTrace(func: Option<TraceFunc>, obj: *mut pyffi::PyObject);
+}
+
+const CALLBACK_CAPSULE_NAME: &[u8] = b"pyembed.trace_callback\0";
+
+/// Describes a profiling or tracing event.
+///
+/// See the documentation of ``sys.settrace()`` and ``sys.setprofile()`` for
+/// when each event occurs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PythonTraceEvent {
+    /// A function is called or a code block is entered.
+    Call,
+    /// An exception was raised.
+    Exception,
+    /// A new line of code is about to be executed.
+    Line,
+    /// A function or code block is about to return.
+    Return,
+    /// A C function is about to be called.
+    CCall,
+    /// A C function raised an exception.
+    CException,
+    /// A C function returned.
+    CReturn,
+    /// A new opcode is about to be executed.
+    Opcode,
+}
+
+impl PythonTraceEvent {
+    fn from_what(what: c_int) -> Option<Self> {
+        match what {
+            0 => Some(PythonTraceEvent::Call),
+            1 => Some(PythonTraceEvent::Exception),
+            2 => Some(PythonTraceEvent::Line),
+            3 => Some(PythonTraceEvent::Return),
+            4 => Some(PythonTraceEvent::CCall),
+            5 => Some(PythonTraceEvent::CException),
+            6 => Some(PythonTraceEvent::CReturn),
+            7 => Some(PythonTraceEvent::Opcode),
+            _ => None,
+        }
+    }
+}
+
+/// Describes a Python stack frame.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PythonFrameInfo {
+    /// Filename of the code being executed.
+    pub filename: String,
+    /// Name of the function or code block being executed.
+    pub function: String,
+    /// Line number being executed.
+    pub line: i32,
+}
+
+/// Convert a borrowed reference to a str to a String.
+///
+/// Returns an empty string if the object isn't a str.
+unsafe fn string_from_ptr(py: Python, ptr: *mut pyffi::PyObject) -> String {
+    if ptr.is_null() {
+        return String::new();
+    }
+
+    PyObject::from_borrowed_ptr(py, ptr)
+        .extract::<String>(py)
+        .unwrap_or_default()
+}
+
+impl PythonFrameInfo {
+    /// Decode information about a frame.
+    ///
+    /// The GIL must be held and `frame` must be a valid frame.
+    unsafe fn from_frame(py: Python, frame: *mut pyffi::PyFrameObject) -> Self {
+        let code = (*frame).f_code;
+
+        PythonFrameInfo {
+            filename: string_from_ptr(py, (*code).co_filename),
+            function: string_from_ptr(py, (*code).co_name),
+            line: pyffi::PyFrame_GetLineNumber(frame),
+        }
+    }
+}
+
+/// A Rust function receiving profiling or tracing events.
+pub(crate) type TraceCallback = Box<dyn FnMut(PythonTraceEvent, &PythonFrameInfo)>;
+
+/// Which of Python's hooks to install a function as.
+pub(crate) enum TraceHook {
+    /// The profile function, as set by ``sys.setprofile()``.
+    Profile,
+    /// The trace function, as set by ``sys.settrace()``.
+    Trace,
+}
+
+unsafe extern "C" fn destroy_callback_capsule(capsule: *mut pyffi::PyObject) {
+    let callback =
+        pyffi::PyCapsule_GetPointer(capsule, CALLBACK_CAPSULE_NAME.as_ptr() as *const c_char)
+            as *mut TraceCallback;
+
+    if !callback.is_null() {
+        drop(Box::from_raw(callback));
+    }
+}
+
+/// Dispatches events from Python's hooks to a Rust function.
+///
+/// `obj` is a capsule holding the function.
+unsafe extern "C" fn call_trace_callback(
+    obj: *mut pyffi::PyObject,
+    frame: *mut pyffi::PyFrameObject,
+    what: c_int,
+    _arg: *mut pyffi::PyObject,
+) -> c_int {
+    let py = Python::assume_gil_acquired();
+
+    let callback = pyffi::PyCapsule_GetPointer(obj, CALLBACK_CAPSULE_NAME.as_ptr() as *const c_char)
+        as *mut TraceCallback;
+
+    if callback.is_null() || frame.is_null() {
+        pyffi::PyErr_Clear();
+        return 0;
+    }
+
+    let event = match PythonTraceEvent::from_what(what) {
+        Some(event) => event,
+        None => return 0,
+    };
+
+    let info = PythonFrameInfo::from_frame(py, frame);
+
+    // Unwinding into Python's C code is undefined behavior. So raise an
+    // exception from the code being profiled or traced instead.
+    match catch_unwind(AssertUnwindSafe(|| (*callback)(event, &info))) {
+        Ok(()) => 0,
+        Err(_) => {
+            PyErr::new::<cpython::exc::RuntimeError, _>(py, "panic in profile or trace callback")
+                .restore(py);
+            -1
+        }
+    }
+}
+
+/// Install a Rust function as the profile or trace function of the current thread.
+///
+/// Replaces the existing function. If `callback` is `None`, the existing
+/// function is removed. The GIL must be held.
+pub(crate) fn set_trace_callback(
+    py: Python,
+    hook: TraceHook,
+    callback: Option<TraceCallback>,
+) -> Result<(), &'static str> {
+    let capsule = match callback {
+        Some(callback) => {
+            let callback = Box::into_raw(Box::new(callback));
+
+            let capsule = unsafe {
+                pyffi::PyCapsule_New(
+                    callback as *mut c_void,
+                    CALLBACK_CAPSULE_NAME.as_ptr() as *const c_char,
+                    Some(destroy_callback_capsule),
+                )
+            };
+
+            if capsule.is_null() {
+                unsafe {
+                    drop(Box::from_raw(callback));
+                }
+                PyErr::fetch(py);
+                return Err("unable to create capsule for callback");
+            }
+
+            capsule
+        }
+        None => std::ptr::null_mut(),
+    };
+
+    let func: Option<TraceFunc> = if capsule.is_null() {
+        None
+    } else {
+        Some(call_trace_callback)
+    };
+
+    // The hook holds a reference to the capsule. So the callback is dropped
+    // when it is replaced.
+    unsafe {
+        match hook {
+            TraceHook::Profile => PyEval_SetProfile(func, capsule),
+            TraceHook::Trace => PyEval_SetTrace(func, capsule),
+        }
+
+        pyffi::Py_XDECREF(capsule);
+    }
+
+    Ok(())
+}
+
+/// Records the Python stacks of all threads at a fixed interval.
+///
+/// Samples are taken by a background thread, which needs to acquire the
+/// GIL to take a sample.
+pub(crate) struct SamplingProfiler {
+    stop: Arc<AtomicBool>,
+    /// Number of samples of each stack, keyed by folded stack.
+    samples: Arc<Mutex<HashMap<String, u64>>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SamplingProfiler {
+    /// Start sampling every `interval`.
+    pub fn start(interval: Duration) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let samples = Arc::new(Mutex::new(HashMap::new()));
+
+        let thread = {
+            let stop = stop.clone();
+            let samples = samples.clone();
+
+            std::thread::spawn(move || loop {
+                std::thread::sleep(interval);
+
+                if stop.load(Ordering::SeqCst) {
+                    break;
+                }
+
+                let gil = GILGuard::acquire();
+                sample_stacks(gil.python(), &samples);
+            })
+        };
+
+        SamplingProfiler {
+            stop,
+            samples,
+            thread: Some(thread),
+        }
+    }
+
+    /// Stop sampling.
+    ///
+    /// The GIL must not be held by the current thread, as the sampling thread
+    /// may be waiting for it.
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+
+    /// Write samples as folded stacks.
+    ///
+    /// Each line holds the `;` delimited frames of a stack, outermost first,
+    /// followed by the number of times the stack was sampled.
+    pub fn write_folded_stacks<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let samples = self.samples.lock().unwrap();
+
+        let mut stacks = samples.iter().collect::<Vec<_>>();
+        stacks.sort();
+
+        for (stack, count) in stacks {
+            writer.write_fmt(format_args!("{} {}\n", stack, count))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Record the current Python stack of every thread.
+fn sample_stacks(py: Python, samples: &Mutex<HashMap<String, u64>>) {
+    let frames = match py
+        .import("sys")
+        .and_then(|sys| sys.call(py, "_current_frames", NoArgs, None))
+    {
+        Ok(frames) => frames,
+        Err(_) => return,
+    };
+
+    let frames = match frames.cast_as::<PyDict>(py) {
+        Ok(frames) => frames,
+        Err(_) => return,
+    };
+
+    let mut samples = samples.lock().unwrap();
+
+    for (_, frame) in frames.items(py) {
+        let mut stack = Vec::new();
+        let mut frame = frame.as_ptr() as *mut pyffi::PyFrameObject;
+
+        while !frame.is_null() {
+            let info = unsafe { PythonFrameInfo::from_frame(py, frame) };
+            stack.push(format!("{} ({})", info.function, info.filename));
+            frame = unsafe { (*frame).f_back };
+        }
+
+        if stack.is_empty() {
+            continue;
+        }
+
+        stack.reverse();
+
+        *samples.entry(stack.join(";")).or_insert(0) += 1;
+    }
+}
+
+/// Write a sampling profile to a directory.
+///
+/// This will create a file named ``profile-<UUID>.folded`` in the directory
+/// holding the samples as folded stacks.
+pub(crate) fn write_profile_to_directory(
+    profiler: &SamplingProfiler,
+    path: &Path,
+) -> Result<(), &'static str> {
+    fs::create_dir_all(path).or_else(|_| Err("could not create directory for profile"))?;
+
+    let rand = uuid::Uuid::new_v4();
+
+    let mut f = fs::File::create(path.join(format!("profile-{}.folded", rand)))
+        .or_else(|_| Err("could not open file for writing"))?;
+    profiler
+        .write_folded_stacks(&mut f)
+        .or_else(|_| Err("could not write"))?;
+
+    Ok(())
+}
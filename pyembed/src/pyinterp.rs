@@ -6,10 +6,17 @@
 
 use {
     super::config::{PythonConfig, PythonRawAllocator, PythonRunMode, TerminfoResolution},
-    super::importer::PyInit__pyoxidizer_importer,
+    super::import_trace::{write_import_trace_to_directory, ImportTracer, ModuleLoadOrder},
+    super::importer::{importer_resources_state, InitModuleState, PyInit__pyoxidizer_importer},
     super::osutils::resolve_terminfo_dirs,
+    super::profiler::{
+        set_trace_callback, write_profile_to_directory, PythonFrameInfo, PythonTraceEvent,
+        SamplingProfiler, TraceHook,
+    },
     super::pyalloc::{make_raw_rust_memory_allocator, RawAllocator},
     super::pystr::{osstr_to_pyobject, osstring_to_bytes, OwnedPyStr},
+    super::python_resources::PythonResourcesState,
+    super::subinterp::PythonSubInterpreter,
     cpython::exc::{SystemExit, ValueError},
     cpython::{
         GILGuard, NoArgs, ObjectProtocol, PyClone, PyDict, PyErr, PyList, PyModule, PyObject,
@@ -22,13 +29,18 @@ use {
     std::ffi::{CStr, CString},
     std::fs,
     std::io::Write,
-    std::path::PathBuf,
+    std::path::{Path, PathBuf},
     std::ptr::null,
+    std::sync::Arc,
+    std::time::Duration,
 };
 
 #[cfg(feature = "jemalloc-sys")]
 use super::pyalloc::make_raw_jemalloc_allocator;
 
+#[cfg(unix)]
+use super::command_server::{command_server_socket, CommandServerExit};
+
 pub const PYOXIDIZER_IMPORTER_NAME: &[u8] = b"_pyoxidizer_importer\0";
 
 const FROZEN_IMPORTLIB_NAME: &[u8] = b"_frozen_importlib\0";
@@ -40,11 +52,143 @@ pub enum PythonRunResult {
     /// Code executed without raising an exception.
     Ok {},
     /// Code executed and raised an exception.
-    Err {},
+    Err { error: PythonError },
     /// Code executed and raised SystemExit with the specified exit code.
     Exit { code: i32 },
 }
 
+/// Describes a raised Python exception.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PythonError {
+    /// Name of the exception type.
+    ///
+    /// Qualified by the name of its module unless it is a built-in type,
+    /// like in tracebacks printed by Python.
+    pub type_name: String,
+    /// The exception value converted to a string.
+    pub message: String,
+    /// Frames of the traceback, outermost first.
+    pub traceback: Vec<PythonFrameInfo>,
+    /// The traceback and exception formatted like Python prints them.
+    pub formatted: String,
+}
+
+impl PythonError {
+    /// Obtain details about a Python error.
+    ///
+    /// Details which can't be resolved are left empty.
+    pub fn from_pyerr(py: Python, err: &PyErr) -> Self {
+        let mut err = PyErr {
+            ptype: err.ptype.clone_ref(py),
+            pvalue: err.pvalue.as_ref().map(|value| value.clone_ref(py)),
+            ptraceback: err.ptraceback.as_ref().map(|tb| tb.clone_ref(py)),
+        };
+        err.normalize(py);
+
+        let message = match &err.pvalue {
+            Some(value) => match value.str(py) {
+                Ok(s) => s.to_string_lossy(py).into_owned(),
+                Err(_) => String::new(),
+            },
+            None => String::new(),
+        };
+
+        let (traceback, formatted) = match py.import("traceback") {
+            Ok(module) => (
+                extract_traceback(py, &module, &err),
+                format_exception(py, &module, &err),
+            ),
+            Err(_) => (Vec::new(), String::new()),
+        };
+
+        PythonError {
+            type_name: exception_type_name(py, &err.ptype),
+            message,
+            traceback,
+            formatted,
+        }
+    }
+}
+
+/// Resolve the name of an exception type like ``traceback`` does.
+fn exception_type_name(py: Python, ptype: &PyObject) -> String {
+    let name = ptype
+        .getattr(py, "__qualname__")
+        .and_then(|name| name.extract::<String>(py))
+        .unwrap_or_default();
+
+    match ptype
+        .getattr(py, "__module__")
+        .and_then(|module| module.extract::<String>(py))
+    {
+        Ok(ref module) if module != "builtins" && module != "__main__" => {
+            format!("{}.{}", module, name)
+        }
+        _ => name,
+    }
+}
+
+/// Obtain the frames of the traceback of a normalized error.
+fn extract_traceback(py: Python, traceback: &PyModule, err: &PyErr) -> Vec<PythonFrameInfo> {
+    let tb = match &err.ptraceback {
+        Some(tb) => tb,
+        None => return Vec::new(),
+    };
+
+    let summary = match traceback.call(py, "extract_tb", (tb.clone_ref(py),), None) {
+        Ok(summary) => summary,
+        Err(_) => return Vec::new(),
+    };
+
+    let frames = match summary.iter(py) {
+        Ok(frames) => frames,
+        Err(_) => return Vec::new(),
+    };
+
+    frames
+        .filter_map(|frame| frame.ok())
+        .map(|frame| PythonFrameInfo {
+            filename: frame
+                .getattr(py, "filename")
+                .and_then(|v| v.extract::<String>(py))
+                .unwrap_or_default(),
+            function: frame
+                .getattr(py, "name")
+                .and_then(|v| v.extract::<String>(py))
+                .unwrap_or_default(),
+            line: frame
+                .getattr(py, "lineno")
+                .and_then(|v| v.extract::<i32>(py))
+                .unwrap_or_default(),
+        })
+        .collect()
+}
+
+/// Format a normalized error like Python prints it.
+fn format_exception(py: Python, traceback: &PyModule, err: &PyErr) -> String {
+    let value = match &err.pvalue {
+        Some(value) => value.clone_ref(py),
+        None => py.None(),
+    };
+    let tb = match &err.ptraceback {
+        Some(tb) => tb.clone_ref(py),
+        None => py.None(),
+    };
+
+    match traceback
+        .call(
+            py,
+            "format_exception",
+            (err.ptype.clone_ref(py), value, tb),
+            None,
+        )
+        .and_then(|lines| lines.extract::<Vec<String>>(py))
+    {
+        Ok(lines) => lines.concat(),
+        Err(_) => String::new(),
+    }
+}
+
 fn make_custom_frozen_modules(config: &PythonConfig) -> [pyffi::_frozen; 3] {
     [
         pyffi::_frozen {
@@ -152,6 +296,16 @@ pub struct MainPythonInterpreter<'a> {
     gil: Option<GILGuard>,
     py: Option<Python<'a>>,
     program_name: Option<OwnedPyStr>,
+    /// Directory to write an import trace to and the tracer recording it.
+    import_trace: Option<(PathBuf, Arc<ImportTracer>)>,
+    /// Records the order modules are loaded in for the loaded modules file.
+    module_load_order: Option<Arc<ModuleLoadOrder>>,
+    /// Directory to write a sampling profile to and the profiler recording it.
+    profiler: Option<(PathBuf, SamplingProfiler)>,
+    /// Receives errors instead of them being printed to stderr.
+    error_sink: Option<Box<dyn FnMut(&PythonError)>>,
+    /// Resources loaded by the importer, shared with sub-interpreters.
+    resources_state: Option<Arc<PythonResourcesState<'static, u8>>>,
 }
 
 impl<'a> MainPythonInterpreter<'a> {
@@ -179,6 +333,23 @@ impl<'a> MainPythonInterpreter<'a> {
 
         let frozen_modules = make_custom_frozen_modules(&config);
 
+        // Tracing needs to be enabled before the first import. So resolve
+        // the trace directory now rather than at shutdown.
+        let import_trace = match &config.write_import_trace_directory_env {
+            Some(key) => match env::var(key) {
+                Ok(path) => Some((PathBuf::from(path), Arc::new(ImportTracer::new()))),
+                Err(_) => None,
+            },
+            None => None,
+        };
+
+        let module_load_order = match &config.write_modules_directory_env {
+            Some(key) if config.write_modules_load_order && env::var(key).is_ok() => {
+                Some(Arc::new(ModuleLoadOrder::new()))
+            }
+            _ => None,
+        };
+
         let mut res = MainPythonInterpreter {
             config,
             frozen_modules,
@@ -188,13 +359,48 @@ impl<'a> MainPythonInterpreter<'a> {
             gil: None,
             py: None,
             program_name: None,
+            import_trace,
+            module_load_order,
+            profiler: None,
+            error_sink: None,
+            resources_state: None,
         };
 
         res.init()?;
+        res.start_sampling_profiler();
 
         Ok(res)
     }
 
+    /// Start the sampling profiler if requested by the environment.
+    pub(crate) fn start_sampling_profiler(&mut self) {
+        if let Some(key) = &self.config.write_profile_directory_env {
+            if let Ok(path) = env::var(key) {
+                let interval =
+                    Duration::from_millis(self.config.profile_sampling_interval_ms.max(1));
+
+                self.profiler = Some((PathBuf::from(path), SamplingProfiler::start(interval)));
+            }
+        }
+    }
+
+    /// Stop the sampling profiler, returning it and where to write its profile.
+    pub(crate) fn stop_sampling_profiler(&mut self) -> Option<(PathBuf, SamplingProfiler)> {
+        let (path, mut profiler) = self.profiler.take()?;
+
+        // The sampling thread may be waiting for the GIL. So release it
+        // while waiting for the thread to exit.
+        self.acquire_gil();
+
+        unsafe {
+            let thread_state = pyffi::PyEval_SaveThread();
+            profiler.stop();
+            pyffi::PyEval_RestoreThread(thread_state);
+        }
+
+        Some((path, profiler))
+    }
+
     /// Initialize the interpreter.
     ///
     /// This mutates global state in the Python interpreter according to the
@@ -214,19 +420,6 @@ impl<'a> MainPythonInterpreter<'a> {
 
         let config = &self.config;
 
-        let exe = env::current_exe().or_else(|_| Err("could not obtain current exe"))?;
-        let origin = exe
-            .parent()
-            .ok_or_else(|| "unable to get exe parent")?
-            .to_path_buf();
-        let origin_string = origin.display().to_string();
-
-        let sys_paths: Vec<String> = config
-            .sys_paths
-            .iter()
-            .map(|path| path.replace("$ORIGIN", &origin_string))
-            .collect();
-
         // TODO should we call PyMem::SetupDebugHooks() if enabled?
         if let Some(raw_allocator) = &self.raw_allocator {
             unsafe {
@@ -271,13 +464,8 @@ impl<'a> MainPythonInterpreter<'a> {
         // It is important for references in this struct to have a lifetime of at least
         // that of the interpreter.
         // TODO specify lifetimes so the compiler validates this for us.
-        let module_state = super::importer::InitModuleState {
-            current_exe: exe.clone(),
-            origin,
-            register_filesystem_importer: self.config.filesystem_importer,
-            sys_paths,
-            packed_resources: config.packed_resources,
-        };
+        let module_state =
+            self.importer_module_state(&config.sys_paths, config.filesystem_importer)?;
 
         if config.use_custom_importlib {
             // Replace the frozen modules in the interpreter with our custom set
@@ -313,7 +501,10 @@ impl<'a> MainPythonInterpreter<'a> {
             }
         }
 
-        let exe_str = exe.to_str().ok_or_else(|| "unable to convert exe to str")?;
+        let exe_str = module_state
+            .current_exe
+            .to_str()
+            .ok_or_else(|| "unable to convert exe to str")?;
 
         let home = OwnedPyStr::from_str(exe_str)?;
 
@@ -406,86 +597,149 @@ impl<'a> MainPythonInterpreter<'a> {
         self.py = Some(py);
         self.init_run = true;
 
-        // env::args() panics if arguments aren't valid Unicode. But invalid
-        // Unicode arguments are possible and some applications may want to
-        // support them.
-        //
-        // env::args_os() provides access to the raw OsString instances, which
-        // will be derived from wchar_t on Windows and char* on POSIX. We can
-        // convert these to Python str instances using a platform-specific
-        // mechanism.
-        let args_objs = env::args_os()
-            .map(|os_arg| osstr_to_pyobject(py, &os_arg, None))
-            .collect::<Result<Vec<PyObject>, &'static str>>()?;
-
-        // This will steal the pointer to the elements and mem::forget them.
-        let args = PyList::new(py, &args_objs);
-        let argv = b"argv\0";
+        if config.use_custom_importlib {
+            self.resources_state = importer_resources_state(py)
+                .or_else(|_| Err("unable to obtain resources of importer"))?;
+        }
 
-        let res = args.with_borrowed_ptr(py, |args_ptr| unsafe {
-            pyffi::PySys_SetObject(argv.as_ptr() as *const i8, args_ptr)
-        });
+        configure_initialized_interpreter(py, config, &module_state.origin)?;
 
-        match res {
-            0 => (),
-            _ => return Err("unable to set sys.argv"),
-        }
+        Ok(py)
+    }
 
-        if config.argvb {
-            let args_objs: Vec<PyObject> = env::args_os()
-                .map(|os_arg| osstring_to_bytes(py, os_arg))
-                .collect();
+    /// Resolve the state of the custom meta path importer module.
+    ///
+    /// ``$ORIGIN`` in `sys_paths` resolves to the directory of the application.
+    fn importer_module_state(
+        &self,
+        sys_paths: &[String],
+        register_filesystem_importer: bool,
+    ) -> Result<InitModuleState, &'static str> {
+        let config = &self.config;
 
-            let args = PyList::new(py, &args_objs);
-            let argvb = b"argvb\0";
+        let exe = env::current_exe().or_else(|_| Err("could not obtain current exe"))?;
+        let origin = exe
+            .parent()
+            .ok_or_else(|| "unable to get exe parent")?
+            .to_path_buf();
+        let origin_string = origin.display().to_string();
 
-            let res = args.with_borrowed_ptr(py, |args_ptr| unsafe {
-                pyffi::PySys_SetObject(argvb.as_ptr() as *const i8, args_ptr)
-            });
+        let sys_paths: Vec<String> = sys_paths
+            .iter()
+            .map(|path| path.replace("$ORIGIN", &origin_string))
+            .collect();
 
-            match res {
-                0 => (),
-                _ => return Err("unable to set sys.argvb"),
-            }
-        }
+        let packed_resources_paths: Vec<PathBuf> = config
+            .packed_resources_paths
+            .iter()
+            .map(|path| match path.strip_prefix("$ORIGIN") {
+                Ok(relative) => origin.join(relative),
+                Err(_) => path.clone(),
+            })
+            .collect();
 
-        // As a convention, sys.oxidized is set to indicate we are running from
-        // a self-contained application.
-        let oxidized = b"oxidized\0";
+        Ok(InitModuleState {
+            current_exe: exe,
+            origin,
+            register_filesystem_importer,
+            sys_paths,
+            resources_state: self.resources_state.clone(),
+            packed_resources: config.packed_resources.clone(),
+            packed_resources_trailer: config.packed_resources_trailer,
+            packed_resources_paths,
+            verify_resources_digests: config.verify_resources_digests,
+            resource_path_extraction: config.resource_path_extraction,
+            lazy_module_loading: config.lazy_module_loading,
+            lazy_modules_allow: config.lazy_modules_allow.clone(),
+            lazy_modules_deny: config.lazy_modules_deny.clone(),
+            import_tracer: self.import_trace.as_ref().map(|(_, tracer)| tracer.clone()),
+            module_load_order: self.module_load_order.clone(),
+        })
+    }
 
-        let res = py.True().with_borrowed_ptr(py, |py_true| unsafe {
-            pyffi::PySys_SetObject(oxidized.as_ptr() as *const i8, py_true)
-        });
+    /// Create a sub-interpreter.
+    ///
+    /// The sub-interpreter has its own modules and PyOxidizer's importer,
+    /// which imports modules from the same packed resources as this
+    /// interpreter. Resources loaded by this interpreter are shared rather
+    /// than loaded again. ``sys.path`` is set to `sys_paths`, in which ``$ORIGIN``
+    /// resolves to the directory of the application. Modules are also imported
+    /// from the filesystem if `sys_paths` isn't empty. ``sys`` attributes
+    /// are otherwise set like for this interpreter.
+    ///
+    /// The GIL must be held. See
+    /// [`PythonSubInterpreter`](struct.PythonSubInterpreter.html) for how
+    /// sub-interpreters can be used.
+    pub fn create_sub_interpreter(
+        &self,
+        sys_paths: &[String],
+    ) -> Result<PythonSubInterpreter<'_>, &'static str> {
+        if self.py.is_none() {
+            return Err("GIL must be held to create a sub-interpreter");
+        }
 
-        match res {
-            0 => (),
-            _ => return Err("unable to set sys.oxidized"),
+        if !self.config.use_custom_importlib {
+            return Err("sub-interpreters require the custom importlib bootstrap modules");
         }
 
-        if config.sys_frozen {
-            let frozen = b"frozen\0";
+        let module_state = self.importer_module_state(
+            sys_paths,
+            self.config.filesystem_importer || !sys_paths.is_empty(),
+        )?;
 
-            match py.True().with_borrowed_ptr(py, |py_true| unsafe {
-                pyffi::PySys_SetObject(frozen.as_ptr() as *const i8, py_true)
-            }) {
-                0 => (),
-                _ => return Err("unable to set sys.frozen"),
-            }
-        }
+        PythonSubInterpreter::new(&module_state, |py| {
+            configure_initialized_interpreter(py, &self.config, &module_state.origin)
+        })
+    }
 
-        if config.sys_meipass {
-            let meipass = b"_MEIPASS\0";
-            let value = PyString::new(py, &origin_string);
+    /// Register a Rust function as the profile function of the current thread.
+    ///
+    /// This is the equivalent of ``sys.setprofile()``. The function is called
+    /// when functions, including C functions, are called and return. It
+    /// replaces the existing profile function, if any. The function is dropped
+    /// when it is replaced or cleared.
+    ///
+    /// If the function panics, a ``RuntimeError`` is raised from the code
+    /// being profiled.
+    pub fn set_profile_callback<F>(&mut self, callback: F) -> Result<(), &'static str>
+    where
+        F: FnMut(PythonTraceEvent, &PythonFrameInfo) + 'static,
+    {
+        let py = self.acquire_gil();
 
-            match value.with_borrowed_ptr(py, |py_value| unsafe {
-                pyffi::PySys_SetObject(meipass.as_ptr() as *const i8, py_value)
-            }) {
-                0 => (),
-                _ => return Err("unable to set sys._MEIPASS"),
-            }
-        }
+        set_trace_callback(py, TraceHook::Profile, Some(Box::new(callback)))
+    }
 
-        Ok(py)
+    /// Remove the profile function of the current thread.
+    pub fn clear_profile_callback(&mut self) -> Result<(), &'static str> {
+        let py = self.acquire_gil();
+
+        set_trace_callback(py, TraceHook::Profile, None)
+    }
+
+    /// Register a Rust function as the trace function of the current thread.
+    ///
+    /// This is the equivalent of ``sys.settrace()``. The function is called
+    /// when functions are called and return, when lines are executed and when
+    /// exceptions are raised. It replaces the existing trace function, if any.
+    /// The function is dropped when it is replaced or cleared.
+    ///
+    /// If the function panics, a ``RuntimeError`` is raised from the code
+    /// being traced.
+    pub fn set_trace_callback<F>(&mut self, callback: F) -> Result<(), &'static str>
+    where
+        F: FnMut(PythonTraceEvent, &PythonFrameInfo) + 'static,
+    {
+        let py = self.acquire_gil();
+
+        set_trace_callback(py, TraceHook::Trace, Some(Box::new(callback)))
+    }
+
+    /// Remove the trace function of the current thread.
+    pub fn clear_trace_callback(&mut self) -> Result<(), &'static str> {
+        let py = self.acquire_gil();
+
+        set_trace_callback(py, TraceHook::Trace, None)
     }
 
     /// Ensure the Python GIL is released.
@@ -607,6 +861,10 @@ impl<'a> MainPythonInterpreter<'a> {
     }
 
     /// Runs the interpreter and handles any exception that was raised.
+    ///
+    /// Raised exceptions other than ``SystemExit`` are reported to the error
+    /// sink, if one is set, or printed to stderr otherwise. Their details are
+    /// returned as well.
     pub fn run_and_handle_error(&mut self) -> PythonRunResult {
         // There are underdefined lifetime bugs at play here. There is no
         // explicit lifetime for the PyObject's returned. If we don't have
@@ -645,18 +903,37 @@ impl<'a> MainPythonInterpreter<'a> {
                     };
                 }
 
-                self.print_err(err);
+                let error = PythonError::from_pyerr(py, &err);
+                self.report_error(py, err, &error);
 
-                PythonRunResult::Err {}
+                PythonRunResult::Err { error }
             }
         }
     }
 
     /// Calls run() and resolves a suitable exit code.
+    ///
+    /// If the process was spawned as a command server by
+    /// ``run_command_client()``, commands are served instead. Processes forked
+    /// by the server to run a command call run() for the command.
     pub fn run_as_main(&mut self) -> i32 {
+        #[cfg(unix)]
+        {
+            if let Some(path) = command_server_socket(&self.config) {
+                match self.run_command_server(&path) {
+                    Ok(CommandServerExit::RunCommand) => {}
+                    Ok(CommandServerExit::Stopped) => return 0,
+                    Err(msg) => {
+                        eprintln!("command server error: {}", msg);
+                        return 1;
+                    }
+                }
+            }
+        }
+
         match self.run_and_handle_error() {
             PythonRunResult::Ok {} => 0,
-            PythonRunResult::Err {} => 1,
+            PythonRunResult::Err { .. } => 1,
             PythonRunResult::Exit { code } => code,
         }
     }
@@ -809,12 +1086,143 @@ impl<'a> MainPythonInterpreter<'a> {
 
     /// Print a Python error.
     ///
-    /// Under the hood this calls ``PyErr_PrintEx()``, which may call
+    /// If an error sink is set, the error's details are passed to it instead.
+    /// Otherwise, under the hood this calls ``PyErr_PrintEx()``, which may call
     /// ``Py_Exit()`` and may write to stderr.
     pub fn print_err(&mut self, err: PyErr) {
         let py = self.acquire_gil();
-        err.print(py);
+
+        if self.error_sink.is_some() {
+            let error = PythonError::from_pyerr(py, &err);
+            self.report_error(py, err, &error);
+        } else {
+            err.print(py);
+        }
+    }
+
+    /// Set a function to receive errors instead of printing them to stderr.
+    ///
+    /// The function receives errors reported by ``print_err()`` and
+    /// ``run_and_handle_error()``, allowing them to be routed to custom logging.
+    /// It replaces the existing sink, if any.
+    pub fn set_error_sink<F>(&mut self, sink: F)
+    where
+        F: FnMut(&PythonError) + 'static,
+    {
+        self.error_sink = Some(Box::new(sink));
+    }
+
+    /// Remove the error sink, printing errors to stderr again.
+    pub fn clear_error_sink(&mut self) {
+        self.error_sink = None;
+    }
+
+    /// Report an error to the error sink or print it to stderr.
+    fn report_error(&mut self, py: Python, err: PyErr, error: &PythonError) {
+        match self.error_sink.as_mut() {
+            Some(sink) => sink(error),
+            None => err.print(py),
+        }
+    }
+}
+
+/// Configure an interpreter after it is initialized.
+///
+/// This sets attributes of the ``sys`` module and prepares lazy module
+/// loading. `py` must refer to the interpreter being configured.
+fn configure_initialized_interpreter(
+    py: Python,
+    config: &PythonConfig,
+    origin: &Path,
+) -> Result<(), &'static str> {
+    // env::args() panics if arguments aren't valid Unicode. But invalid
+    // Unicode arguments are possible and some applications may want to
+    // support them.
+    //
+    // env::args_os() provides access to the raw OsString instances, which
+    // will be derived from wchar_t on Windows and char* on POSIX. We can
+    // convert these to Python str instances using a platform-specific
+    // mechanism.
+    let args_objs = env::args_os()
+        .map(|os_arg| osstr_to_pyobject(py, &os_arg, None))
+        .collect::<Result<Vec<PyObject>, &'static str>>()?;
+
+    // This will steal the pointer to the elements and mem::forget them.
+    let args = PyList::new(py, &args_objs);
+    let argv = b"argv\0";
+
+    let res = args.with_borrowed_ptr(py, |args_ptr| unsafe {
+        pyffi::PySys_SetObject(argv.as_ptr() as *const i8, args_ptr)
+    });
+
+    match res {
+        0 => (),
+        _ => return Err("unable to set sys.argv"),
+    }
+
+    if config.argvb {
+        let args_objs: Vec<PyObject> = env::args_os()
+            .map(|os_arg| osstring_to_bytes(py, os_arg))
+            .collect();
+
+        let args = PyList::new(py, &args_objs);
+        let argvb = b"argvb\0";
+
+        let res = args.with_borrowed_ptr(py, |args_ptr| unsafe {
+            pyffi::PySys_SetObject(argvb.as_ptr() as *const i8, args_ptr)
+        });
+
+        match res {
+            0 => (),
+            _ => return Err("unable to set sys.argvb"),
+        }
+    }
+
+    // As a convention, sys.oxidized is set to indicate we are running from
+    // a self-contained application.
+    let oxidized = b"oxidized\0";
+
+    let res = py.True().with_borrowed_ptr(py, |py_true| unsafe {
+        pyffi::PySys_SetObject(oxidized.as_ptr() as *const i8, py_true)
+    });
+
+    match res {
+        0 => (),
+        _ => return Err("unable to set sys.oxidized"),
+    }
+
+    if config.sys_frozen {
+        let frozen = b"frozen\0";
+
+        match py.True().with_borrowed_ptr(py, |py_true| unsafe {
+            pyffi::PySys_SetObject(frozen.as_ptr() as *const i8, py_true)
+        }) {
+            0 => (),
+            _ => return Err("unable to set sys.frozen"),
+        }
+    }
+
+    if config.sys_meipass {
+        let meipass = b"_MEIPASS\0";
+        let value = PyString::new(py, &origin.display().to_string());
+
+        match value.with_borrowed_ptr(py, |py_value| unsafe {
+            pyffi::PySys_SetObject(meipass.as_ptr() as *const i8, py_value)
+        }) {
+            0 => (),
+            _ => return Err("unable to set sys._MEIPASS"),
+        }
     }
+
+    // Lazy module loading relies on importlib.util.LazyLoader. Modules are
+    // loaded eagerly until importlib.util is imported. So import it now
+    // that interpreter initialization is complete.
+    if config.use_custom_importlib && config.lazy_module_loading {
+        py.import("importlib.util")
+            .or_else(|_| Err("unable to import importlib.util"))?;
+    }
+
+    Ok(())
 }
 
 /// Write loaded Python modules to a directory.
@@ -822,7 +1230,14 @@ impl<'a> MainPythonInterpreter<'a> {
 /// Given a Python interpreter and a path to a directory, this will create a
 /// file in that directory named ``modules-<UUID>`` and write a ``\n`` delimited
 /// list of loaded names from ``sys.modules`` into that file.
-fn write_modules_to_directory(py: Python, path: &PathBuf) -> Result<(), &'static str> {
+///
+/// If a load order is given, modules in it are written first, in load order.
+/// Other modules are written in alphabetical order.
+fn write_modules_to_directory(
+    py: Python,
+    path: &PathBuf,
+    load_order: Option<&ModuleLoadOrder>,
+) -> Result<(), &'static str> {
     // TODO this needs better error handling all over.
 
     fs::create_dir_all(path).or_else(|_| Err("could not create directory for modules"))?;
@@ -850,9 +1265,20 @@ fn write_modules_to_directory(py: Python, path: &PathBuf) -> Result<(), &'static
         );
     }
 
+    let mut ordered_names = match load_order {
+        Some(load_order) => load_order.names(),
+        None => Vec::new(),
+    };
+
+    for name in &ordered_names {
+        names.remove(name);
+    }
+
+    ordered_names.extend(names);
+
     let mut f = fs::File::create(path).or_else(|_| Err("could not open file for writing"))?;
 
-    for name in names {
+    for name in ordered_names {
         f.write_fmt(format_args!("{}\n", name))
             .or_else(|_| Err("could not write"))?;
     }
@@ -862,17 +1288,37 @@ fn write_modules_to_directory(py: Python, path: &PathBuf) -> Result<(), &'static
 
 impl<'a> Drop for MainPythonInterpreter<'a> {
     fn drop(&mut self) {
+        if let Some((path, profiler)) = self.stop_sampling_profiler() {
+            if let Err(msg) = write_profile_to_directory(&profiler, &path) {
+                eprintln!("error writing profile: {}", msg);
+            }
+        }
+
         if let Some(key) = &self.config.write_modules_directory_env {
             if let Ok(path) = env::var(key) {
                 let path = PathBuf::from(path);
                 let py = self.acquire_gil();
 
-                if let Err(msg) = write_modules_to_directory(py, &path) {
+                if let Err(msg) =
+                    write_modules_to_directory(py, &path, self.module_load_order.as_deref())
+                {
                     eprintln!("error writing modules file: {}", msg);
                 }
             }
         }
 
+        if let Some((path, tracer)) = &self.import_trace {
+            if let Err(msg) = write_import_trace_to_directory(tracer, path) {
+                eprintln!("error writing import trace: {}", msg);
+            }
+        }
+
         let _ = unsafe { pyffi::Py_FinalizeEx() };
+
+        // Sub-interpreters share the resources, so extracted resources are
+        // only deleted once all interpreters are finalized.
+        if let Some(resources_state) = &self.resources_state {
+            resources_state.remove_extraction_directory();
+        }
     }
 }
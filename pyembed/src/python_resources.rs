@@ -7,18 +7,26 @@ Management of Python resources.
 */
 
 use {
+    super::import_trace::ImportSource,
     super::pystr::path_to_pyobject,
     cpython::exc::{ImportError, OSError},
     cpython::{
-        NoArgs, ObjectProtocol, PyBytes, PyClone, PyDict, PyErr, PyList, PyObject, PyResult,
-        PyString, Python, PythonObject, ToPyObject,
+        NoArgs, ObjectProtocol, PyBytes, PyDict, PyErr, PyObject, PyResult, PyString, Python,
+        PythonObject, ToPyObject,
     },
+    lazy_static::lazy_static,
     python3_sys as pyffi,
-    python_packed_resources::data::{Resource, ResourceFlavor},
+    python_packed_resources::data::{
+        validate_package_resource_name, Resource, ResourceField, ResourceFlavor,
+    },
+    python_packed_resources::parser::{IndexedResources, ParseError},
+    python_packed_resources::trailer::find_trailer,
     std::borrow::Cow,
-    std::collections::{HashMap, HashSet},
+    std::collections::{BTreeSet, HashMap, HashSet},
     std::ffi::CStr,
+    std::fs::File,
     std::path::{Path, PathBuf},
+    std::sync::Mutex,
 };
 
 /// Python bytecode optimization level.
@@ -55,12 +63,12 @@ where
 ///
 /// This essentially is an abstraction over raw `Resource` entries that
 /// allows the importer code to be simpler.
-pub(crate) struct ImportablePythonModule<'a, X: 'a>
+pub(crate) struct ImportablePythonModule<'a, X: 'a + Clone>
 where
     [X]: ToOwned<Owned = Vec<X>>,
 {
     /// The raw resource backing this importable module.
-    resource: &'a Resource<'a, X>,
+    resource: Cow<'a, Resource<'a, X>>,
 
     /// Path to current executable.
     current_exe: &'a Path,
@@ -68,16 +76,40 @@ where
     /// Path from which relative paths should be interpreted.
     origin: &'a Path,
 
-    /// Cached bytecode (when read from an external source such as the filesystem).
+    /// Cached bytecode (when read from an external source such as the filesystem
+    /// or decompressed from memory).
     bytecode: Option<Vec<u8>>,
 
+    /// Whether to verify the resource's content digest before serving code.
+    verify_digest: bool,
+
     /// The resource/module flavor.
-    pub flavor: &'a ResourceFlavor,
+    pub flavor: ResourceFlavor,
     /// Whether this module is a package.
     pub is_package: bool,
 }
 
 impl<'a> ImportablePythonModule<'a, u8> {
+    /// Verify the content digest of the backing resource, if requested.
+    ///
+    /// Raises `ImportError` if the resource doesn't have a digest or if the
+    /// digest doesn't match the resource's content.
+    fn verify_content_digest(&self, py: Python) -> PyResult<()> {
+        if !self.verify_digest {
+            return Ok(());
+        }
+
+        self.resource.verify_content_digest().or_else(|e| {
+            Err(PyErr::new::<ImportError, _>(
+                py,
+                (
+                    format!("error verifying module integrity: {}", e),
+                    self.resource.name.clone(),
+                ),
+            ))
+        })
+    }
+
     /// Attempt to resolve a Python `bytes` for the source code behind this module.
     ///
     /// Will return a PyErr if an error occurs resolving source. If there is no source,
@@ -89,8 +121,23 @@ impl<'a> ImportablePythonModule<'a, u8> {
     /// and this function can't handle `memoryview`. So until callers can support
     /// 0-copy, let's not worry about it.
     pub fn resolve_source(&self, py: Python) -> PyResult<Option<PyBytes>> {
+        self.verify_content_digest(py)?;
+
         Ok(if let Some(data) = &self.resource.in_memory_source {
-            Some(PyBytes::new(py, data))
+            let data = self
+                .resource
+                .resolve_in_memory_data(ResourceField::InMemorySource, None, data)
+                .or_else(|e| {
+                    Err(PyErr::new::<ImportError, _>(
+                        py,
+                        (
+                            format!("error decompressing module source: {}", e),
+                            self.resource.name.clone(),
+                        ),
+                    ))
+                })?;
+
+            Some(PyBytes::new(py, &data))
         } else if let Some(relative_path) = &self.resource.relative_path_module_source {
             let path = self.origin.join(relative_path);
 
@@ -123,6 +170,10 @@ impl<'a> ImportablePythonModule<'a, u8> {
         py: Python,
         optimize_level: OptimizeLevel,
     ) -> PyResult<Option<PyObject>> {
+        self.verify_content_digest(py)?;
+
+        let resource = &self.resource;
+
         if let Some(cached) = &self.bytecode {
             let ptr = unsafe {
                 pyffi::PyMemoryView_FromMemory(
@@ -133,11 +184,41 @@ impl<'a> ImportablePythonModule<'a, u8> {
             };
 
             Ok(unsafe { PyObject::from_owned_ptr_opt(py, ptr) })
-        } else if let Some(data) = match optimize_level {
-            OptimizeLevel::Zero => &self.resource.in_memory_bytecode,
-            OptimizeLevel::One => &self.resource.in_memory_bytecode_opt1,
-            OptimizeLevel::Two => &self.resource.in_memory_bytecode_opt2,
+        } else if let (field, Some(data)) = match optimize_level {
+            OptimizeLevel::Zero => (
+                ResourceField::InMemoryBytecode,
+                &resource.in_memory_bytecode,
+            ),
+            OptimizeLevel::One => (
+                ResourceField::InMemoryBytecodeOpt1,
+                &resource.in_memory_bytecode_opt1,
+            ),
+            OptimizeLevel::Two => (
+                ResourceField::InMemoryBytecodeOpt2,
+                &resource.in_memory_bytecode_opt2,
+            ),
         } {
+            // Compressed bytecode is decompressed into our cache so the
+            // memoryview has something to reference. Uncompressed bytecode
+            // is referenced directly.
+            let data = match resource
+                .resolve_in_memory_data(field, None, data)
+                .or_else(|e| {
+                    Err(PyErr::new::<ImportError, _>(
+                        py,
+                        (
+                            format!("error decompressing bytecode: {}", e),
+                            resource.name.clone(),
+                        ),
+                    ))
+                })? {
+                Cow::Borrowed(data) => data,
+                Cow::Owned(data) => {
+                    self.bytecode = Some(data);
+                    self.bytecode.as_ref().unwrap()
+                }
+            };
+
             let ptr = unsafe {
                 pyffi::PyMemoryView_FromMemory(
                     data.as_ptr() as _,
@@ -318,6 +399,36 @@ impl<'a> ImportablePythonModule<'a, u8> {
         }
     }
 
+    /// Describe where the code of this module comes from.
+    pub fn import_source(&self, optimize_level: OptimizeLevel) -> ImportSource {
+        let resource = &self.resource;
+
+        match self.flavor {
+            ResourceFlavor::BuiltinExtensionModule => ImportSource::Builtin,
+            ResourceFlavor::FrozenModule => ImportSource::Frozen,
+            ResourceFlavor::Extension => {
+                if resource.in_memory_extension_module_shared_library.is_some() {
+                    ImportSource::Memory
+                } else {
+                    ImportSource::Filesystem
+                }
+            }
+            _ => {
+                let in_memory_bytecode = match optimize_level {
+                    OptimizeLevel::Zero => &resource.in_memory_bytecode,
+                    OptimizeLevel::One => &resource.in_memory_bytecode_opt1,
+                    OptimizeLevel::Two => &resource.in_memory_bytecode_opt2,
+                };
+
+                if in_memory_bytecode.is_some() || resource.in_memory_source.is_some() {
+                    ImportSource::Memory
+                } else {
+                    ImportSource::Filesystem
+                }
+            }
+        }
+    }
+
     /// Obtain the filesystem path to bytecode for this module.
     fn bytecode_path(&self, optimize_level: OptimizeLevel) -> Option<PathBuf> {
         let bytecode_path = match optimize_level {
@@ -352,7 +463,31 @@ where
     pub packages: HashSet<&'static str>,
 
     /// Named resources available for loading.
+    ///
+    /// If `indexed_resources` is non-empty, this only holds resources not
+    /// defined by packed resources data having a lookup index or whose metadata
+    /// was altered after loading. Use `get_resource()` to look up resources.
     pub resources: HashMap<Cow<'a, str>, Resource<'a, X>>,
+
+    /// Packed resources data having a lookup index and random access to it.
+    ///
+    /// Resources in this data are parsed on demand. Entries are in load order.
+    /// Resources in later entries shadow those in earlier entries.
+    pub indexed_resources: Vec<(&'a [u8], IndexedResources<'a>)>,
+
+    /// Whether to verify digests of resources data.
+    ///
+    /// If set, the payload digest is verified when resources data is loaded
+    /// and the content digest of a module is verified before its code is
+    /// served.
+    pub verify_digests: bool,
+
+    /// Whether to extract in-memory package resources to serve filesystem paths.
+    pub resource_path_extraction: bool,
+
+    /// Temporary directory holding extracted package resources, once created,
+    /// and the ID of the process which created it.
+    extraction_directory: Mutex<Option<(PathBuf, u32)>>,
 }
 
 impl<'a> Default for PythonResourcesState<'a, u8> {
@@ -362,74 +497,184 @@ impl<'a> Default for PythonResourcesState<'a, u8> {
             origin: PathBuf::new(),
             packages: HashSet::new(),
             resources: HashMap::new(),
+            indexed_resources: vec![],
+            verify_digests: false,
+            resource_path_extraction: false,
+            extraction_directory: Mutex::new(None),
         }
     }
 }
 
+lazy_static! {
+    /// Files memory mapped by `map_file()`, keyed by path.
+    static ref MAPPED_FILES: Mutex<HashMap<PathBuf, &'static memmap::Mmap>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Memory map a file for the lifetime of the process.
+///
+/// Resources and values derived from them may reference the mapped memory
+/// for as long as they like. So mappings are never unmapped. Each path is
+/// mapped once per process and later calls reuse the existing mapping.
+fn map_file(path: &Path) -> Result<&'static [u8], String> {
+    let mut mapped_files = MAPPED_FILES.lock().unwrap();
+
+    if let Some(mapped) = mapped_files.get(path) {
+        return Ok(&mapped[..]);
+    }
+
+    let f = File::open(path).map_err(|e| format!("error opening {}: {}", path.display(), e))?;
+
+    let mapped = unsafe { memmap::Mmap::map(&f) }
+        .map_err(|e| format!("error memory mapping {}: {}", path.display(), e))?;
+
+    let mapped: &'static memmap::Mmap = Box::leak(Box::new(mapped));
+    mapped_files.insert(path.to_path_buf(), mapped);
+
+    Ok(&mapped[..])
+}
+
+/// Obtain the number of files in a directory memory mapped by `map_file()`.
+#[cfg(test)]
+pub(crate) fn mapped_files_count(directory: &Path) -> usize {
+    MAPPED_FILES
+        .lock()
+        .unwrap()
+        .keys()
+        .filter(|path| path.starts_with(directory))
+        .count()
+}
+
 impl<'a> PythonResourcesState<'a, u8> {
     /// Load state from the environment and by parsing data structures.
-    pub fn load(&mut self, resources_data: &'static [u8]) -> Result<(), &'static str> {
+    ///
+    /// Each of `resources_datas` is loaded in order, followed by the packed
+    /// resources data in the trailer of `current_exe` if `resources_trailer`
+    /// is set, followed by the packed resources data in each file of
+    /// `resources_paths`. Files are memory mapped once for the lifetime of
+    /// the process. Resources loaded later shadow resources of the same name
+    /// loaded earlier. Empty data is ignored.
+    ///
+    /// Fields stored in compressed blob sections are decompressed by the
+    /// parser and held as owned data. Fields in uncompressed sections continue
+    /// to reference the packed resources data directly.
+    pub fn load(
+        &mut self,
+        resources_datas: &[&'static [u8]],
+        resources_trailer: bool,
+        resources_paths: &[PathBuf],
+    ) -> Result<(), String> {
+        // Applications may only use packed resources data in files.
+        for data in resources_datas.iter().filter(|data| !data.is_empty()) {
+            self.load_resources(data)
+                .map_err(|e| format!("error parsing packed resources: {}", e))?;
+        }
+
+        if resources_trailer {
+            self.load_resources_trailer()?;
+        }
+
+        for path in resources_paths {
+            self.load_resources_file(path)?;
+        }
+
         // Loading of builtin and frozen knows to mutate existing entries rather
         // than replace. So do these last.
-        self.load_resources(resources_data)?;
         self.load_interpreter_builtin_modules()?;
         self.load_interpreter_frozen_modules()?;
 
         Ok(())
     }
 
+    /// Obtain a named resource.
+    ///
+    /// Resources in packed resources data having a lookup index are parsed on
+    /// demand and returned as owned values. Resources whose entry can't be
+    /// parsed are treated as missing.
+    pub fn get_resource(&self, name: &str) -> Option<Cow<Resource<'a, u8>>> {
+        if let Some(resource) = self.resources.get(name) {
+            Some(Cow::Borrowed(resource))
+        } else if let Some(resource) = self.get_indexed_resource(name) {
+            Some(Cow::Owned(resource))
+        } else {
+            None
+        }
+    }
+
+    /// Obtain a named resource from the lookup index of packed resources data.
+    ///
+    /// Packed resources data loaded later is searched first.
+    fn get_indexed_resource(&self, name: &str) -> Option<Resource<'a, u8>> {
+        self.indexed_resources
+            .iter()
+            .rev()
+            .find_map(|(_, indexed)| indexed.get(name).ok().flatten())
+    }
+
+    /// Obtain all resources.
+    ///
+    /// If resources are looked up via a lookup index, this parses all packed
+    /// resources data. So this should only be called when all resources are
+    /// needed.
+    pub fn all_resources(&self) -> Result<Vec<Cow<Resource<'a, u8>>>, ParseError> {
+        let mut resources = self
+            .resources
+            .values()
+            .map(Cow::Borrowed)
+            .collect::<Vec<_>>();
+
+        let mut seen = self.resources.keys().cloned().collect::<HashSet<_>>();
+
+        // Later data shadows earlier data. So process it first.
+        for (data, _) in self.indexed_resources.iter().rev() {
+            for resource in python_packed_resources::parser::load_resources(data)? {
+                let resource = resource?;
+
+                if seen.insert(resource.name.clone()) {
+                    resources.push(Cow::Owned(resource));
+                }
+            }
+        }
+
+        Ok(resources)
+    }
+
     /// Attempt to resolve an importable Python module.
     pub fn resolve_importable_module(
         &self,
         name: &str,
         optimize_level: OptimizeLevel,
     ) -> Option<ImportablePythonModule<u8>> {
-        let resource = match self.resources.get(name) {
-            Some(entry) => entry,
+        // Resources are rebuilt so their lifetime can be narrowed to ours.
+        let resource = match self.get_resource(name) {
+            Some(Cow::Borrowed(entry)) => Cow::Borrowed(entry),
+            Some(Cow::Owned(entry)) => Cow::Owned(entry),
             None => return None,
         };
 
-        match resource.flavor {
+        let verify_digest = match resource.flavor {
             ResourceFlavor::Module => {
-                if is_module_importable(resource, optimize_level) {
-                    Some(ImportablePythonModule {
-                        resource,
-                        current_exe: &self.current_exe,
-                        origin: &self.origin,
-                        bytecode: None,
-                        flavor: &resource.flavor,
-                        is_package: resource.is_package,
-                    })
+                if is_module_importable(&resource, optimize_level) {
+                    self.verify_digests
                 } else {
-                    None
+                    return None;
                 }
             }
-            ResourceFlavor::Extension => Some(ImportablePythonModule {
-                resource,
-                current_exe: &self.current_exe,
-                origin: &self.origin,
-                bytecode: None,
-                flavor: &resource.flavor,
-                is_package: resource.is_package,
-            }),
-            ResourceFlavor::BuiltinExtensionModule => Some(ImportablePythonModule {
-                resource,
-                current_exe: &self.current_exe,
-                origin: &self.origin,
-                bytecode: None,
-                flavor: &resource.flavor,
-                is_package: resource.is_package,
-            }),
-            ResourceFlavor::FrozenModule => Some(ImportablePythonModule {
-                resource,
-                current_exe: &self.current_exe,
-                origin: &self.origin,
-                bytecode: None,
-                flavor: &resource.flavor,
-                is_package: resource.is_package,
-            }),
-            _ => None,
-        }
+            ResourceFlavor::Extension
+            | ResourceFlavor::BuiltinExtensionModule
+            | ResourceFlavor::FrozenModule => false,
+            _ => return None,
+        };
+
+        Some(ImportablePythonModule {
+            flavor: resource.flavor,
+            is_package: resource.is_package,
+            resource,
+            current_exe: &self.current_exe,
+            origin: &self.origin,
+            bytecode: None,
+            verify_digest,
+        })
     }
 
     /// Obtain a single named resource in a package.
@@ -443,7 +688,7 @@ impl<'a> PythonResourcesState<'a, u8> {
         package: &str,
         resource_name: &str,
     ) -> PyResult<Option<PyObject>> {
-        let entry = match self.resources.get(package) {
+        let entry = match self.get_resource(package) {
             Some(entry) => entry,
             None => return Ok(None),
         };
@@ -453,7 +698,14 @@ impl<'a> PythonResourcesState<'a, u8> {
                 let io_module = py.import("io")?;
                 let bytes_io = io_module.get(py, "BytesIO")?;
 
-                let data = PyBytes::new(py, data);
+                let data = entry
+                    .resolve_in_memory_data(
+                        ResourceField::InMemoryResourcesData,
+                        Some(resource_name),
+                        data,
+                    )
+                    .or_else(|e| Err(PyErr::new::<OSError, _>(py, e)))?;
+                let data = PyBytes::new(py, &data);
                 return Ok(Some(bytes_io.call(py, (data,), None)?));
             }
         }
@@ -475,9 +727,128 @@ impl<'a> PythonResourcesState<'a, u8> {
         Ok(None)
     }
 
+    /// Resolve the filesystem path of a Python package resource.
+    ///
+    /// Filesystem relative resources resolve to their path. In-memory resources
+    /// are extracted to a temporary directory if `resource_path_extraction` is
+    /// set. `Ok(None)` is returned if the resource does not exist or does not
+    /// have a filesystem path.
+    pub fn resolve_package_resource_path(
+        &self,
+        py: Python,
+        package: &str,
+        resource_name: &str,
+    ) -> PyResult<Option<PathBuf>> {
+        let entry = match self.get_resource(package) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+
+        if let Some(resources) = &entry.in_memory_package_resources {
+            if let Some(data) = resources.get(resource_name) {
+                if !self.resource_path_extraction {
+                    return Ok(None);
+                }
+
+                let data = entry
+                    .resolve_in_memory_data(
+                        ResourceField::InMemoryResourcesData,
+                        Some(resource_name),
+                        data,
+                    )
+                    .or_else(|e| Err(PyErr::new::<OSError, _>(py, e)))?;
+
+                return Ok(Some(self.extract_package_resource(
+                    py,
+                    package,
+                    resource_name,
+                    &data,
+                )?));
+            }
+        }
+
+        if let Some(resources) = &entry.relative_path_package_resources {
+            if let Some(path) = resources.get(resource_name) {
+                return Ok(Some(self.origin.join(path)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Write the data of an in-memory package resource to the filesystem.
+    ///
+    /// The temporary directory holding extracted resources is created on first
+    /// use and deleted by `remove_extraction_directory()`. Resources are
+    /// extracted once.
+    fn extract_package_resource(
+        &self,
+        py: Python,
+        package: &str,
+        resource_name: &str,
+        data: &[u8],
+    ) -> PyResult<PathBuf> {
+        // Names come from packed resources data, which may not have been
+        // validated. Don't let them escape the temporary directory.
+        validate_package_resource_name(resource_name)
+            .or_else(|e| Err(PyErr::new::<OSError, _>(py, e)))?;
+
+        let mut directory = self.extraction_directory.lock().unwrap();
+
+        if directory.is_none() {
+            let path =
+                std::env::temp_dir().join(format!("pyoxidizer-resources-{}", uuid::Uuid::new_v4()));
+
+            std::fs::create_dir(&path).or_else(|e| {
+                Err(PyErr::new::<OSError, _>(
+                    py,
+                    format!("error creating {}: {}", path.display(), e),
+                ))
+            })?;
+
+            *directory = Some((path, std::process::id()));
+        }
+
+        let mut path = directory.as_ref().unwrap().0.clone();
+        path.extend(package.split('.'));
+        path.extend(resource_name.split('/'));
+
+        if !path.exists() {
+            let write = || -> std::io::Result<()> {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+
+                std::fs::write(&path, data)
+            };
+
+            write().or_else(|e| {
+                Err(PyErr::new::<OSError, _>(
+                    py,
+                    format!("error extracting resource to {}: {}", path.display(), e),
+                ))
+            })?;
+        }
+
+        Ok(path)
+    }
+
+    /// Delete the temporary directory holding extracted package resources.
+    ///
+    /// Processes forked from the process which created the directory, like
+    /// commands run by a command server, share it. Only the creating process
+    /// deletes the directory, as it is still used by it and its other children.
+    pub fn remove_extraction_directory(&self) {
+        if let Some((path, pid)) = self.extraction_directory.lock().unwrap().take() {
+            if std::process::id() == pid {
+                let _ = std::fs::remove_dir_all(&path);
+            }
+        }
+    }
+
     /// Determines whether a specific package + name pair is a known Python package resource.
     pub fn is_package_resource(&self, package: &str, resource_name: &str) -> bool {
-        if let Some(entry) = self.resources.get(package) {
+        if let Some(entry) = self.get_resource(package) {
             if let Some(resources) = &entry.in_memory_package_resources {
                 if resources.contains_key(resource_name) {
                     return true;
@@ -494,32 +865,180 @@ impl<'a> PythonResourcesState<'a, u8> {
         false
     }
 
-    /// Obtain the resources available in a Python package, as a Python list.
+    /// Obtain the entries of a Python package's resources, as a Python list.
+    ///
+    /// Like `os.listdir()` on a package directory, this holds the names of
+    /// resources and directories directly within the package. Resources in
+    /// subdirectories are represented by their top-most directory.
     pub fn package_resource_names(&self, py: Python, package: &str) -> PyResult<PyObject> {
-        let entry = match self.resources.get(package) {
-            Some(entry) => entry,
-            None => return Ok(PyList::new(py, &[]).into_object()),
+        let names = self
+            .package_resource_directory_entries(package, "")
+            .or_else(|e| Err(PyErr::new::<OSError, _>(py, e.to_string())))?;
+
+        Ok(names.to_py_object(py).into_object())
+    }
+
+    /// Obtain the names of all resources in a Python package.
+    ///
+    /// Names of in-memory and filesystem relative resources are combined.
+    fn package_resource_names_set(&self, package: &str) -> BTreeSet<String> {
+        let mut names = BTreeSet::new();
+
+        if let Some(entry) = self.get_resource(package) {
+            if let Some(resources) = &entry.in_memory_package_resources {
+                names.extend(resources.keys().map(|name| name.to_string()));
+            }
+
+            if let Some(resources) = &entry.relative_path_package_resources {
+                names.extend(resources.keys().map(|name| name.to_string()));
+            }
+        }
+
+        names
+    }
+
+    /// Determines whether a path in a Python package's resources is a directory.
+    ///
+    /// `path` is relative to the package and uses `/` as a separator. The empty
+    /// path denotes the package itself. Directories aren't stored: a path is a
+    /// directory if resources exist beneath it.
+    pub fn is_package_resource_directory(&self, package: &str, path: &str) -> bool {
+        if path.is_empty() {
+            return match self.get_resource(package) {
+                Some(entry) => entry.is_package,
+                None => false,
+            };
+        }
+
+        let prefix = format!("{}/", path);
+
+        self.package_resource_names_set(package)
+            .iter()
+            .any(|name| name.starts_with(&prefix))
+    }
+
+    /// Obtain the names of resources which may be packages directly within a package.
+    ///
+    /// Names are found without parsing resources, via the lookup index of
+    /// indexed packed resources data.
+    fn child_package_candidates(&self, package: &str) -> Result<BTreeSet<String>, ParseError> {
+        let prefix = format!("{}.", package);
+        let is_child =
+            |name: &str| name.starts_with(&prefix) && !name[prefix.len()..].contains('.');
+
+        let mut names = self
+            .resources
+            .keys()
+            .filter(|name| is_child(name))
+            .map(|name| name.to_string())
+            .collect::<BTreeSet<_>>();
+
+        for (_, indexed) in &self.indexed_resources {
+            for name in indexed.names_with_prefix(&prefix)? {
+                if is_child(&name) {
+                    names.insert(name);
+                }
+            }
+        }
+
+        Ok(names)
+    }
+
+    /// Obtain the names of entries in a directory in a Python package's resources.
+    ///
+    /// Names are sorted. The entries of the package itself include the
+    /// packages directly within it, as their resources are reachable through
+    /// it.
+    pub fn package_resource_directory_entries(
+        &self,
+        package: &str,
+        path: &str,
+    ) -> Result<Vec<String>, ParseError> {
+        let prefix = if path.is_empty() {
+            "".to_string()
+        } else {
+            format!("{}/", path)
         };
 
-        if let Some(resources) = &entry.in_memory_package_resources {
-            let names = resources
-                .keys()
-                .map(|name| name.to_py_object(py))
-                .collect::<Vec<PyString>>();
+        let mut entries = BTreeSet::new();
 
-            return Ok(names.to_py_object(py).as_object().clone_ref(py));
+        for name in self.package_resource_names_set(package) {
+            if name.starts_with(&prefix) {
+                let relative = &name[prefix.len()..];
+
+                entries.insert(match relative.find('/') {
+                    Some(index) => relative[0..index].to_string(),
+                    None => relative.to_string(),
+                });
+            }
         }
 
-        if let Some(resources) = &entry.relative_path_package_resources {
-            let names = resources
-                .keys()
-                .map(|name| name.to_py_object(py))
-                .collect::<Vec<PyString>>();
+        if path.is_empty() {
+            for name in self.child_package_candidates(package)? {
+                if let Some(resource) = self.get_resource(&name) {
+                    if resource.is_package {
+                        entries.insert(name[package.len() + 1..].to_string());
+                    }
+                }
+            }
+        }
 
-            return Ok(names.to_py_object(py).as_object().clone_ref(py));
+        Ok(entries.into_iter().collect())
+    }
+
+    /// Obtain the content of a data file.
+    ///
+    /// Data files are application data not belonging to any Python package.
+    /// In-memory data is borrowed from the packed resources data when it isn't
+    /// compressed. Data files registered by relative path are read from the
+    /// filesystem. `Ok(None)` is returned if the data file does not exist.
+    pub fn get_data_file(&self, name: &str) -> anyhow::Result<Option<Cow<'a, [u8]>>> {
+        let resource = match self.get_resource(name) {
+            Some(resource) if resource.flavor == ResourceFlavor::DataFile => resource,
+            _ => return Ok(None),
+        };
+
+        if self.verify_digests {
+            resource
+                .verify_content_digest()
+                .map_err(|e| anyhow::anyhow!("error verifying data file integrity: {}", e))?;
+        }
+
+        if let Some(data) = &resource.in_memory_data_file {
+            let data = match data {
+                Cow::Borrowed(data) => {
+                    resource.resolve_in_memory_data(ResourceField::InMemoryDataFile, None, *data)
+                }
+                Cow::Owned(data) => resource
+                    .resolve_in_memory_data(ResourceField::InMemoryDataFile, None, data)
+                    .map(|data| Cow::Owned(data.into_owned())),
+            }
+            .map_err(|e| anyhow::anyhow!(e))?;
+
+            return Ok(Some(data));
+        }
+
+        if let Some(path) = &resource.relative_path_data_file {
+            let path = self.origin.join(path);
+
+            return Ok(Some(Cow::Owned(std::fs::read(&path)?)));
         }
 
-        Ok(PyList::new(py, &[]).into_object())
+        Ok(None)
+    }
+
+    /// Obtain the names of all data files.
+    pub fn data_file_names(&self) -> Result<Vec<String>, ParseError> {
+        let mut names = self
+            .all_resources()?
+            .iter()
+            .filter(|resource| resource.flavor == ResourceFlavor::DataFile)
+            .map(|resource| resource.name.to_string())
+            .collect::<Vec<_>>();
+
+        names.sort();
+
+        Ok(names)
     }
 
     /// Attempt to resolve a PyBytes for resource data given a relative path.
@@ -626,11 +1145,19 @@ impl<'a> PythonResourcesState<'a, u8> {
             let resource_name = name_parts.join("/");
             let resource_name_ref: &str = &resource_name;
 
-            if let Some(entry) = self.resources.get(package_name_ref) {
+            if let Some(entry) = self.get_resource(package_name_ref) {
                 if check_in_memory {
                     if let Some(resources) = &entry.in_memory_package_resources {
                         if let Some(data) = resources.get(resource_name_ref) {
-                            return Ok(PyBytes::new(py, data).into_object());
+                            let data = entry
+                                .resolve_in_memory_data(
+                                    ResourceField::InMemoryResourcesData,
+                                    Some(resource_name_ref),
+                                    data,
+                                )
+                                .or_else(|e| Err(PyErr::new::<OSError, _>(py, e)))?;
+
+                            return Ok(PyBytes::new(py, &data).into_object());
                         }
                     }
                 }
@@ -691,6 +1218,9 @@ impl<'a> PythonResourcesState<'a, u8> {
             // update the big.
             if let Some(mut entry) = self.resources.get_mut(name_str) {
                 entry.flavor = ResourceFlavor::BuiltinExtensionModule;
+            } else if let Some(mut entry) = self.get_indexed_resource(name_str) {
+                entry.flavor = ResourceFlavor::BuiltinExtensionModule;
+                self.resources.insert(entry.name.clone(), entry);
             } else {
                 self.resources.insert(
                     // This is probably unsafe.
@@ -728,6 +1258,9 @@ impl<'a> PythonResourcesState<'a, u8> {
             // update the big.
             if let Some(mut entry) = self.resources.get_mut(name_str) {
                 entry.flavor = ResourceFlavor::FrozenModule;
+            } else if let Some(mut entry) = self.get_indexed_resource(name_str) {
+                entry.flavor = ResourceFlavor::FrozenModule;
+                self.resources.insert(entry.name.clone(), entry);
             } else {
                 self.resources.insert(
                     // This is probably unsafe.
@@ -745,9 +1278,27 @@ impl<'a> PythonResourcesState<'a, u8> {
     }
 
     /// Load resources by parsing a blob.
-    fn load_resources(&mut self, data: &'a [u8]) -> Result<(), &'static str> {
+    fn load_resources(&mut self, data: &'a [u8]) -> Result<(), ParseError> {
         let resources = python_packed_resources::parser::load_resources(data)?;
 
+        if self.verify_digests {
+            resources.verify_payload_digest()?;
+        }
+
+        // Resources are looked up on demand if possible, avoiding parsing
+        // entries of resources that are never used.
+        if resources.has_lookup_index() {
+            let indexed = resources.into_indexed()?;
+
+            // Resources loaded earlier are shadowed by this data.
+            self.resources
+                .retain(|name, _| !indexed.contains(name).unwrap_or(false));
+
+            self.indexed_resources.push((data, indexed));
+
+            return Ok(());
+        }
+
         // Reserve space for expected number of incoming items so we can avoid extra
         // allocations.
         self.resources.reserve(resources.expected_resources_count());
@@ -760,4 +1311,72 @@ impl<'a> PythonResourcesState<'a, u8> {
 
         Ok(())
     }
+
+    /// Load resources from packed resources data appended to `current_exe`.
+    fn load_resources_trailer(&mut self) -> Result<(), String> {
+        let exe = self.current_exe.clone();
+        let data = map_file(&exe)?;
+
+        let data = match find_trailer(data) {
+            Ok(Some(data)) => data,
+            Ok(None) => {
+                return Err(format!(
+                    "{} does not have a packed resources trailer",
+                    exe.display()
+                ))
+            }
+            Err(e) => {
+                return Err(format!(
+                    "error parsing packed resources trailer of {}: {}",
+                    exe.display(),
+                    e
+                ))
+            }
+        };
+
+        self.load_resources(data).map_err(|e| {
+            format!(
+                "error parsing packed resources trailer of {}: {}",
+                exe.display(),
+                e
+            )
+        })
+    }
+
+    /// Load resources from a file containing packed resources data.
+    ///
+    /// The file is memory mapped for the lifetime of the process. Loading the
+    /// same file again reuses the mapping.
+    fn load_resources_file(&mut self, path: &Path) -> Result<(), String> {
+        let data = map_file(path)?;
+
+        self.load_resources(data).map_err(|e| {
+            format!(
+                "error parsing packed resources in {}: {}",
+                path.display(),
+                e
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map_file_reuses_mappings() -> Result<(), String> {
+        let directory = std::env::temp_dir().join(format!("pyembed-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&directory).map_err(|e| e.to_string())?;
+        let path = directory.join("resources");
+        std::fs::write(&path, b"data").map_err(|e| e.to_string())?;
+
+        let first = map_file(&path)?;
+        let second = map_file(&path)?;
+        assert_eq!(first, b"data");
+        assert_eq!(first.as_ptr(), second.as_ptr());
+        assert_eq!(mapped_files_count(&directory), 1);
+
+        Ok(())
+    }
 }
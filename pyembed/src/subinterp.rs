@@ -0,0 +1,124 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Manage Python sub-interpreters.
+
+use {
+    super::importer::{InitModuleState, NEXT_MODULE_STATE},
+    cpython::Python,
+    python3_sys as pyffi,
+    std::marker::PhantomData,
+};
+
+/// Makes a thread state current until dropped.
+struct ThreadStateGuard {
+    previous: *mut pyffi::PyThreadState,
+}
+
+impl ThreadStateGuard {
+    fn swap(thread_state: *mut pyffi::PyThreadState) -> Self {
+        ThreadStateGuard {
+            previous: unsafe { pyffi::PyThreadState_Swap(thread_state) },
+        }
+    }
+}
+
+impl Drop for ThreadStateGuard {
+    fn drop(&mut self) {
+        unsafe {
+            pyffi::PyThreadState_Swap(self.previous);
+        }
+    }
+}
+
+/// A Python sub-interpreter.
+///
+/// Instances are created by
+/// [`MainPythonInterpreter::create_sub_interpreter()`](struct.MainPythonInterpreter.html#method.create_sub_interpreter).
+///
+/// A sub-interpreter is an isolated environment for executing Python code
+/// within the process of the main interpreter. It has its own set of imported
+/// modules, including ``sys``, and its own ``sys.path``. Modules imported by
+/// other interpreters aren't visible to it. PyOxidizer's importer is installed
+/// and imports modules from the same packed resources as the main interpreter.
+///
+/// All interpreters share the GIL. A sub-interpreter is bound to the thread
+/// which created it and the GIL must be held by that thread to use it or drop
+/// it. Python objects created by a sub-interpreter should not outlive
+/// [`with()`](#method.with) calls. Extension modules which don't support
+/// multi-phase initialization may share state between interpreters.
+///
+/// The sub-interpreter is destroyed when dropped.
+pub struct PythonSubInterpreter<'interp> {
+    thread_state: *mut pyffi::PyThreadState,
+    /// Sub-interpreters must not outlive the main interpreter.
+    _interp: PhantomData<&'interp ()>,
+}
+
+impl<'interp> PythonSubInterpreter<'interp> {
+    /// Create a sub-interpreter using the given state for its importer module.
+    ///
+    /// The GIL must be held.
+    pub(crate) fn new(
+        module_state: &InitModuleState,
+        configure: impl FnOnce(Python) -> Result<(), &'static str>,
+    ) -> Result<PythonSubInterpreter<'interp>, &'static str> {
+        let previous = unsafe { pyffi::PyThreadState_Get() };
+
+        // The importer module is initialized as part of creating the
+        // sub-interpreter. Like during Py_Initialize(), it reads its state
+        // from this pointer.
+        let thread_state = unsafe {
+            NEXT_MODULE_STATE = module_state;
+            let thread_state = pyffi::Py_NewInterpreter();
+            NEXT_MODULE_STATE = std::ptr::null();
+
+            thread_state
+        };
+
+        // The previous thread state is restored on failure.
+        if thread_state.is_null() {
+            return Err("unable to create sub-interpreter");
+        }
+
+        let interp = PythonSubInterpreter {
+            thread_state,
+            _interp: PhantomData,
+        };
+
+        // Py_NewInterpreter() made the sub-interpreter current.
+        let res = configure(unsafe { Python::assume_gil_acquired() });
+
+        unsafe {
+            pyffi::PyThreadState_Swap(previous);
+        }
+
+        res.map(|_| interp)
+    }
+
+    /// Run a function with this sub-interpreter as the current interpreter.
+    ///
+    /// The previously current interpreter is restored when the function
+    /// returns.
+    pub fn with<F, T>(&self, f: F) -> T
+    where
+        F: FnOnce(Python) -> T,
+    {
+        let _guard = ThreadStateGuard::swap(self.thread_state);
+
+        f(unsafe { Python::assume_gil_acquired() })
+    }
+}
+
+impl<'interp> Drop for PythonSubInterpreter<'interp> {
+    fn drop(&mut self) {
+        let previous = unsafe { pyffi::PyThreadState_Swap(self.thread_state) };
+
+        unsafe {
+            // Leaves no thread state current.
+            pyffi::Py_EndInterpreter(self.thread_state);
+            pyffi::PyThreadState_Swap(previous);
+        }
+    }
+}
@@ -61,6 +61,23 @@ they were created with.
 On success, instructions on potential next steps are printed.
 ";
 
+const PACKED_RESOURCES_TRAILER_ABOUT: &str = "\
+Append packed resources data to an executable.
+
+The EXE_PATH argument is a filesystem path to an executable. The
+RESOURCES_PATH argument is a filesystem path to a file containing packed
+resources data.
+
+The packed resources data is appended to the executable as a trailer,
+replacing an existing trailer. Executables built with
+`packed_resources_trailer` enabled in their `pyembed::PythonConfig` load
+resources from this trailer at run-time. This allows Python resources to be
+updated without rebuilding the executable.
+
+The executable is modified in place. Modifying an executable invalidates
+code signatures.
+";
+
 const RUN_BUILD_SCRIPT_ABOUT: &str = "\
 Runs a crate build script to generate Python artifacts.
 
@@ -227,6 +244,41 @@ pub fn run_cli() -> Result<()> {
                 )
                 .arg(Arg::with_name("extra").multiple(true)),
         )
+        .subcommand(
+            SubCommand::with_name("packed-resources-dump")
+                .about("Print a textual representation of packed resources data")
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(&["json", "toml"])
+                        .default_value("json")
+                        .help("Output format"),
+                )
+                .arg(
+                    Arg::with_name("path")
+                        .required(true)
+                        .value_name("PATH")
+                        .help("Path to file containing packed resources data"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("packed-resources-trailer")
+                .about("Append packed resources data to an executable")
+                .long_about(PACKED_RESOURCES_TRAILER_ABOUT)
+                .arg(
+                    Arg::with_name("exe_path")
+                        .required(true)
+                        .value_name("EXE_PATH")
+                        .help("Path to executable to modify"),
+                )
+                .arg(
+                    Arg::with_name("resources_path")
+                        .required(true)
+                        .value_name("RESOURCES_PATH")
+                        .help("Path to file containing packed resources data"),
+                ),
+        )
         .subcommand(
             SubCommand::with_name("python-distribution-extract")
                 .about("Extract a Python distribution archive to a directory")
@@ -336,6 +388,20 @@ pub fn run_cli() -> Result<()> {
             projectmgmt::init_rust_project(&project_path)
         }
 
+        ("packed-resources-dump", Some(args)) => {
+            let path = args.value_of("path").unwrap();
+            let format = args.value_of("format").unwrap();
+
+            projectmgmt::packed_resources_dump(path, format)
+        }
+
+        ("packed-resources-trailer", Some(args)) => {
+            let exe_path = args.value_of("exe_path").unwrap();
+            let resources_path = args.value_of("resources_path").unwrap();
+
+            projectmgmt::packed_resources_trailer(exe_path, resources_path)
+        }
+
         ("python-distribution-extract", Some(args)) => {
             let dist_path = args.value_of("dist_path").unwrap();
             let dest_path = args.value_of("dest_path").unwrap();
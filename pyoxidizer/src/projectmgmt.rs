@@ -10,6 +10,8 @@ use {
     crate::py_packaging::standalone_distribution::StandaloneDistribution,
     crate::starlark::eval::{eval_starlark_config_file, EvalResult},
     anyhow::{anyhow, Result},
+    python_packed_resources::serialization::SerializedResources,
+    python_packed_resources::trailer::replace_trailer,
     std::fs::create_dir_all,
     std::io::{Cursor, Read},
     std::path::Path,
@@ -293,6 +295,56 @@ pub fn python_distribution_info(dist_path: &str) -> Result<()> {
     Ok(())
 }
 
+pub fn packed_resources_dump(path: &str, format: &str) -> Result<()> {
+    let data = std::fs::read(Path::new(path))?;
+
+    let resources = SerializedResources::from_packed_resources(&data)?;
+
+    match format {
+        "json" => println!("{}", resources.to_json()?),
+        "toml" => println!("{}", resources.to_toml()?),
+        _ => return Err(anyhow!("unknown output format: {}", format)),
+    }
+
+    Ok(())
+}
+
+pub fn packed_resources_trailer(exe_path: &str, resources_path: &str) -> Result<()> {
+    let exe_path = Path::new(exe_path);
+
+    let exe = std::fs::read(exe_path)?;
+    let resources = std::fs::read(Path::new(resources_path))?;
+
+    let data = replace_trailer(&exe, &resources)?;
+
+    // Running instances of the executable memory map it and the executable
+    // can't be opened for writing while it runs on some platforms. So write
+    // a new file with the same permissions and replace the executable with it.
+    let file_name = exe_path
+        .file_name()
+        .ok_or_else(|| anyhow!("{} is not a file", exe_path.display()))?;
+    let temp_path = exe_path.with_file_name(format!(
+        ".{}.{}.tmp",
+        file_name.to_string_lossy(),
+        uuid::Uuid::new_v4()
+    ));
+
+    let permissions = std::fs::metadata(exe_path)?.permissions();
+    std::fs::write(&temp_path, data)?;
+
+    let res = std::fs::set_permissions(&temp_path, permissions)
+        .and_then(|_| std::fs::rename(&temp_path, exe_path));
+
+    if let Err(e) = res {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(e.into());
+    }
+
+    println!("wrote packed resources trailer to {}", exe_path.display());
+
+    Ok(())
+}
+
 pub fn python_distribution_licenses(path: &str) -> Result<()> {
     let fh = std::fs::File::open(Path::new(path))?;
     let reader = std::io::BufReader::new(fh);
@@ -230,6 +230,13 @@ pub trait PythonBinaryBuilder {
         glob_patterns: &[&str],
     ) -> Result<()>;
 
+    /// Order embedded resources by names in files.
+    ///
+    /// `files` is files to read names from, such as loaded modules files
+    /// listing modules in load order. Resources are written in the order of
+    /// their names in these files, followed by other resources.
+    fn order_resources_from_files(&mut self, files: &[&Path]) -> Result<()>;
+
     /// Whether the binary requires the jemalloc library.
     fn requires_jemalloc(&self) -> bool;
 
@@ -53,6 +53,7 @@ pub struct EmbeddedPythonConfig {
     pub legacy_windows_fs_encoding: bool,
     pub legacy_windows_stdio: bool,
     pub optimize_level: i64,
+    pub packed_resources_trailer: bool,
     pub parser_debug: bool,
     pub stdio_encoding_name: Option<String>,
     pub stdio_encoding_errors: Option<String>,
@@ -71,6 +72,12 @@ pub struct EmbeddedPythonConfig {
     pub verbose: i32,
     pub write_bytecode: bool,
     pub write_modules_directory_env: Option<String>,
+    pub write_modules_load_order: bool,
+    pub write_import_trace_directory_env: Option<String>,
+    pub command_server_socket_env: Option<String>,
+    pub command_server_preload_modules: Vec<String>,
+    pub write_profile_directory_env: Option<String>,
+    pub profile_sampling_interval_ms: u64,
 }
 
 impl Default for EmbeddedPythonConfig {
@@ -84,6 +91,7 @@ impl Default for EmbeddedPythonConfig {
             legacy_windows_fs_encoding: false,
             legacy_windows_stdio: false,
             optimize_level: 0,
+            packed_resources_trailer: false,
             parser_debug: false,
             quiet: false,
             stdio_encoding_name: None,
@@ -102,6 +110,12 @@ impl Default for EmbeddedPythonConfig {
             user_site_directory: false,
             write_bytecode: false,
             write_modules_directory_env: None,
+            write_modules_load_order: false,
+            write_import_trace_directory_env: None,
+            command_server_socket_env: None,
+            command_server_preload_modules: Vec::new(),
+            write_profile_directory_env: None,
+            profile_sampling_interval_ms: 10,
         }
     }
 }
@@ -79,6 +79,12 @@ impl<'a> TryFrom<&EmbeddedResourcePythonModulePrePackaged> for EmbeddedResource<
             name: Cow::Owned(value.name.clone()),
             is_package: value.is_package,
             is_namespace_package: value.is_namespace_package,
+            in_memory_compression: None,
+            in_memory_decompressed_lengths: None,
+            content_digest: None,
+            // Modules never carry data files.
+            in_memory_data_file: None,
+            relative_path_data_file: None,
             in_memory_source: if let Some(location) = &value.in_memory_source {
                 Some(Cow::Owned(location.resolve()?))
             } else {
@@ -949,10 +955,17 @@ impl EmbeddedPythonResourcesPrePackaged {
     ///
     /// This method performs actions necessary to produce entities which will allow the
     /// resources to be embedded in a binary.
+    ///
+    /// `load_order` holds names of modules in the order they are loaded at
+    /// run-time, such as recorded by `pyembed` in a loaded modules file.
+    /// Resources are written in this order, followed by other resources in
+    /// name order. This allows resources data to be read linearly when
+    /// importing modules. Names not corresponding to resources are ignored.
     pub fn package(
         &self,
         logger: &slog::Logger,
         python_exe: &Path,
+        load_order: Option<&[String]>,
     ) -> Result<EmbeddedPythonResources> {
         let mut file_seen = false;
         for module in self.find_dunder_file()? {
@@ -1112,8 +1125,27 @@ impl EmbeddedPythonResourcesPrePackaged {
             }
         }
 
+        let resources_order = match load_order {
+            Some(names) => {
+                let ordered = names
+                    .iter()
+                    .filter(|name| modules.contains_key(*name))
+                    .count();
+                info!(
+                    logger,
+                    "ordering {} of {} resources by load order",
+                    ordered,
+                    modules.len()
+                );
+
+                names.to_vec()
+            }
+            None => Vec::new(),
+        };
+
         Ok(EmbeddedPythonResources {
             resources: modules,
+            resources_order,
             extra_files,
             extension_modules: self.extension_module_states.clone(),
         })
@@ -1225,6 +1257,9 @@ pub struct EmbeddedPythonResources<'a> {
     /// Resources to write to a packed resources data structure.
     resources: BTreeMap<String, EmbeddedResource<'a, u8>>,
 
+    /// Names of resources to write before other resources, in order.
+    resources_order: Vec<String>,
+
     /// Additional files that need to be written out next to the produced binary.
     extra_files: FileManifest,
 
@@ -1242,15 +1277,33 @@ impl<'a> EmbeddedPythonResources<'a> {
             module_names.write_all(b"\n").expect("failed to write");
         }
 
-        write_embedded_resources_v1(
-            &self
-                .resources
-                .values()
-                .cloned()
-                .collect::<Vec<EmbeddedResource<'a, u8>>>(),
-            resources,
-            None,
-        )
+        write_embedded_resources_v1(&self.ordered_resources(), resources, None)
+    }
+
+    /// Obtain resources in the order they should be written.
+    ///
+    /// Resources named by `resources_order` come first, in that order. Other
+    /// resources follow in name order.
+    fn ordered_resources(&self) -> Vec<EmbeddedResource<'a, u8>> {
+        let mut seen = BTreeSet::new();
+        let mut res = Vec::with_capacity(self.resources.len());
+
+        for name in &self.resources_order {
+            if let Some(resource) = self.resources.get(name) {
+                if seen.insert(name.as_str()) {
+                    res.push(resource.clone());
+                }
+            }
+        }
+
+        res.extend(
+            self.resources
+                .iter()
+                .filter(|(name, _)| !seen.contains(name.as_str()))
+                .map(|(_, resource)| resource.clone()),
+        );
+
+        res
     }
 
     /// Obtain a list of built-in extensions.
@@ -1687,6 +1740,49 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_write_blobs_load_order() -> Result<()> {
+        let mut resources = BTreeMap::new();
+        for name in &["a", "b", "c", "d"] {
+            resources.insert(
+                name.to_string(),
+                EmbeddedResource {
+                    flavor: ResourceFlavor::Module,
+                    name: Cow::Owned(name.to_string()),
+                    in_memory_source: Some(Cow::Owned(name.as_bytes().to_vec())),
+                    ..EmbeddedResource::default()
+                },
+            );
+        }
+
+        let r = EmbeddedPythonResources {
+            resources,
+            resources_order: vec![
+                "c".to_string(),
+                "missing".to_string(),
+                "a".to_string(),
+                "c".to_string(),
+            ],
+            ..EmbeddedPythonResources::default()
+        };
+
+        let mut module_names = Vec::new();
+        let mut data = Vec::new();
+        r.write_blobs(&mut module_names, &mut data)?;
+
+        // Module names are always sorted.
+        assert_eq!(module_names, b"a\nb\nc\nd\n");
+
+        let names = python_packed_resources::parser::load_resources(&data)
+            .map_err(|e| anyhow!("{}", e))?
+            .map(|resource| resource.map(|resource| resource.name.to_string()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| anyhow!("{}", e))?;
+        assert_eq!(names, vec!["c", "a", "b", "d"]);
+
+        Ok(())
+    }
+
     #[test]
     fn test_find_dunder_file() -> Result<()> {
         let mut r = EmbeddedPythonResourcesPrePackaged::new(&PythonResourcesPolicy::InMemoryOnly);
@@ -11,12 +11,13 @@ use slog::warn;
 use std::collections::{BTreeMap, BTreeSet};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
+use std::iter::FromIterator;
 use std::path::Path;
 
-pub fn read_resource_names_file(path: &Path) -> Result<BTreeSet<String>> {
+fn read_resource_names_lines(path: &Path) -> Result<Vec<String>> {
     let fh = File::open(path)?;
 
-    let mut res: BTreeSet<String> = BTreeSet::new();
+    let mut res = Vec::new();
 
     for line in BufReader::new(fh).lines() {
         let line = line?;
@@ -25,7 +26,31 @@ pub fn read_resource_names_file(path: &Path) -> Result<BTreeSet<String>> {
             continue;
         }
 
-        res.insert(line);
+        res.push(line);
+    }
+
+    Ok(res)
+}
+
+pub fn read_resource_names_file(path: &Path) -> Result<BTreeSet<String>> {
+    Ok(BTreeSet::from_iter(read_resource_names_lines(path)?))
+}
+
+/// Read resource names from files, preserving their order.
+///
+/// Names are returned in the order they are first encountered, files being
+/// read in order. This is suitable for reading loaded modules files listing
+/// modules in load order.
+pub fn read_resource_names_files_ordered(files: &[&Path]) -> Result<Vec<String>> {
+    let mut seen = BTreeSet::new();
+    let mut res = Vec::new();
+
+    for path in files {
+        for name in read_resource_names_lines(path)? {
+            if seen.insert(name.clone()) {
+                res.push(name);
+            }
+        }
     }
 
     Ok(res)
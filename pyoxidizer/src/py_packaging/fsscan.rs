@@ -948,6 +948,70 @@ mod tests {
         Ok(())
     }
 
+    /// Resource files in nested sub-directories resolve to the leaf-most package.
+    #[test]
+    fn test_nested_subdirectory_resource() -> Result<()> {
+        let td = tempdir::TempDir::new("pyoxidizer-test")?;
+        let tp = td.path();
+
+        let package_dir = tp.join("foo");
+        let subpackage_dir = package_dir.join("bar");
+        let templates_dir = package_dir.join("templates").join("html");
+        let locale_dir = subpackage_dir.join("locale").join("de");
+        create_dir_all(&templates_dir)?;
+        create_dir_all(&locale_dir)?;
+
+        let module_path = package_dir.join("__init__.py");
+        write(&module_path, "")?;
+        let submodule_path = subpackage_dir.join("__init__.py");
+        write(&submodule_path, "")?;
+        let template_path = templates_dir.join("base.html");
+        write(&template_path, "content")?;
+        let locale_path = locale_dir.join("messages.txt");
+        write(&locale_path, "content")?;
+
+        let resources =
+            PythonResourceIterator::new(tp, &EMPTY_SUFFIXES).collect::<Result<Vec<_>>>()?;
+
+        assert_eq!(resources.len(), 4);
+        assert_eq!(
+            resources[0],
+            PythonResource::ModuleSource(PythonModuleSource {
+                name: "foo".to_string(),
+                source: DataLocation::Path(module_path),
+                is_package: true,
+            }),
+        );
+        assert_eq!(
+            resources[1],
+            PythonResource::ModuleSource(PythonModuleSource {
+                name: "foo.bar".to_string(),
+                source: DataLocation::Path(submodule_path),
+                is_package: true,
+            }),
+        );
+        assert_eq!(
+            resources[2],
+            PythonResource::Resource(PythonPackageResource {
+                full_name: "foo/bar/locale/de/messages.txt".to_string(),
+                leaf_package: "foo.bar".to_string(),
+                relative_name: "locale/de/messages.txt".to_string(),
+                data: DataLocation::Path(locale_path),
+            })
+        );
+        assert_eq!(
+            resources[3],
+            PythonResource::Resource(PythonPackageResource {
+                full_name: "foo/templates/html/base.html".to_string(),
+                leaf_package: "foo".to_string(),
+                relative_name: "templates/html/base.html".to_string(),
+                data: DataLocation::Path(template_path),
+            })
+        );
+
+        Ok(())
+    }
+
     /// .dist-info directory ignored if METADATA file not present.
     #[test]
     fn test_distinfo_missing_metadata() -> Result<()> {
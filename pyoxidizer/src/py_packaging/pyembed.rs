@@ -46,7 +46,14 @@ pub fn derive_python_config(
          verbose: {},\n    \
          frozen_importlib_bytecode: include_bytes!(r#\"{}\"#),\n    \
          frozen_importlib_external_bytecode: include_bytes!(r#\"{}\"#),\n    \
-         packed_resources: include_bytes!(r#\"{}\"#),\n    \
+         packed_resources: vec![include_bytes!(r#\"{}\"#) as &[u8]],\n    \
+         packed_resources_trailer: {},\n    \
+         packed_resources_paths: vec![],\n    \
+         verify_resources_digests: false,\n    \
+         resource_path_extraction: false,\n    \
+         lazy_module_loading: false,\n    \
+         lazy_modules_allow: vec![],\n    \
+         lazy_modules_deny: vec![],\n    \
          extra_extension_modules: vec![],\n    \
          argvb: false,\n    \
          sys_frozen: {},\n    \
@@ -54,6 +61,12 @@ pub fn derive_python_config(
          raw_allocator: {},\n    \
          terminfo_resolution: {},\n    \
          write_modules_directory_env: {},\n    \
+         write_modules_load_order: {},\n    \
+         write_import_trace_directory_env: {},\n    \
+         command_server_socket_env: {},\n    \
+         command_server_preload_modules: [{}].to_vec(),\n    \
+         write_profile_directory_env: {},\n    \
+         profile_sampling_interval_ms: {},\n    \
          run: {},\n\
          }}",
         match &embedded.stdio_encoding_name {
@@ -90,6 +103,7 @@ pub fn derive_python_config(
         importlib_bootstrap_path.display(),
         importlib_bootstrap_external_path.display(),
         embedded_resources_path.display(),
+        embedded.packed_resources_trailer,
         embedded.sys_frozen,
         embedded.sys_meipass,
         match embedded.raw_allocator {
@@ -108,6 +122,26 @@ pub fn derive_python_config(
             Some(path) => "Some(\"".to_owned() + &path + "\".to_string())",
             _ => "None".to_owned(),
         },
+        embedded.write_modules_load_order,
+        match &embedded.write_import_trace_directory_env {
+            Some(path) => "Some(\"".to_owned() + &path + "\".to_string())",
+            _ => "None".to_owned(),
+        },
+        match &embedded.command_server_socket_env {
+            Some(key) => "Some(\"".to_owned() + &key + "\".to_string())",
+            _ => "None".to_owned(),
+        },
+        &embedded
+            .command_server_preload_modules
+            .iter()
+            .map(|m| "\"".to_owned() + m + "\".to_string()")
+            .collect::<Vec<String>>()
+            .join(", "),
+        match &embedded.write_profile_directory_env {
+            Some(path) => "Some(\"".to_owned() + &path + "\".to_string())",
+            _ => "None".to_owned(),
+        },
+        embedded.profile_sampling_interval_ms,
         match embedded.run_mode {
             RunMode::Noop => "pyembed::PythonRunMode::None".to_owned(),
             RunMode::Repl => "pyembed::PythonRunMode::Repl".to_owned(),
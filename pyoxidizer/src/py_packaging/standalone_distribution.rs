@@ -17,6 +17,7 @@ use {
     },
     super::distutils::prepare_hacked_distutils,
     super::embedded_resource::{EmbeddedPythonResources, EmbeddedPythonResourcesPrePackaged},
+    super::filtering::read_resource_names_files_ordered,
     super::fsscan::{find_python_resources, is_package_from_path, walk_tree_files},
     super::libpython::{derive_importlib, link_libpython, ImportlibBytecode},
     super::resource::{
@@ -996,6 +997,7 @@ impl PythonDistribution for StandaloneDistribution {
             distribution: self.clone(),
             resources_policy: resources_policy.clone(),
             resources: EmbeddedPythonResourcesPrePackaged::new(resources_policy),
+            resources_load_order: None,
             config: config.clone(),
             python_exe,
             importlib_bytecode,
@@ -1270,6 +1272,9 @@ pub struct StandalonePythonExecutableBuilder {
     /// Python resources to be embedded in the binary.
     resources: EmbeddedPythonResourcesPrePackaged,
 
+    /// Names of resources in the order they should be written.
+    resources_load_order: Option<Vec<String>>,
+
     /// Configuration of the embedded Python interpreter.
     config: EmbeddedPythonConfig,
 
@@ -1725,6 +1730,12 @@ impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
             .filter_from_files(logger, files, glob_patterns)
     }
 
+    fn order_resources_from_files(&mut self, files: &[&Path]) -> Result<()> {
+        self.resources_load_order = Some(read_resource_names_files_ordered(files)?);
+
+        Ok(())
+    }
+
     fn requires_jemalloc(&self) -> bool {
         self.config.raw_allocator == RawAllocator::Jemalloc
     }
@@ -1734,7 +1745,11 @@ impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
         logger: &slog::Logger,
         opt_level: &str,
     ) -> Result<EmbeddedPythonBinaryData> {
-        let resources = self.resources.package(logger, &self.python_exe)?;
+        let resources = self.resources.package(
+            logger,
+            &self.python_exe,
+            self.resources_load_order.as_deref(),
+        )?;
         let mut extra_files = resources.extra_install_files()?;
         let linking_info = self.resolve_python_linking_info(logger, opt_level, &resources)?;
         let resources = EmbeddedResourcesBlobs::try_from(resources)?;
@@ -14,6 +14,7 @@ use {
         PythonModuleSuffixes, IMPORTLIB_BOOTSTRAP_EXTERNAL_PY_37, IMPORTLIB_BOOTSTRAP_PY_37,
     },
     super::embedded_resource::EmbeddedPythonResourcesPrePackaged,
+    super::filtering::read_resource_names_files_ordered,
     super::libpython::{derive_importlib, ImportlibBytecode},
     super::packaging_tool::bootstrap_packaging_tools,
     super::resource::{
@@ -294,6 +295,7 @@ impl PythonDistribution for WindowsEmbeddableDistribution {
             python_dll: self.python_dll.clone(),
             // TODO add distribution resources to this instance.
             resources: EmbeddedPythonResourcesPrePackaged::new(resources_policy),
+            resources_load_order: None,
             config: config.clone(),
             importlib_bytecode: self.resolve_importlib_bytecode()?,
         }))
@@ -465,6 +467,9 @@ pub struct WindowsEmbeddedablePythonExecutableBuilder {
     /// Python resources to be embedded in the binary.
     resources: EmbeddedPythonResourcesPrePackaged,
 
+    /// Names of resources in the order they should be written.
+    resources_load_order: Option<Vec<String>>,
+
     /// Configuration for embedded Python interpreter.
     config: EmbeddedPythonConfig,
 
@@ -686,6 +691,12 @@ impl PythonBinaryBuilder for WindowsEmbeddedablePythonExecutableBuilder {
             .filter_from_files(logger, files, glob_patterns)
     }
 
+    fn order_resources_from_files(&mut self, files: &[&Path]) -> Result<()> {
+        self.resources_load_order = Some(read_resource_names_files_ordered(files)?);
+
+        Ok(())
+    }
+
     fn requires_jemalloc(&self) -> bool {
         // jemalloc not supported on Windows.
         false
@@ -698,7 +709,11 @@ impl PythonBinaryBuilder for WindowsEmbeddedablePythonExecutableBuilder {
     ) -> Result<EmbeddedPythonBinaryData> {
         let resources = self
             .resources
-            .package(logger, &self.python_exe)?
+            .package(
+                logger,
+                &self.python_exe,
+                self.resources_load_order.as_deref(),
+            )?
             .try_into()?;
 
         let extra_files = FileManifest::default();
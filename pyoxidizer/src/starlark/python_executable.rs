@@ -922,6 +922,35 @@ impl PythonExecutable {
 
         Ok(Value::new(None))
     }
+
+    /// PythonExecutable.order_resources_from_files(files=None)
+    pub fn starlark_order_resources_from_files(&mut self, files: &Value) -> ValueResult {
+        optional_list_arg("files", "string", &files)?;
+
+        let files = match files.get_type() {
+            "list" => files
+                .into_iter()?
+                .map(|x| PathBuf::from(x.to_string()))
+                .collect(),
+            "NoneType" => Vec::new(),
+            _ => panic!("type should have been validated above"),
+        };
+
+        let files_refs = files.iter().map(|x| x.as_ref()).collect::<Vec<&Path>>();
+
+        self.exe
+            .order_resources_from_files(&files_refs)
+            .or_else(|e| {
+                Err(RuntimeError {
+                    code: "RUNTIME_ERROR",
+                    message: e.to_string(),
+                    label: "order_resources_from_files()".to_string(),
+                }
+                .into())
+            })?;
+
+        Ok(Value::new(None))
+    }
 }
 
 starlark_module! { python_executable_env =>
@@ -1170,6 +1199,13 @@ starlark_module! { python_executable_env =>
         })
     }
 
+    #[allow(clippy::ptr_arg)]
+    PythonExecutable.order_resources_from_files(this, files=None) {
+        this.downcast_apply_mut(|exe: &mut PythonExecutable| {
+            exe.starlark_order_resources_from_files(&files)
+        })
+    }
+
     #[allow(clippy::ptr_arg)]
     PythonExecutable.to_embedded_resources(this) {
         this.downcast_apply(|exe: &PythonExecutable| {
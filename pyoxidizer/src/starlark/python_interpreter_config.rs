@@ -88,6 +88,13 @@ impl EmbeddedPythonConfig {
         verbose: &Value,
         write_bytecode: &Value,
         write_modules_directory_env: &Value,
+        write_modules_load_order: &Value,
+        write_import_trace_directory_env: &Value,
+        command_server_socket_env: &Value,
+        command_server_preload_modules: &Value,
+        write_profile_directory_env: &Value,
+        profile_sampling_interval_ms: &Value,
+        packed_resources_trailer: &Value,
     ) -> ValueResult {
         required_type_arg("bytes_warning", "int", &bytes_warning)?;
         let ignore_environment = required_bool_arg("ignore_environment", &ignore_environment)?;
@@ -122,6 +129,28 @@ impl EmbeddedPythonConfig {
         let write_bytecode = required_bool_arg("write_bytecode", &write_bytecode)?;
         let write_modules_directory_env =
             optional_str_arg("write_modules_directory_env", &write_modules_directory_env)?;
+        let write_modules_load_order =
+            required_bool_arg("write_modules_load_order", &write_modules_load_order)?;
+        let write_import_trace_directory_env = optional_str_arg(
+            "write_import_trace_directory_env",
+            &write_import_trace_directory_env,
+        )?;
+        let command_server_socket_env =
+            optional_str_arg("command_server_socket_env", &command_server_socket_env)?;
+        optional_list_arg(
+            "command_server_preload_modules",
+            "string",
+            &command_server_preload_modules,
+        )?;
+        let write_profile_directory_env =
+            optional_str_arg("write_profile_directory_env", &write_profile_directory_env)?;
+        required_type_arg(
+            "profile_sampling_interval_ms",
+            "int",
+            &profile_sampling_interval_ms,
+        )?;
+        let packed_resources_trailer =
+            required_bool_arg("packed_resources_trailer", &packed_resources_trailer)?;
 
         let build_target = env.get("BUILD_TARGET_TRIPLE").unwrap().to_str();
 
@@ -224,6 +253,25 @@ impl EmbeddedPythonConfig {
 
         let filesystem_importer = filesystem_importer || !sys_paths.is_empty();
 
+        let profile_sampling_interval_ms = profile_sampling_interval_ms.to_int().unwrap();
+        if profile_sampling_interval_ms <= 0 {
+            return Err(RuntimeError {
+                code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                message: "profile_sampling_interval_ms must be positive".to_string(),
+                label: "profile_sampling_interval_ms must be positive".to_string(),
+            }
+            .into());
+        }
+
+        let command_server_preload_modules = match command_server_preload_modules.get_type() {
+            "list" => command_server_preload_modules
+                .into_iter()
+                .unwrap()
+                .map(|x| x.to_string())
+                .collect(),
+            _ => Vec::new(),
+        };
+
         Ok(Value::new(EmbeddedPythonConfig {
             bytes_warning: bytes_warning.to_int().unwrap() as i32,
             ignore_environment,
@@ -233,6 +281,7 @@ impl EmbeddedPythonConfig {
             legacy_windows_fs_encoding,
             legacy_windows_stdio,
             optimize_level: optimize_level.to_int().unwrap(),
+            packed_resources_trailer,
             parser_debug,
             quiet,
             stdio_encoding_name,
@@ -251,6 +300,12 @@ impl EmbeddedPythonConfig {
             verbose: verbose.to_int().unwrap() as i32,
             write_bytecode,
             write_modules_directory_env,
+            write_modules_load_order,
+            write_import_trace_directory_env,
+            command_server_socket_env,
+            command_server_preload_modules,
+            write_profile_directory_env,
+            profile_sampling_interval_ms: profile_sampling_interval_ms as u64,
         }))
     }
 }
@@ -288,7 +343,14 @@ starlark_module! { embedded_python_config_module =>
         user_site_directory=false,
         verbose=0,
         write_bytecode=false,
-        write_modules_directory_env=None
+        write_modules_directory_env=None,
+        write_modules_load_order=false,
+        write_import_trace_directory_env=None,
+        command_server_socket_env=None,
+        command_server_preload_modules=None,
+        write_profile_directory_env=None,
+        profile_sampling_interval_ms=10,
+        packed_resources_trailer=false
     ) {
         EmbeddedPythonConfig::starlark_new(
             &env,
@@ -321,7 +383,14 @@ starlark_module! { embedded_python_config_module =>
             &user_site_directory,
             &verbose,
             &write_bytecode,
-            &write_modules_directory_env
+            &write_modules_directory_env,
+            &write_modules_load_order,
+            &write_import_trace_directory_env,
+            &command_server_socket_env,
+            &command_server_preload_modules,
+            &write_profile_directory_env,
+            &profile_sampling_interval_ms,
+            &packed_resources_trailer
         )
     }
 }
@@ -344,6 +413,7 @@ mod tests {
             legacy_windows_fs_encoding: false,
             legacy_windows_stdio: false,
             optimize_level: 0,
+            packed_resources_trailer: false,
             parser_debug: false,
             quiet: false,
             use_hash_seed: false,
@@ -362,6 +432,12 @@ mod tests {
             user_site_directory: false,
             write_bytecode: false,
             write_modules_directory_env: None,
+            write_modules_load_order: false,
+            write_import_trace_directory_env: None,
+            command_server_socket_env: None,
+            command_server_preload_modules: Vec::new(),
+            write_profile_directory_env: None,
+            profile_sampling_interval_ms: 10,
         };
 
         c.downcast_apply(|x: &EmbeddedPythonConfig| assert_eq!(x, &wanted));
@@ -379,6 +455,12 @@ mod tests {
         c.downcast_apply(|x: &EmbeddedPythonConfig| assert_eq!(x.optimize_level, 1));
     }
 
+    #[test]
+    fn test_packed_resources_trailer() {
+        let c = starlark_ok("PythonInterpreterConfig(packed_resources_trailer=True)");
+        c.downcast_apply(|x: &EmbeddedPythonConfig| assert!(x.packed_resources_trailer));
+    }
+
     #[test]
     fn test_sys_paths() {
         let c = starlark_ok("PythonInterpreterConfig(sys_paths=['foo', 'bar'])");
@@ -389,6 +471,36 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_command_server() {
+        let c = starlark_ok(
+            "PythonInterpreterConfig(command_server_socket_env='APP_SOCKET', command_server_preload_modules=['app'])",
+        );
+        c.downcast_apply(|x: &EmbeddedPythonConfig| {
+            assert_eq!(x.command_server_socket_env, Some("APP_SOCKET".to_string()));
+            assert_eq!(x.command_server_preload_modules, ["app"]);
+        });
+    }
+
+    #[test]
+    fn test_profile() {
+        let c = starlark_ok(
+            "PythonInterpreterConfig(write_profile_directory_env='APP_PROFILE', profile_sampling_interval_ms=5)",
+        );
+        c.downcast_apply(|x: &EmbeddedPythonConfig| {
+            assert_eq!(
+                x.write_profile_directory_env,
+                Some("APP_PROFILE".to_string())
+            );
+            assert_eq!(x.profile_sampling_interval_ms, 5);
+        });
+
+        let err = starlark_nok("PythonInterpreterConfig(profile_sampling_interval_ms=0)");
+        assert!(err
+            .message
+            .starts_with("profile_sampling_interval_ms must be positive"));
+    }
+
     #[test]
     fn test_stdio_encoding() {
         let c = starlark_ok("PythonInterpreterConfig(stdio_encoding='foo:strict')");
@@ -16,6 +16,15 @@ fn main() {
         // file used at build time.
         let config = default_python_config();
 
+        // If the configuration enables a command server, forward the command to
+        // it instead of initializing a Python interpreter in this process.
+        #[cfg(unix)]
+        {
+            if let Some(code) = pyembed::run_command_client(&config) {
+                std::process::exit(code);
+            }
+        }
+
         // Construct a new Python interpreter using that config, handling any errors
         // from construction.
         match MainPythonInterpreter::new(config) {
@@ -39,6 +39,13 @@ def make_exe(dist):
     #     use_hash_seed=False,
     #     verbose=0,
     #     write_modules_directory_env=None,
+    #     write_modules_load_order=False,
+    #     write_import_trace_directory_env=None,
+    #     command_server_socket_env=None,
+    #     command_server_preload_modules=None,
+    #     write_profile_directory_env=None,
+    #     profile_sampling_interval_ms=10,
+    #     packed_resources_trailer=False,
     #     run_eval={{#if code}}(r"""{{{code}}}"""{{else}}None{{/if}},
     #     run_module=None,
     #     run_noop=False,
@@ -122,6 +129,10 @@ def make_exe(dist):
     # in a file.
     #exe.filter_from_files(files=["/path/to/filter-file"]))
 
+    # Store resources in the order they are loaded at run-time, as recorded
+    # in a file written by a binary with `write_modules_load_order=True`.
+    #exe.order_resources_from_files(files=["/path/to/modules-file"])
+
     # Return our `PythonExecutable` instance so it can be built and
     # referenced by other consumers of this target.
     return exe
@@ -0,0 +1,33 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Fuzz parsing of packed resources data.
+
+Parsing arbitrary data should only ever produce errors, never panics.
+*/
+
+#![no_main]
+use {libfuzzer_sys::fuzz_target, python_packed_resources::parser::load_resources};
+
+fuzz_target!(|data: &[u8]| {
+    let parser = match load_resources(data) {
+        Ok(parser) => parser,
+        Err(_) => return,
+    };
+
+    let _ = parser.verify_payload_digest();
+
+    let names = parser
+        .filter_map(|resource| resource.ok())
+        .map(|resource| resource.name.to_string())
+        .collect::<Vec<_>>();
+
+    if let Ok(indexed) = load_resources(data).and_then(|parser| parser.into_indexed()) {
+        for name in &names {
+            let _ = indexed.get(name);
+        }
+
+        let _ = indexed.get("missing");
+    }
+});
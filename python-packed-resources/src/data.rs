@@ -4,11 +4,56 @@
 
 /*! Declares the foundational data primitives inside packed resources data. */
 
-use {std::borrow::Cow, std::collections::HashMap, std::convert::TryFrom, std::path::Path};
+use {
+    sha2::{Digest, Sha256},
+    std::borrow::Cow,
+    std::collections::HashMap,
+    std::convert::TryFrom,
+    std::io::{Read, Write},
+    std::path::Path,
+};
 
 /// Header value for version 1 of resources payload.
 pub const HEADER_V1: &[u8] = b"pyembed\x01";
 
+/// Header value for version 2 of resources payload.
+pub const HEADER_V2: &[u8] = b"pyembed\x02";
+
+/// Length in bytes of digests stored in resources payloads.
+///
+/// Digests are SHA-256.
+pub const DIGEST_LENGTH: usize = 32;
+
+/// Compute the SHA-256 digest of data.
+pub fn sha256_digest(data: &[u8]) -> Vec<u8> {
+    Sha256::digest(data).to_vec()
+}
+
+/// Compute the number of bytes needed to align an offset to a boundary.
+pub fn alignment_padding(offset: usize, alignment: usize) -> usize {
+    (alignment - offset % alignment) % alignment
+}
+
+/// Validate the name of a Python package resource.
+///
+/// Package resource names are paths relative to their package. Directory
+/// components are separated by `/` on all platforms. Names can't be absolute
+/// or have empty, `.` or `..` components.
+pub fn validate_package_resource_name(name: &str) -> Result<(), &'static str> {
+    if name.contains('\\') {
+        return Err("package resource names must use / as directory separator");
+    }
+
+    if name
+        .split('/')
+        .any(|component| component.is_empty() || component == "." || component == "..")
+    {
+        return Err("package resource names must not have empty, . or .. components");
+    }
+
+    Ok(())
+}
+
 /// Defines the type of a resource.
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum ResourceFlavor {
@@ -18,6 +63,7 @@ pub enum ResourceFlavor {
     FrozenModule = 0x03,
     Extension = 0x04,
     SharedLibrary = 0x05,
+    DataFile = 0x06,
 }
 
 impl Into<u8> for ResourceFlavor {
@@ -29,6 +75,7 @@ impl Into<u8> for ResourceFlavor {
             ResourceFlavor::FrozenModule => 0x03,
             ResourceFlavor::Extension => 0x04,
             ResourceFlavor::SharedLibrary => 0x05,
+            ResourceFlavor::DataFile => 0x06,
         }
     }
 }
@@ -44,6 +91,7 @@ impl TryFrom<u8> for ResourceFlavor {
             0x03 => Ok(ResourceFlavor::FrozenModule),
             0x04 => Ok(ResourceFlavor::Extension),
             0x05 => Ok(ResourceFlavor::SharedLibrary),
+            0x06 => Ok(ResourceFlavor::DataFile),
             _ => Err("unrecognized resource flavor"),
         }
     }
@@ -72,6 +120,108 @@ impl Into<u8> for &BlobInteriorPadding {
     }
 }
 
+/// Defines a compression algorithm applied to blob data.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CompressionAlgorithm {
+    /// Zstandard compression.
+    ///
+    /// Data is a single zstd frame.
+    Zstd = 0x01,
+}
+
+impl From<CompressionAlgorithm> for u8 {
+    fn from(algorithm: CompressionAlgorithm) -> Self {
+        match algorithm {
+            CompressionAlgorithm::Zstd => 0x01,
+        }
+    }
+}
+
+impl TryFrom<u8> for CompressionAlgorithm {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x01 => Ok(CompressionAlgorithm::Zstd),
+            _ => Err("unrecognized compression algorithm"),
+        }
+    }
+}
+
+impl CompressionAlgorithm {
+    /// Compress data using this algorithm.
+    ///
+    /// `level` is the algorithm specific compression level.
+    pub fn compress(self, data: &[u8], level: i32) -> Result<Vec<u8>, &'static str> {
+        match self {
+            CompressionAlgorithm::Zstd => {
+                zstd::block::compress(data, level).map_err(|_| "zstd compression failed")
+            }
+        }
+    }
+
+    /// Compress data read from a reader into a writer using this algorithm.
+    ///
+    /// Unlike `compress()`, data is processed incrementally. The
+    /// decompressed length is not recorded in the compressed data, so
+    /// `decompressed_length()` can't be used on the output.
+    pub fn compress_stream<R: Read, W: Write>(
+        self,
+        source: &mut R,
+        dest: &mut W,
+        level: i32,
+    ) -> Result<(), &'static str> {
+        match self {
+            CompressionAlgorithm::Zstd => zstd::stream::copy_encode(source, dest, level)
+                .or_else(|_| Err("zstd compression failed")),
+        }
+    }
+
+    /// Decompress data that was compressed with this algorithm.
+    ///
+    /// `decompressed_length` is the length of the original data. Decompression
+    /// fails if the decompressed data has a different length.
+    pub fn decompress(
+        self,
+        data: &[u8],
+        decompressed_length: usize,
+    ) -> Result<Vec<u8>, &'static str> {
+        // Decompress incrementally rather than allocating the declared length
+        // upfront, as the length may come from untrusted data. Reading one byte
+        // past the declared length detects data that is too long.
+        let mut res = Vec::new();
+
+        match self {
+            CompressionAlgorithm::Zstd => {
+                zstd::stream::read::Decoder::with_buffer(data)
+                    .or_else(|_| Err("zstd decompression failed"))?
+                    .take((decompressed_length as u64).saturating_add(1))
+                    .read_to_end(&mut res)
+                    .or_else(|_| Err("zstd decompression failed"))?;
+            }
+        }
+
+        if res.len() != decompressed_length {
+            return Err("decompressed data length mismatch");
+        }
+
+        Ok(res)
+    }
+
+    /// Obtain the decompressed length of data compressed with this algorithm.
+    ///
+    /// The length is read from the header of the compressed data.
+    pub fn decompressed_length(self, data: &[u8]) -> Result<usize, &'static str> {
+        match self {
+            CompressionAlgorithm::Zstd => match zstd_safe::get_frame_content_size(data) {
+                zstd_safe::CONTENTSIZE_UNKNOWN => Err("zstd frame content size not known"),
+                zstd_safe::CONTENTSIZE_ERROR => Err("error reading zstd frame header"),
+                size => Ok(size as usize),
+            },
+        }
+    }
+}
+
 /// Describes a blob section field type in the blob index.
 #[derive(Debug, PartialEq, PartialOrd)]
 pub enum BlobSectionField {
@@ -81,6 +231,10 @@ pub enum BlobSectionField {
     ResourceFieldType = 0x03,
     RawPayloadLength = 0x04,
     InteriorPadding = 0x05,
+    Compression = 0x06,
+    DecompressedPayloadLength = 0x07,
+    Alignment = 0x08,
+    EntryAlignment = 0x09,
 }
 
 impl Into<u8> for BlobSectionField {
@@ -91,6 +245,10 @@ impl Into<u8> for BlobSectionField {
             BlobSectionField::ResourceFieldType => 0x02,
             BlobSectionField::RawPayloadLength => 0x03,
             BlobSectionField::InteriorPadding => 0x04,
+            BlobSectionField::Compression => 0x05,
+            BlobSectionField::DecompressedPayloadLength => 0x06,
+            BlobSectionField::Alignment => 0x07,
+            BlobSectionField::EntryAlignment => 0x08,
             BlobSectionField::EndOfEntry => 0xff,
         }
     }
@@ -106,6 +264,10 @@ impl TryFrom<u8> for BlobSectionField {
             0x02 => Ok(BlobSectionField::ResourceFieldType),
             0x03 => Ok(BlobSectionField::RawPayloadLength),
             0x04 => Ok(BlobSectionField::InteriorPadding),
+            0x05 => Ok(BlobSectionField::Compression),
+            0x06 => Ok(BlobSectionField::DecompressedPayloadLength),
+            0x07 => Ok(BlobSectionField::Alignment),
+            0x08 => Ok(BlobSectionField::EntryAlignment),
             0xff => Ok(BlobSectionField::EndOfEntry),
             _ => Err("invalid blob index field type"),
         }
@@ -113,7 +275,7 @@ impl TryFrom<u8> for BlobSectionField {
 }
 
 /// Describes a resource field type in the resource index.
-#[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Eq, Ord)]
+#[derive(Clone, Copy, Debug, Hash, PartialEq, PartialOrd, Eq, Ord)]
 pub enum ResourceField {
     EndOfIndex = 0x00,
     StartOfEntry = 0x01,
@@ -138,6 +300,10 @@ pub enum ResourceField {
     RelativeFilesystemExtensionModuleSharedLibrary = 0x13,
     RelativeFilesystemPackageResources = 0x14,
     RelativeFilesystemDistributionResource = 0x15,
+    InMemoryCompression = 0x16,
+    ContentDigest = 0x17,
+    InMemoryDataFile = 0x18,
+    RelativeFilesystemDataFile = 0x19,
 }
 
 impl Into<u8> for ResourceField {
@@ -165,6 +331,10 @@ impl Into<u8> for ResourceField {
             ResourceField::RelativeFilesystemExtensionModuleSharedLibrary => 0x13,
             ResourceField::RelativeFilesystemPackageResources => 0x14,
             ResourceField::RelativeFilesystemDistributionResource => 0x15,
+            ResourceField::InMemoryCompression => 0x16,
+            ResourceField::ContentDigest => 0x17,
+            ResourceField::InMemoryDataFile => 0x18,
+            ResourceField::RelativeFilesystemDataFile => 0x19,
             ResourceField::EndOfEntry => 0xff,
         }
     }
@@ -197,12 +367,22 @@ impl TryFrom<u8> for ResourceField {
             0x13 => Ok(ResourceField::RelativeFilesystemExtensionModuleSharedLibrary),
             0x14 => Ok(ResourceField::RelativeFilesystemPackageResources),
             0x15 => Ok(ResourceField::RelativeFilesystemDistributionResource),
+            0x16 => Ok(ResourceField::InMemoryCompression),
+            0x17 => Ok(ResourceField::ContentDigest),
+            0x18 => Ok(ResourceField::InMemoryDataFile),
+            0x19 => Ok(ResourceField::RelativeFilesystemDataFile),
             0xff => Ok(ResourceField::EndOfEntry),
             _ => Err("invalid field type"),
         }
     }
 }
 
+/// Decompressed lengths of a resource's compressed in-memory data.
+///
+/// Keyed by the field holding the data and, for package and distribution
+/// resources, the name of the resource.
+pub type InMemoryDataLengths<'a> = HashMap<(ResourceField, Option<Cow<'a, str>>), usize>;
+
 /// Represents an embedded resource and all its metadata.
 #[derive(Clone, Debug, PartialEq)]
 pub struct Resource<'a, X: 'a>
@@ -273,6 +453,32 @@ where
 
     /// Mapping of Python package distribution files to relative filesystem paths for those resources.
     pub relative_path_distribution_resources: Option<HashMap<Cow<'a, str>, Cow<'a, Path>>>,
+
+    /// Content of a data file to expose via in-memory data access.
+    ///
+    /// Data files are application data not belonging to any Python package.
+    /// Their resource name is a virtual path, such as `certs/ca.pem`.
+    pub in_memory_data_file: Option<Cow<'a, [X]>>,
+
+    /// Relative path to file containing the content of a data file.
+    pub relative_path_data_file: Option<Cow<'a, Path>>,
+
+    /// Compression applied to in-memory data.
+    ///
+    /// If set, in-memory module source, module bytecode, package resources,
+    /// distribution resources, and data files are each independently
+    /// compressed with this algorithm. Use `resolve_in_memory_data()` to obtain the original data.
+    pub in_memory_compression: Option<CompressionAlgorithm>,
+
+    /// Decompressed lengths of compressed in-memory data.
+    ///
+    /// Decompressing data whose output has a different length fails.
+    pub in_memory_decompressed_lengths: Option<InMemoryDataLengths<'a>>,
+
+    /// SHA-256 digest of the resource's content.
+    ///
+    /// See `compute_content_digest()` for what the digest covers.
+    pub content_digest: Option<Cow<'a, [X]>>,
 }
 
 impl<'a, X> Default for Resource<'a, X>
@@ -301,6 +507,426 @@ where
             relative_path_extension_module_shared_library: None,
             relative_path_package_resources: None,
             relative_path_distribution_resources: None,
+            in_memory_data_file: None,
+            relative_path_data_file: None,
+            in_memory_compression: None,
+            in_memory_decompressed_lengths: None,
+            content_digest: None,
+        }
+    }
+}
+
+impl<'a> Resource<'a, u8> {
+    /// Obtain a copy of this resource that owns all its data.
+    ///
+    /// The returned resource doesn't borrow from the data it was parsed
+    /// from, so it can outlive it.
+    pub fn to_owned_resource(&self) -> Resource<'static, u8> {
+        let data = |data: &Option<Cow<[u8]>>| data.as_ref().map(|d| Cow::Owned(d.to_vec()));
+        let path = |path: &Option<Cow<Path>>| path.as_ref().map(|p| Cow::Owned(p.to_path_buf()));
+        let data_map = |map: &Option<HashMap<Cow<str>, Cow<[u8]>>>| {
+            map.as_ref().map(|map| {
+                map.iter()
+                    .map(|(k, v)| (Cow::Owned(k.to_string()), Cow::Owned(v.to_vec())))
+                    .collect()
+            })
+        };
+        let path_map = |map: &Option<HashMap<Cow<str>, Cow<Path>>>| {
+            map.as_ref().map(|map| {
+                map.iter()
+                    .map(|(k, v)| (Cow::Owned(k.to_string()), Cow::Owned(v.to_path_buf())))
+                    .collect()
+            })
+        };
+
+        Resource {
+            flavor: self.flavor,
+            name: Cow::Owned(self.name.to_string()),
+            is_package: self.is_package,
+            is_namespace_package: self.is_namespace_package,
+            in_memory_source: data(&self.in_memory_source),
+            in_memory_bytecode: data(&self.in_memory_bytecode),
+            in_memory_bytecode_opt1: data(&self.in_memory_bytecode_opt1),
+            in_memory_bytecode_opt2: data(&self.in_memory_bytecode_opt2),
+            in_memory_extension_module_shared_library: data(
+                &self.in_memory_extension_module_shared_library,
+            ),
+            in_memory_package_resources: data_map(&self.in_memory_package_resources),
+            in_memory_distribution_resources: data_map(&self.in_memory_distribution_resources),
+            in_memory_shared_library: data(&self.in_memory_shared_library),
+            shared_library_dependency_names: self.shared_library_dependency_names.as_ref().map(
+                |names| {
+                    names
+                        .iter()
+                        .map(|name| Cow::Owned(name.to_string()))
+                        .collect()
+                },
+            ),
+            relative_path_module_source: path(&self.relative_path_module_source),
+            relative_path_module_bytecode: path(&self.relative_path_module_bytecode),
+            relative_path_module_bytecode_opt1: path(&self.relative_path_module_bytecode_opt1),
+            relative_path_module_bytecode_opt2: path(&self.relative_path_module_bytecode_opt2),
+            relative_path_extension_module_shared_library: path(
+                &self.relative_path_extension_module_shared_library,
+            ),
+            relative_path_package_resources: path_map(&self.relative_path_package_resources),
+            relative_path_distribution_resources: path_map(
+                &self.relative_path_distribution_resources,
+            ),
+            in_memory_data_file: data(&self.in_memory_data_file),
+            relative_path_data_file: path(&self.relative_path_data_file),
+            in_memory_compression: self.in_memory_compression,
+            in_memory_decompressed_lengths: self.in_memory_decompressed_lengths.as_ref().map(
+                |lengths| {
+                    lengths
+                        .iter()
+                        .map(|((field, name), length)| {
+                            (
+                                (*field, name.as_ref().map(|n| Cow::Owned(n.to_string()))),
+                                *length,
+                            )
+                        })
+                        .collect()
+                },
+            ),
+            content_digest: data(&self.content_digest),
+        }
+    }
+
+    /// Obtain the decompressed length of compressed in-memory data.
+    ///
+    /// `data` is the value of the compressible in-memory data field `field`
+    /// of this resource. `name` is the name of the package or distribution
+    /// resource holding `data`, if any. The stored decompressed length is
+    /// used if present. Otherwise it is read from the compressed data.
+    pub fn in_memory_decompressed_length(
+        &self,
+        field: ResourceField,
+        name: Option<&str>,
+        data: &[u8],
+    ) -> Result<usize, &'static str> {
+        let algorithm = self
+            .in_memory_compression
+            .ok_or("in-memory data is not compressed")?;
+
+        let stored = self
+            .in_memory_decompressed_lengths
+            .as_ref()
+            .and_then(|lengths| lengths.get(&(field, name.map(Cow::Borrowed))));
+
+        match stored {
+            Some(length) => Ok(*length),
+            None => algorithm.decompressed_length(data),
+        }
+    }
+
+    /// Resolve in-memory data belonging to this resource.
+    ///
+    /// `data` is the value of the compressible in-memory data field `field` of
+    /// this resource. `name` is the name of the package or distribution
+    /// resource holding `data`, if any. If in-memory data is compressed, it is
+    /// decompressed and an owned value is returned. Otherwise `data` is
+    /// returned as-is.
+    pub fn resolve_in_memory_data<'b>(
+        &self,
+        field: ResourceField,
+        name: Option<&str>,
+        data: &'b [u8],
+    ) -> Result<Cow<'b, [u8]>, &'static str> {
+        if let Some(algorithm) = self.in_memory_compression {
+            let length = self.in_memory_decompressed_length(field, name, data)?;
+
+            Ok(Cow::Owned(algorithm.decompress(data, length)?))
+        } else {
+            Ok(Cow::Borrowed(data))
+        }
+    }
+
+    /// Obtain a copy of this resource with in-memory data compressed.
+    ///
+    /// Resources whose in-memory data is already compressed are returned as-is.
+    pub fn compress_in_memory_data(
+        &self,
+        algorithm: CompressionAlgorithm,
+        level: i32,
+    ) -> Result<Resource<'a, u8>, &'static str> {
+        if self.in_memory_compression.is_some() {
+            return Ok(self.clone());
+        }
+
+        self.transform_in_memory_data(Some(algorithm), |_, _, data| {
+            algorithm.compress(data, level)
+        })
+    }
+
+    /// Obtain a copy of this resource with in-memory data decompressed.
+    pub fn decompress_in_memory_data(&self) -> Result<Resource<'a, u8>, &'static str> {
+        if self.in_memory_compression.is_none() {
+            return Ok(self.clone());
+        }
+
+        self.transform_in_memory_data(None, |field, name, data| {
+            Ok(self.resolve_in_memory_data(field, name, data)?.into_owned())
+        })
+    }
+
+    /// Compute the SHA-256 digest of this resource's content.
+    ///
+    /// The digest covers the flavor, name, package flags, and all in-memory
+    /// data of the resource. In-memory data is digested as stored, so the
+    /// digest of a resource with compressed in-memory data covers the
+    /// compressed data. Relative filesystem paths and the files they refer to
+    /// are not covered.
+    pub fn compute_content_digest(&self) -> Vec<u8> {
+        let mut hasher = Sha256::new();
+
+        // Every variable length value is prefixed by its length so values
+        // can't bleed into each other.
+        let mut add = |data: &[u8]| {
+            hasher.input((data.len() as u64).to_le_bytes());
+            hasher.input(data);
+        };
+
+        add(&[self.flavor.into()]);
+        add(self.name.as_bytes());
+        add(&[self.is_package as u8, self.is_namespace_package as u8]);
+        add(&[match self.in_memory_compression {
+            Some(algorithm) => algorithm.into(),
+            None => 0,
+        }]);
+
+        let fields = [
+            (ResourceField::InMemorySource, &self.in_memory_source),
+            (ResourceField::InMemoryBytecode, &self.in_memory_bytecode),
+            (
+                ResourceField::InMemoryBytecodeOpt1,
+                &self.in_memory_bytecode_opt1,
+            ),
+            (
+                ResourceField::InMemoryBytecodeOpt2,
+                &self.in_memory_bytecode_opt2,
+            ),
+            (
+                ResourceField::InMemoryExtensionModuleSharedLibrary,
+                &self.in_memory_extension_module_shared_library,
+            ),
+            (
+                ResourceField::InMemorySharedLibrary,
+                &self.in_memory_shared_library,
+            ),
+            (ResourceField::InMemoryDataFile, &self.in_memory_data_file),
+        ];
+
+        for (field, data) in fields.iter() {
+            if let Some(data) = data {
+                add(&[(*field).into()]);
+                add(data);
+            }
         }
+
+        let maps = [
+            (
+                ResourceField::InMemoryResourcesData,
+                &self.in_memory_package_resources,
+            ),
+            (
+                ResourceField::InMemoryDistributionResource,
+                &self.in_memory_distribution_resources,
+            ),
+        ];
+
+        for (field, map) in maps.iter() {
+            if let Some(map) = map {
+                add(&[(*field).into()]);
+                add(&(map.len() as u64).to_le_bytes());
+
+                // Map iteration order isn't stable. So sort by key.
+                let mut keys = map.keys().collect::<Vec<_>>();
+                keys.sort();
+
+                for key in keys {
+                    add(key.as_bytes());
+                    add(&map[key]);
+                }
+            }
+        }
+
+        if let Some(names) = &self.shared_library_dependency_names {
+            add(&[ResourceField::SharedLibraryDependencyNames.into()]);
+            add(&(names.len() as u64).to_le_bytes());
+
+            for name in names {
+                add(name.as_bytes());
+            }
+        }
+
+        hasher.result().to_vec()
+    }
+
+    /// Obtain a copy of this resource with its content digest populated.
+    pub fn with_content_digest(&self) -> Resource<'a, u8> {
+        Resource {
+            content_digest: Some(Cow::Owned(self.compute_content_digest())),
+            ..self.clone()
+        }
+    }
+
+    /// Validate the names of Python package resources of this resource.
+    ///
+    /// See `validate_package_resource_name()` for the rules.
+    pub fn validate_package_resource_names(&self) -> Result<(), &'static str> {
+        if let Some(resources) = &self.in_memory_package_resources {
+            for name in resources.keys() {
+                validate_package_resource_name(name)?;
+            }
+        }
+
+        if let Some(resources) = &self.relative_path_package_resources {
+            for name in resources.keys() {
+                validate_package_resource_name(name)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Verify the content digest of this resource.
+    ///
+    /// Errors if the resource doesn't have a content digest or if the digest
+    /// doesn't match the resource's content.
+    pub fn verify_content_digest(&self) -> Result<(), &'static str> {
+        match &self.content_digest {
+            Some(digest) => {
+                if digest.as_ref() == self.compute_content_digest().as_slice() {
+                    Ok(())
+                } else {
+                    Err("resource content digest mismatch")
+                }
+            }
+            None => Err("resource does not have a content digest"),
+        }
+    }
+
+    /// Obtain the lengths of all in-memory data, keyed like `in_memory_decompressed_lengths`.
+    fn in_memory_data_lengths(&self) -> InMemoryDataLengths<'a> {
+        let mut lengths = HashMap::new();
+
+        let fields = [
+            (ResourceField::InMemorySource, &self.in_memory_source),
+            (ResourceField::InMemoryBytecode, &self.in_memory_bytecode),
+            (
+                ResourceField::InMemoryBytecodeOpt1,
+                &self.in_memory_bytecode_opt1,
+            ),
+            (
+                ResourceField::InMemoryBytecodeOpt2,
+                &self.in_memory_bytecode_opt2,
+            ),
+            (ResourceField::InMemoryDataFile, &self.in_memory_data_file),
+        ];
+
+        for (field, data) in fields.iter() {
+            if let Some(data) = data {
+                lengths.insert((*field, None), data.len());
+            }
+        }
+
+        let maps = [
+            (
+                ResourceField::InMemoryResourcesData,
+                &self.in_memory_package_resources,
+            ),
+            (
+                ResourceField::InMemoryDistributionResource,
+                &self.in_memory_distribution_resources,
+            ),
+        ];
+
+        for (field, map) in maps.iter() {
+            if let Some(map) = map {
+                for (name, data) in map.iter() {
+                    lengths.insert((*field, Some(name.clone())), data.len());
+                }
+            }
+        }
+
+        lengths
+    }
+
+    /// Obtain a copy of this resource with `transform` applied to all in-memory data.
+    ///
+    /// `compression` is the compression of the transformed data. When
+    /// compressing, the lengths of the original data are recorded as the
+    /// decompressed lengths.
+    fn transform_in_memory_data<F>(
+        &self,
+        compression: Option<CompressionAlgorithm>,
+        transform: F,
+    ) -> Result<Resource<'a, u8>, &'static str>
+    where
+        F: Fn(ResourceField, Option<&str>, &[u8]) -> Result<Vec<u8>, &'static str>,
+    {
+        let transform_data =
+            |field: ResourceField, data: &Option<Cow<'a, [u8]>>| -> Result<_, &'static str> {
+                Ok(match data {
+                    Some(data) => Some(Cow::Owned(transform(field, None, data)?)),
+                    None => None,
+                })
+            };
+
+        let transform_map = |field: ResourceField,
+                             map: &Option<HashMap<Cow<'a, str>, Cow<'a, [u8]>>>|
+         -> Result<_, &'static str> {
+            Ok(match map {
+                Some(map) => Some(
+                    map.iter()
+                        .map(|(k, v)| Ok((k.clone(), Cow::Owned(transform(field, Some(k), v)?))))
+                        .collect::<Result<HashMap<_, _>, &'static str>>()?,
+                ),
+                None => None,
+            })
+        };
+
+        let in_memory_decompressed_lengths = if compression.is_some() {
+            Some(self.in_memory_data_lengths())
+        } else {
+            None
+        };
+
+        Ok(Resource {
+            in_memory_source: transform_data(
+                ResourceField::InMemorySource,
+                &self.in_memory_source,
+            )?,
+            in_memory_bytecode: transform_data(
+                ResourceField::InMemoryBytecode,
+                &self.in_memory_bytecode,
+            )?,
+            in_memory_bytecode_opt1: transform_data(
+                ResourceField::InMemoryBytecodeOpt1,
+                &self.in_memory_bytecode_opt1,
+            )?,
+            in_memory_bytecode_opt2: transform_data(
+                ResourceField::InMemoryBytecodeOpt2,
+                &self.in_memory_bytecode_opt2,
+            )?,
+            in_memory_package_resources: transform_map(
+                ResourceField::InMemoryResourcesData,
+                &self.in_memory_package_resources,
+            )?,
+            in_memory_distribution_resources: transform_map(
+                ResourceField::InMemoryDistributionResource,
+                &self.in_memory_distribution_resources,
+            )?,
+            in_memory_data_file: transform_data(
+                ResourceField::InMemoryDataFile,
+                &self.in_memory_data_file,
+            )?,
+            in_memory_compression: compression,
+            in_memory_decompressed_lengths,
+            // The digest covers in-memory data as stored, so it is invalidated.
+            content_digest: None,
+            ..self.clone()
+        })
     }
 }
@@ -25,7 +25,10 @@ be used outside the PyOxidizer project.
 */
 
 pub mod data;
+pub mod merge;
 #[allow(unused)]
 pub mod parser;
+pub mod serialization;
 pub mod specifications;
+pub mod trailer;
 pub mod writer;
@@ -0,0 +1,258 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Combining and filtering packed resources.
+
+Resources parsed from multiple payloads can be merged into a single set of
+resources and resources can be removed by name. The results can be written
+to a new payload via the `writer` module.
+*/
+
+use {
+    super::data::Resource,
+    super::parser::{load_resources, ParseError},
+    super::writer::{write_embedded_resources_v2, WriteOptions},
+    anyhow::{anyhow, Result},
+    std::collections::{HashMap, HashSet},
+    std::io::Write,
+};
+
+/// Defines how to handle resources having the same name when merging.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConflictPolicy {
+    /// Error if multiple resources have the same name.
+    Error,
+
+    /// Keep the resource from the earliest set of resources defining it.
+    KeepFirst,
+
+    /// Keep the resource from the latest set of resources defining it.
+    ///
+    /// This allows later sets of resources to override earlier ones.
+    KeepLast,
+}
+
+/// Merge multiple sets of resources into one.
+///
+/// Resources are emitted in the order they are first seen. If a name is
+/// defined multiple times, `policy` determines which resource is kept. A
+/// kept resource occupies the position of the first resource with its name.
+pub fn merge_resources<'a>(
+    resources: impl IntoIterator<Item = Vec<Resource<'a, u8>>>,
+    policy: ConflictPolicy,
+) -> Result<Vec<Resource<'a, u8>>> {
+    let mut merged: Vec<Resource<'a, u8>> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+
+    for set in resources {
+        for resource in set {
+            if let Some(position) = positions.get(resource.name.as_ref()) {
+                match policy {
+                    ConflictPolicy::Error => {
+                        return Err(anyhow!(
+                            "resource {} is defined multiple times",
+                            resource.name
+                        ));
+                    }
+                    ConflictPolicy::KeepFirst => {}
+                    ConflictPolicy::KeepLast => {
+                        merged[*position] = resource;
+                    }
+                }
+            } else {
+                positions.insert(resource.name.to_string(), merged.len());
+                merged.push(resource);
+            }
+        }
+    }
+
+    Ok(merged)
+}
+
+/// Remove resources having any of the specified names.
+pub fn subtract_resources<'a>(
+    resources: Vec<Resource<'a, u8>>,
+    names: &HashSet<&str>,
+) -> Vec<Resource<'a, u8>> {
+    resources
+        .into_iter()
+        .filter(|resource| !names.contains(resource.name.as_ref()))
+        .collect()
+}
+
+fn parse_resources(data: &[u8]) -> Result<Vec<Resource<'_, u8>>> {
+    load_resources(data)
+        .map_err(|e| anyhow!("parsing resources: {}", e))?
+        .collect::<Result<Vec<_>, ParseError>>()
+        .map_err(|e| anyhow!("parsing resources: {}", e))
+}
+
+/// Merge multiple packed resources payloads into a new payload.
+///
+/// See `merge_resources()` for semantics. The merged payload is written as
+/// version 2 with the given options.
+pub fn merge_packed_resources<W: Write>(
+    datas: &[&[u8]],
+    policy: ConflictPolicy,
+    dest: &mut W,
+    options: &WriteOptions,
+) -> Result<()> {
+    let resources = datas
+        .iter()
+        .map(|data| parse_resources(data))
+        .collect::<Result<Vec<_>>>()?;
+
+    let merged = merge_resources(resources, policy)?;
+
+    write_embedded_resources_v2(&merged, dest, options)
+}
+
+/// Write a new payload lacking resources having any of the specified names.
+///
+/// The new payload is written as version 2 with the given options.
+pub fn subtract_packed_resources<W: Write>(
+    data: &[u8],
+    names: &HashSet<&str>,
+    dest: &mut W,
+    options: &WriteOptions,
+) -> Result<()> {
+    let resources = subtract_resources(parse_resources(data)?, names);
+
+    write_embedded_resources_v2(&resources, dest, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::data::{CompressionAlgorithm, ResourceFlavor},
+        std::borrow::Cow,
+    };
+
+    fn module(name: &str, source: &[u8]) -> Resource<'static, u8> {
+        Resource {
+            flavor: ResourceFlavor::Module,
+            name: Cow::Owned(name.to_string()),
+            in_memory_source: Some(Cow::Owned(source.to_vec())),
+            ..Resource::default()
+        }
+    }
+
+    #[test]
+    fn test_merge_policies() -> Result<()> {
+        let base = vec![module("foo", b"base foo"), module("bar", b"base bar")];
+        let product = vec![module("baz", b"product baz"), module("foo", b"product foo")];
+
+        assert!(
+            merge_resources(vec![base.clone(), product.clone()], ConflictPolicy::Error).is_err()
+        );
+
+        let merged = merge_resources(
+            vec![base.clone(), product.clone()],
+            ConflictPolicy::KeepFirst,
+        )?;
+        assert_eq!(
+            merged,
+            vec![
+                module("foo", b"base foo"),
+                module("bar", b"base bar"),
+                module("baz", b"product baz"),
+            ]
+        );
+
+        let merged = merge_resources(vec![base, product], ConflictPolicy::KeepLast)?;
+        assert_eq!(
+            merged,
+            vec![
+                module("foo", b"product foo"),
+                module("bar", b"base bar"),
+                module("baz", b"product baz"),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_no_conflicts() -> Result<()> {
+        let merged = merge_resources(
+            vec![
+                vec![module("foo", b"foo")],
+                vec![],
+                vec![module("bar", b"bar")],
+            ],
+            ConflictPolicy::Error,
+        )?;
+        assert_eq!(merged, vec![module("foo", b"foo"), module("bar", b"bar")]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_subtract() {
+        let resources = vec![module("foo", b"foo"), module("bar", b"bar")];
+
+        let mut names = HashSet::new();
+        names.insert("foo");
+        names.insert("missing");
+
+        assert_eq!(
+            subtract_resources(resources, &names),
+            vec![module("bar", b"bar")]
+        );
+    }
+
+    #[test]
+    fn test_merge_packed_resources() -> Result<()> {
+        let compressed = WriteOptions {
+            resource_compression: Some(CompressionAlgorithm::Zstd),
+            ..WriteOptions::default()
+        };
+
+        let mut base = Vec::new();
+        write_embedded_resources_v2(
+            &[module("foo", b"base foo"), module("bar", b"base bar")],
+            &mut base,
+            &compressed,
+        )?;
+
+        let mut product = Vec::new();
+        write_embedded_resources_v2(
+            &[module("foo", b"product foo")],
+            &mut product,
+            &WriteOptions::default(),
+        )?;
+
+        let mut merged = Vec::new();
+        merge_packed_resources(
+            &[&base, &product],
+            ConflictPolicy::KeepLast,
+            &mut merged,
+            &WriteOptions::default(),
+        )?;
+
+        let resources = parse_resources(&merged)?;
+        assert_eq!(resources.len(), 2);
+        assert_eq!(resources[0], module("foo", b"product foo"));
+        // Compressed data is carried over as-is.
+        assert_eq!(
+            resources[1]
+                .decompress_in_memory_data()
+                .map_err(|e| anyhow!(e))?,
+            module("bar", b"base bar")
+        );
+
+        let mut names = HashSet::new();
+        names.insert("foo");
+
+        let mut subtracted = Vec::new();
+        subtract_packed_resources(&merged, &names, &mut subtracted, &WriteOptions::default())?;
+
+        let resources = parse_resources(&subtracted)?;
+        assert_eq!(resources.len(), 1);
+        assert_eq!(resources[0].name, "bar");
+
+        Ok(())
+    }
+}
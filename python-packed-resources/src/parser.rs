@@ -6,7 +6,9 @@
 
 use {
     super::data::{
-        BlobInteriorPadding, BlobSectionField, Resource, ResourceField, ResourceFlavor, HEADER_V1,
+        alignment_padding, sha256_digest, BlobInteriorPadding, BlobSectionField,
+        CompressionAlgorithm, Resource, ResourceField, ResourceFlavor, DIGEST_LENGTH, HEADER_V1,
+        HEADER_V2,
     },
     byteorder::{LittleEndian, ReadBytesExt},
     std::borrow::Cow,
@@ -15,37 +17,232 @@ use {
     std::ffi::OsStr,
     std::io::{Cursor, Read},
     std::path::Path,
+    std::sync::{Arc, Mutex},
 };
 
 #[cfg(unix)]
-use std::os::unix::ffi::OsStrExt;
+use {
+    std::ffi::OsString,
+    std::os::unix::ffi::{OsStrExt, OsStringExt},
+    std::path::PathBuf,
+};
 #[cfg(windows)]
 use {std::ffi::OsString, std::os::windows::ffi::OsStringExt, std::path::PathBuf};
 
+/// An error encountered when parsing packed resources data.
+///
+/// Offsets are relative to the start of the packed resources data, including
+/// the magic header.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParseError {
+    /// Data is malformed at the given offset.
+    Malformed {
+        message: &'static str,
+        offset: usize,
+    },
+
+    /// A blob section extends beyond the end of the data.
+    SectionOutOfBounds {
+        field: u8,
+        offset: usize,
+        length: usize,
+        data_length: usize,
+    },
+
+    /// A blob extends beyond the end of its blob section.
+    ///
+    /// `offset` is the offset of the blob within its (decompressed) section.
+    BlobOutOfBounds {
+        field: u8,
+        offset: usize,
+        length: usize,
+        section_length: usize,
+    },
+
+    /// A compressed blob section could not be decompressed.
+    Decompression { field: u8, message: &'static str },
+
+    /// Data can't be used for the requested operation or failed verification.
+    Invalid(&'static str),
+}
+
+impl ParseError {
+    fn malformed(message: &'static str, offset: usize) -> Self {
+        ParseError::Malformed { message, offset }
+    }
+
+    /// A short description of the error, without offsets.
+    pub fn message(&self) -> &'static str {
+        match self {
+            ParseError::Malformed { message, .. } => message,
+            ParseError::SectionOutOfBounds { .. } => "blob section extends beyond end of data",
+            ParseError::BlobOutOfBounds { .. } => "blob extends beyond end of blob section",
+            ParseError::Decompression { message, .. } => message,
+            ParseError::Invalid(message) => message,
+        }
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::Malformed { message, offset } => {
+                write!(f, "{} at offset {}", message, offset)
+            }
+            ParseError::SectionOutOfBounds {
+                field,
+                offset,
+                length,
+                data_length,
+            } => write!(
+                f,
+                "blob section for field {:#04x} at offset {} with length {} extends beyond end of data of length {}",
+                field, offset, length, data_length
+            ),
+            ParseError::BlobOutOfBounds {
+                field,
+                offset,
+                length,
+                section_length,
+            } => write!(
+                f,
+                "blob at offset {} with length {} extends beyond blob section for field {:#04x} of length {}",
+                offset, length, field, section_length
+            ),
+            ParseError::Decompression { field, message } => write!(
+                f,
+                "error decompressing blob section for field {:#04x}: {}",
+                field, message
+            ),
+            ParseError::Invalid(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Reads values from an index, producing errors describing where reads failed.
+///
+/// Readers wrap the data following the magic header. Offsets in errors
+/// account for it.
+trait IndexReader {
+    /// Offset of the next read relative to the start of the data.
+    fn offset(&self) -> usize;
+
+    /// The number of bytes remaining to be read.
+    fn remaining(&self) -> usize;
+
+    /// Produce an error for malformed data at the current offset.
+    fn malformed(&self, message: &'static str) -> ParseError {
+        ParseError::Malformed {
+            message,
+            offset: self.offset(),
+        }
+    }
+
+    fn read_index_u8(&mut self, message: &'static str) -> Result<u8, ParseError>;
+    fn read_index_u16(&mut self, message: &'static str) -> Result<usize, ParseError>;
+    fn read_index_u32(&mut self, message: &'static str) -> Result<usize, ParseError>;
+    fn read_index_u64(&mut self, message: &'static str) -> Result<usize, ParseError>;
+
+    /// Read a `u8` and convert it to an enum.
+    fn read_index_enum<T>(&mut self, message: &'static str) -> Result<T, ParseError>
+    where
+        T: TryFrom<u8, Error = &'static str>,
+    {
+        let offset = self.offset();
+        let value = self.read_index_u8(message)?;
+
+        T::try_from(value).map_err(|message| ParseError::Malformed { message, offset })
+    }
+}
+
+impl IndexReader for Cursor<&[u8]> {
+    fn offset(&self) -> usize {
+        HEADER_V1.len() + self.position() as usize
+    }
+
+    fn remaining(&self) -> usize {
+        self.get_ref()
+            .len()
+            .saturating_sub(self.position() as usize)
+    }
+
+    fn read_index_u8(&mut self, message: &'static str) -> Result<u8, ParseError> {
+        let error = self.malformed(message);
+        self.read_u8().map_err(|_| error)
+    }
+
+    fn read_index_u16(&mut self, message: &'static str) -> Result<usize, ParseError> {
+        let error = self.malformed(message);
+        Ok(self.read_u16::<LittleEndian>().map_err(|_| error)? as usize)
+    }
+
+    fn read_index_u32(&mut self, message: &'static str) -> Result<usize, ParseError> {
+        let error = self.malformed(message);
+        Ok(self.read_u32::<LittleEndian>().map_err(|_| error)? as usize)
+    }
+
+    fn read_index_u64(&mut self, message: &'static str) -> Result<usize, ParseError> {
+        let error = self.malformed(message);
+        let value = self.read_u64::<LittleEndian>().map_err(|_| error)?;
+
+        usize::try_from(value).map_err(|_| error)
+    }
+}
+
 /// Represents a blob section in the blob index.
 #[derive(Debug)]
 struct BlobSection {
     resource_field: u8,
     raw_payload_length: usize,
     interior_padding: Option<BlobInteriorPadding>,
+    compression: Option<CompressionAlgorithm>,
+    decompressed_payload_length: Option<usize>,
+    alignment: Option<usize>,
+    entry_alignment: Option<usize>,
 }
 
 /// Holds state used to read an individual blob section.
 #[derive(Clone, Copy, Debug)]
 struct BlobSectionReadState {
+    /// Offset of the section's raw payload from the start of data.
+    start_offset: usize,
+    /// Length of the section's raw payload.
+    raw_payload_length: usize,
+    /// Offset of the next blob relative to the start of the (decompressed) section.
     offset: usize,
     interior_padding: BlobInteriorPadding,
+    /// Compression applied to the section and the length of its decompressed payload.
+    compression: Option<(CompressionAlgorithm, usize)>,
+    /// Alignment of each blob, relative to the start of the packed data.
+    entry_alignment: Option<usize>,
 }
 
 pub type PythonPackageResources<'a> = HashMap<&'a str, &'a [u8]>;
 
 pub struct ResourceParserIterator<'a> {
     done: bool,
+    version: u8,
     data: &'a [u8],
     reader: Cursor<&'a [u8]>,
     blob_sections: [Option<BlobSectionReadState>; 256],
+    /// Decompressed payloads of compressed blob sections, keyed by resource field.
+    ///
+    /// Sections are decompressed on first access.
+    decompressed_sections: HashMap<u8, Arc<Vec<u8>>>,
     claimed_resources_count: usize,
     read_resources_count: usize,
+    /// Offset of the data following the global header.
+    global_header_length: usize,
+    /// SHA-256 digest of the data following the global header.
+    payload_digest: Option<&'a [u8]>,
+    /// Offset of the resources index.
+    resources_index_offset: usize,
+    /// Resource fields of blob sections, in blob index order.
+    section_fields: Vec<u8>,
+    /// The lookup index, if present.
+    lookup_index: Option<&'a [u8]>,
 }
 
 impl<'a> ResourceParserIterator<'a> {
@@ -54,64 +251,283 @@ impl<'a> ResourceParserIterator<'a> {
         self.claimed_resources_count
     }
 
-    /// Resolve a slice to an individual blob's data.
+    /// The version of the packed resources format of the payload.
+    pub fn format_version(&self) -> u8 {
+        self.version
+    }
+
+    /// Whether the payload has a lookup index.
+    ///
+    /// Payloads with a lookup index can be converted to `IndexedResources`
+    /// via `into_indexed()`.
+    pub fn has_lookup_index(&self) -> bool {
+        self.lookup_index.is_some()
+    }
+
+    /// Convert into a random-access view of resources.
+    ///
+    /// Errors if the payload doesn't have a lookup index. Compressed blob
+    /// sections are decompressed the first time a lookup needs them.
+    pub fn into_indexed(mut self) -> Result<IndexedResources<'a>, ParseError> {
+        let lookup_index = match self.lookup_index {
+            Some(index) => index,
+            None => return Err(ParseError::Invalid("payload does not have a lookup index")),
+        };
+
+        // Offset into resources index + name length + offset into each blob section.
+        let row_length = 4 + 2 + 4 * self.section_fields.len();
+
+        if Some(lookup_index.len()) != row_length.checked_mul(self.claimed_resources_count) {
+            return Err(ParseError::Invalid(
+                "lookup index length does not match resources count",
+            ));
+        }
+
+        if self.claimed_resources_count > 0
+            && !self
+                .section_fields
+                .contains(&ResourceField::ModuleName.into())
+        {
+            return Err(ParseError::Invalid(
+                "lookup index requires resource names blob section",
+            ));
+        }
+
+        let decompressed_sections = std::mem::take(&mut self.decompressed_sections);
+
+        Ok(IndexedResources {
+            parser: self,
+            lookup_index,
+            row_length,
+            decompressed_sections: Mutex::new(decompressed_sections),
+        })
+    }
+
+    /// The SHA-256 digest of the payload, if present.
+    pub fn payload_digest(&self) -> Option<&'a [u8]> {
+        self.payload_digest
+    }
+
+    /// Verify the payload digest against the payload data.
+    ///
+    /// This reads the entire payload, which defeats lazy access to blob data.
+    /// Errors if the payload doesn't have a digest or if the digest doesn't
+    /// match.
+    ///
+    /// Individual resources can be verified with
+    /// `Resource::verify_content_digest()`.
+    pub fn verify_payload_digest(&self) -> Result<(), ParseError> {
+        match self.payload_digest {
+            Some(digest) => {
+                if digest == sha256_digest(&self.data[self.global_header_length..]).as_slice() {
+                    Ok(())
+                } else {
+                    Err(ParseError::Invalid("payload digest mismatch"))
+                }
+            }
+            None => Err(ParseError::Invalid("payload does not have a digest")),
+        }
+    }
+
+    /// Decompress a compressed blob section, if not already decompressed.
+    ///
+    /// Does nothing for uncompressed sections.
+    fn decompress_section(&mut self, field: u8) -> Result<(), ParseError> {
+        let state = match self.blob_sections[field as usize] {
+            Some(state) => state,
+            None => return Ok(()),
+        };
+
+        if state.compression.is_some() && !self.decompressed_sections.contains_key(&field) {
+            let decompressed = decompress_section_data(self.data, field, &state)?;
+            self.decompressed_sections
+                .insert(field, Arc::new(decompressed));
+        }
+
+        Ok(())
+    }
+
+    /// Resolve an individual blob's data.
+    ///
+    /// This accepts the resource field being accessed and the length of the
+    /// blob and returns the data for that blob.
+    ///
+    /// Blobs in uncompressed sections are borrowed from the original payload.
+    /// Blobs in compressed sections are copied out of the decompressed section,
+    /// which is decompressed the first time any of its blobs is accessed.
+    ///
+    /// Errors if the blob extends beyond its section.
+    fn resolve_blob_data(
+        &mut self,
+        resource_field: ResourceField,
+        length: usize,
+    ) -> Result<Cow<'a, [u8]>, ParseError> {
+        let field: u8 = resource_field.into();
+
+        let state = match self.blob_sections[field as usize].as_mut() {
+            Some(state) => state,
+            // Writers omit sections only holding empty blobs.
+            None if length == 0 => return Ok(Cow::Borrowed(&[])),
+            None => {
+                return Err(self
+                    .reader
+                    .malformed("blob section for resource field not found"))
+            }
+        };
+
+        let section_length = match state.compression {
+            Some((_, decompressed_length)) => decompressed_length,
+            None => state.raw_payload_length,
+        };
+
+        let mut offset = state.offset;
+
+        if let Some(alignment) = state.entry_alignment {
+            offset += alignment_padding(HEADER_V1.len() + state.start_offset + offset, alignment);
+        }
+
+        let end = match offset.checked_add(length) {
+            Some(end) if end <= section_length => end,
+            _ => {
+                return Err(ParseError::BlobOutOfBounds {
+                    field,
+                    offset,
+                    length,
+                    section_length,
+                })
+            }
+        };
+
+        state.offset = match &state.interior_padding {
+            BlobInteriorPadding::None => end,
+            BlobInteriorPadding::Null => end + 1,
+        };
+
+        let state = *state;
+
+        match state.compression {
+            None => Ok(Cow::Borrowed(
+                &self.data[state.start_offset + offset..state.start_offset + end],
+            )),
+            Some(_) => {
+                self.decompress_section(field)?;
+
+                Ok(Cow::Owned(
+                    self.decompressed_sections[&field][offset..end].to_vec(),
+                ))
+            }
+        }
+    }
+
+    /// Resolve an individual blob's data as a string.
     ///
-    /// This accepts a reference to the original blobs payload, an array of
-    /// current blob section offsets, the resource field being accessed, and the
-    /// length of the blob and returns a slice to that blob.
-    fn resolve_blob_data(&mut self, resource_field: ResourceField, length: usize) -> &'a [u8] {
-        let mut state = self.blob_sections[resource_field as usize]
-            .as_mut()
-            .expect("blob state not found");
-
-        let blob = &self.data[state.offset..state.offset + length];
-
-        let increment = match &state.interior_padding {
-            BlobInteriorPadding::None => length,
-            BlobInteriorPadding::Null => length + 1,
+    /// Errors if the data isn't valid UTF-8.
+    fn resolve_blob_str(
+        &mut self,
+        resource_field: ResourceField,
+        length: usize,
+    ) -> Result<Cow<'a, str>, ParseError> {
+        let error = self.reader.malformed("blob data is not valid UTF-8");
+
+        Ok(match self.resolve_blob_data(resource_field, length)? {
+            Cow::Borrowed(data) => Cow::Borrowed(std::str::from_utf8(data).map_err(|_| error)?),
+            Cow::Owned(data) => Cow::Owned(String::from_utf8(data).map_err(|_| error)?),
+        })
+    }
+
+    /// Read the length of a compressible in-memory data blob.
+    ///
+    /// `length` is the already read length of the data. If in-memory data of the
+    /// current resource is compressed, `length` is the decompressed length and
+    /// is recorded on the resource for `field` and `name`. The compressed length
+    /// follows and is returned, as it is the length of the data stored in the
+    /// blob section.
+    fn read_in_memory_data_length(
+        &mut self,
+        resource: &mut Resource<'a, u8>,
+        field: ResourceField,
+        name: Option<Cow<'a, str>>,
+        length: usize,
+        wide: bool,
+    ) -> Result<usize, ParseError> {
+        if resource.in_memory_compression.is_none() {
+            return Ok(length);
+        }
+
+        let compressed_length = if wide {
+            self.reader
+                .read_index_u64("failed reading compressed length")?
+        } else {
+            self.reader
+                .read_index_u32("failed reading compressed length")?
         };
 
-        state.offset += increment;
+        resource
+            .in_memory_decompressed_lengths
+            .get_or_insert_with(HashMap::new)
+            .insert((field, name), length);
 
-        blob
+        Ok(compressed_length)
     }
 
     #[cfg(unix)]
-    fn resolve_path(&mut self, resource_field: ResourceField, length: usize) -> Cow<'a, Path> {
-        let path_str = OsStr::from_bytes(self.resolve_blob_data(resource_field, length));
-        Cow::Borrowed(Path::new(path_str))
+    fn resolve_path(
+        &mut self,
+        resource_field: ResourceField,
+        length: usize,
+    ) -> Result<Cow<'a, Path>, ParseError> {
+        Ok(match self.resolve_blob_data(resource_field, length)? {
+            Cow::Borrowed(data) => Cow::Borrowed(Path::new(OsStr::from_bytes(data))),
+            Cow::Owned(data) => Cow::Owned(PathBuf::from(OsString::from_vec(data))),
+        })
     }
 
     #[cfg(windows)]
-    fn resolve_path(&mut self, resource_field: ResourceField, length: usize) -> Cow<'a, Path> {
-        let raw = self.resolve_blob_data(resource_field, length);
-        let raw = unsafe { std::slice::from_raw_parts(raw.as_ptr() as *const u16, raw.len() / 2) };
+    fn resolve_path(
+        &mut self,
+        resource_field: ResourceField,
+        length: usize,
+    ) -> Result<Cow<'a, Path>, ParseError> {
+        let error = self.reader.malformed("path data has odd length");
+
+        let raw = self.resolve_blob_data(resource_field, length)?;
+
+        if raw.len() % 2 != 0 {
+            return Err(error);
+        }
+
+        // Blob data isn't necessarily aligned for u16, so decode byte pairs.
+        let raw = raw
+            .chunks_exact(2)
+            .map(|pair| u16::from_ne_bytes([pair[0], pair[1]]))
+            .collect::<Vec<_>>();
 
         // There isn't an API that lets us get a OsStr from &[u16]. So we need to use
         // owned types.
-        let path_string = OsString::from_wide(raw);
+        let path_string = OsString::from_wide(&raw);
 
-        Cow::Owned(PathBuf::from(path_string))
+        Ok(Cow::Owned(PathBuf::from(path_string)))
     }
 
-    fn parse_next(&mut self) -> Result<Option<Resource<'a, u8>>, &'static str> {
+    fn parse_next(&mut self) -> Result<Option<Resource<'a, u8>>, ParseError> {
         let mut current_resource = Resource::default();
         let mut current_resource_name = None;
 
         loop {
-            let field_type = self
-                .reader
-                .read_u8()
-                .or_else(|_| Err("failed reading field type"))?;
+            let field_offset = self.reader.offset();
 
-            let field_type = ResourceField::try_from(field_type)?;
+            let field_type: ResourceField =
+                self.reader.read_index_enum("failed reading field type")?;
 
             match field_type {
                 ResourceField::EndOfIndex => {
                     self.done = true;
 
                     if self.read_resources_count != self.claimed_resources_count {
-                        return Err("mismatch between advertised index count and actual");
+                        return Err(ParseError::malformed(
+                            "mismatch between advertised index count and actual",
+                            field_offset,
+                        ));
                     }
 
                     return Ok(None);
@@ -124,9 +540,13 @@ impl<'a> ResourceParserIterator<'a> {
 
                 ResourceField::EndOfEntry => {
                     let res = if let Some(name) = current_resource_name {
+                        current_resource.name = name;
                         Ok(Some(current_resource))
                     } else {
-                        Err("resource name field is required")
+                        Err(ParseError::malformed(
+                            "resource name field is required",
+                            field_offset,
+                        ))
                     };
 
                     current_resource = Resource::default();
@@ -135,27 +555,23 @@ impl<'a> ResourceParserIterator<'a> {
                     return res;
                 }
                 ResourceField::Flavor => {
-                    let flavor = self
-                        .reader
-                        .read_u8()
-                        .or_else(|_| Err("failed reading flavor value"))?;
-
-                    current_resource.flavor = ResourceFlavor::try_from(flavor)?;
+                    current_resource.flavor =
+                        self.reader.read_index_enum("failed reading flavor value")?;
+
+                    if current_resource.flavor == ResourceFlavor::DataFile && self.version < 2 {
+                        return Err(ParseError::malformed(
+                            "data files not supported by format version",
+                            field_offset,
+                        ));
+                    }
                 }
 
                 ResourceField::ModuleName => {
                     let l = self
                         .reader
-                        .read_u16::<LittleEndian>()
-                        .or_else(|_| Err("failed reading resource name length"))?
-                        as usize;
-
-                    let name = unsafe {
-                        std::str::from_utf8_unchecked(self.resolve_blob_data(field_type, l))
-                    };
+                        .read_index_u16("failed reading resource name length")?;
 
-                    current_resource_name = Some(name);
-                    current_resource.name = Cow::Borrowed(name);
+                    current_resource_name = Some(self.resolve_blob_str(field_type, l)?);
                 }
                 ResourceField::IsPackage => {
                     current_resource.is_package = true;
@@ -163,89 +579,141 @@ impl<'a> ResourceParserIterator<'a> {
                 ResourceField::IsNamespacePackage => {
                     current_resource.is_namespace_package = true;
                 }
-                ResourceField::InMemorySource => {
+                ResourceField::ContentDigest => {
+                    if self.version < 2 {
+                        return Err(ParseError::malformed(
+                            "content digest not supported by format version",
+                            field_offset,
+                        ));
+                    }
+
                     let l = self
                         .reader
-                        .read_u32::<LittleEndian>()
-                        .or_else(|_| Err("failed reading source length"))?
+                        .read_index_u8("failed reading content digest length")?
                         as usize;
 
+                    if l != DIGEST_LENGTH {
+                        return Err(ParseError::malformed(
+                            "invalid content digest length",
+                            field_offset,
+                        ));
+                    }
+
+                    current_resource.content_digest = Some(self.resolve_blob_data(field_type, l)?);
+                }
+                ResourceField::InMemoryCompression => {
+                    if self.version < 2 {
+                        return Err(ParseError::malformed(
+                            "in-memory compression not supported by format version",
+                            field_offset,
+                        ));
+                    }
+
+                    current_resource.in_memory_compression = Some(
+                        self.reader
+                            .read_index_enum("failed reading in-memory compression value")?,
+                    );
+                }
+                ResourceField::InMemorySource => {
+                    let l = self.reader.read_index_u32("failed reading source length")?;
+
+                    let l = self.read_in_memory_data_length(
+                        &mut current_resource,
+                        field_type,
+                        None,
+                        l,
+                        false,
+                    )?;
+
                     current_resource.in_memory_source =
-                        Some(Cow::Borrowed(self.resolve_blob_data(field_type, l)));
+                        Some(self.resolve_blob_data(field_type, l)?);
                 }
                 ResourceField::InMemoryBytecode => {
                     let l = self
                         .reader
-                        .read_u32::<LittleEndian>()
-                        .or_else(|_| Err("failed reading bytecode length"))?
-                        as usize;
+                        .read_index_u32("failed reading bytecode length")?;
+
+                    let l = self.read_in_memory_data_length(
+                        &mut current_resource,
+                        field_type,
+                        None,
+                        l,
+                        false,
+                    )?;
 
                     current_resource.in_memory_bytecode =
-                        Some(Cow::Borrowed(self.resolve_blob_data(field_type, l)));
+                        Some(self.resolve_blob_data(field_type, l)?);
                 }
                 ResourceField::InMemoryBytecodeOpt1 => {
                     let l = self
                         .reader
-                        .read_u32::<LittleEndian>()
-                        .or_else(|_| Err("failed reading bytecode length"))?
-                        as usize;
+                        .read_index_u32("failed reading bytecode length")?;
+
+                    let l = self.read_in_memory_data_length(
+                        &mut current_resource,
+                        field_type,
+                        None,
+                        l,
+                        false,
+                    )?;
 
                     current_resource.in_memory_bytecode_opt1 =
-                        Some(Cow::Borrowed(self.resolve_blob_data(field_type, l)));
+                        Some(self.resolve_blob_data(field_type, l)?);
                 }
                 ResourceField::InMemoryBytecodeOpt2 => {
                     let l = self
                         .reader
-                        .read_u32::<LittleEndian>()
-                        .or_else(|_| Err("failed reading bytecode length"))?
-                        as usize;
+                        .read_index_u32("failed reading bytecode length")?;
+
+                    let l = self.read_in_memory_data_length(
+                        &mut current_resource,
+                        field_type,
+                        None,
+                        l,
+                        false,
+                    )?;
 
                     current_resource.in_memory_bytecode_opt2 =
-                        Some(Cow::Borrowed(self.resolve_blob_data(field_type, l)));
+                        Some(self.resolve_blob_data(field_type, l)?);
                 }
                 ResourceField::InMemoryExtensionModuleSharedLibrary => {
                     let l = self
                         .reader
-                        .read_u32::<LittleEndian>()
-                        .or_else(|_| Err("failed reading extension module length"))?
-                        as usize;
+                        .read_index_u32("failed reading extension module length")?;
 
                     current_resource.in_memory_extension_module_shared_library =
-                        Some(Cow::Borrowed(self.resolve_blob_data(field_type, l)));
+                        Some(self.resolve_blob_data(field_type, l)?);
                 }
 
                 ResourceField::InMemoryResourcesData => {
                     let resource_count = self
                         .reader
-                        .read_u32::<LittleEndian>()
-                        .or_else(|_| Err("failed reading resources length"))?
-                        as usize;
+                        .read_index_u32("failed reading resources length")?;
 
-                    let mut resources = HashMap::with_capacity(resource_count);
+                    let mut resources =
+                        HashMap::with_capacity(resource_count.min(self.reader.remaining()));
 
                     for _ in 0..resource_count {
-                        let resource_name_length = self
-                            .reader
-                            .read_u16::<LittleEndian>()
-                            .or_else(|_| Err("failed reading resource name"))?
-                            as usize;
+                        let resource_name_length =
+                            self.reader.read_index_u16("failed reading resource name")?;
 
-                        let resource_name = unsafe {
-                            std::str::from_utf8_unchecked(
-                                self.resolve_blob_data(field_type, resource_name_length),
-                            )
-                        };
+                        let resource_name =
+                            self.resolve_blob_str(field_type, resource_name_length)?;
 
                         let resource_length = self
                             .reader
-                            .read_u64::<LittleEndian>()
-                            .or_else(|_| Err("failed reading resource length"))?
-                            as usize;
-
-                        let resource_data = self.resolve_blob_data(field_type, resource_length);
-
-                        resources
-                            .insert(Cow::Borrowed(resource_name), Cow::Borrowed(resource_data));
+                            .read_index_u64("failed reading resource length")?;
+                        let resource_length = self.read_in_memory_data_length(
+                            &mut current_resource,
+                            field_type,
+                            Some(resource_name.clone()),
+                            resource_length,
+                            true,
+                        )?;
+
+                        let resource_data = self.resolve_blob_data(field_type, resource_length)?;
+
+                        resources.insert(resource_name, resource_data);
                     }
 
                     current_resource.in_memory_package_resources = Some(resources);
@@ -254,33 +722,32 @@ impl<'a> ResourceParserIterator<'a> {
                 ResourceField::InMemoryDistributionResource => {
                     let resource_count = self
                         .reader
-                        .read_u32::<LittleEndian>()
-                        .or_else(|_| Err("failed reading package distribution length"))?
-                        as usize;
+                        .read_index_u32("failed reading package distribution length")?;
 
-                    let mut resources = HashMap::with_capacity(resource_count);
+                    let mut resources =
+                        HashMap::with_capacity(resource_count.min(self.reader.remaining()));
 
                     for _ in 0..resource_count {
                         let name_length = self
                             .reader
-                            .read_u16::<LittleEndian>()
-                            .or_else(|_| Err("failed reading distribution metadata name"))?
-                            as usize;
+                            .read_index_u16("failed reading distribution metadata name")?;
 
-                        let name = unsafe {
-                            std::str::from_utf8_unchecked(
-                                self.resolve_blob_data(field_type, name_length),
-                            )
-                        };
+                        let name = self.resolve_blob_str(field_type, name_length)?;
 
-                        let resource_length =
-                            self.reader.read_u64::<LittleEndian>().or_else(|_| {
-                                Err("failed reading package distribution resource length")
-                            })? as usize;
+                        let resource_length = self.reader.read_index_u64(
+                            "failed reading package distribution resource length",
+                        )?;
+                        let resource_length = self.read_in_memory_data_length(
+                            &mut current_resource,
+                            field_type,
+                            Some(name.clone()),
+                            resource_length,
+                            true,
+                        )?;
 
-                        let resource_data = self.resolve_blob_data(field_type, resource_length);
+                        let resource_data = self.resolve_blob_data(field_type, resource_length)?;
 
-                        resources.insert(Cow::Borrowed(name), Cow::Borrowed(resource_data));
+                        resources.insert(name, resource_data);
                     }
 
                     current_resource.in_memory_distribution_resources = Some(resources);
@@ -289,34 +756,27 @@ impl<'a> ResourceParserIterator<'a> {
                 ResourceField::InMemorySharedLibrary => {
                     let l = self
                         .reader
-                        .read_u64::<LittleEndian>()
-                        .or_else(|_| Err("failed reading in-memory shared library length"))?
-                        as usize;
+                        .read_index_u64("failed reading in-memory shared library length")?;
 
                     current_resource.in_memory_shared_library =
-                        Some(Cow::Borrowed(self.resolve_blob_data(field_type, l)));
+                        Some(self.resolve_blob_data(field_type, l)?);
                 }
 
                 ResourceField::SharedLibraryDependencyNames => {
-                    let names_count =
-                        self.reader.read_u16::<LittleEndian>().or_else(|_| {
-                            Err("failed reading shared library dependency names length")
-                        })? as usize;
+                    let names_count = self
+                        .reader
+                        .read_index_u16("failed reading shared library dependency names length")?;
 
                     let mut names = Vec::new();
 
                     for _ in 0..names_count {
-                        let name_length = self.reader.read_u16::<LittleEndian>().or_else(|_| {
-                            Err("failed reading shared library dependency name length")
-                        })? as usize;
+                        let name_length = self.reader.read_index_u16(
+                            "failed reading shared library dependency name length",
+                        )?;
 
-                        let name = unsafe {
-                            std::str::from_utf8_unchecked(
-                                self.resolve_blob_data(field_type, name_length),
-                            )
-                        };
+                        let name = self.resolve_blob_str(field_type, name_length)?;
 
-                        names.push(Cow::Borrowed(name));
+                        names.push(name);
                     }
 
                     current_resource.shared_library_dependency_names = Some(names);
@@ -325,127 +785,152 @@ impl<'a> ResourceParserIterator<'a> {
                 ResourceField::RelativeFilesystemModuleSource => {
                     let path_length = self
                         .reader
-                        .read_u32::<LittleEndian>()
-                        .or_else(|_| Err("failed reading Python module relative path length"))?
-                        as usize;
+                        .read_index_u32("failed reading Python module relative path length")?;
 
-                    let path = self.resolve_path(field_type, path_length);
+                    let path = self.resolve_path(field_type, path_length)?;
 
                     current_resource.relative_path_module_source = Some(path);
                 }
 
                 ResourceField::RelativeFilesystemModuleBytecode => {
-                    let path_length = self.reader.read_u32::<LittleEndian>().or_else(|_| {
-                        Err("failed reading Python module bytecode relative path length")
-                    })? as usize;
+                    let path_length = self.reader.read_index_u32(
+                        "failed reading Python module bytecode relative path length",
+                    )?;
 
-                    let path = self.resolve_path(field_type, path_length);
+                    let path = self.resolve_path(field_type, path_length)?;
 
                     current_resource.relative_path_module_bytecode = Some(path);
                 }
 
                 ResourceField::RelativeFilesystemModuleBytecodeOpt1 => {
-                    let path_length = self.reader.read_u32::<LittleEndian>().or_else(|_| {
-                        Err("failed reading Python module bytecode opt 1 relative path length")
-                    })? as usize;
+                    let path_length = self.reader.read_index_u32(
+                        "failed reading Python module bytecode opt 1 relative path length",
+                    )?;
 
-                    let path = self.resolve_path(field_type, path_length);
+                    let path = self.resolve_path(field_type, path_length)?;
 
                     current_resource.relative_path_module_bytecode_opt1 = Some(path);
                 }
 
                 ResourceField::RelativeFilesystemModuleBytecodeOpt2 => {
-                    let path_length = self.reader.read_u32::<LittleEndian>().or_else(|_| {
-                        Err("failed reading Python module bytecode opt 2 relative path length")
-                    })? as usize;
+                    let path_length = self.reader.read_index_u32(
+                        "failed reading Python module bytecode opt 2 relative path length",
+                    )?;
 
-                    let path = self.resolve_path(field_type, path_length);
+                    let path = self.resolve_path(field_type, path_length)?;
 
                     current_resource.relative_path_module_bytecode_opt2 = Some(path);
                 }
 
                 ResourceField::RelativeFilesystemExtensionModuleSharedLibrary => {
-                    let path_length = self.reader.read_u32::<LittleEndian>().or_else(|_| {
-                        Err("failed reading Python extension module shared library relative path length")
-                    })? as usize;
+                    let path_length = self.reader.read_index_u32("failed reading Python extension module shared library relative path length")?;
 
-                    let path = self.resolve_path(field_type, path_length);
+                    let path = self.resolve_path(field_type, path_length)?;
 
                     current_resource.relative_path_extension_module_shared_library = Some(path);
                 }
 
                 ResourceField::RelativeFilesystemPackageResources => {
-                    let resource_count = self.reader.read_u32::<LittleEndian>().or_else(|_| {
-                        Err("failed reading package resources relative path item count")
-                    })? as usize;
+                    let resource_count = self.reader.read_index_u32(
+                        "failed reading package resources relative path item count",
+                    )?;
 
-                    let mut resources = HashMap::with_capacity(resource_count);
+                    let mut resources =
+                        HashMap::with_capacity(resource_count.min(self.reader.remaining()));
 
                     for _ in 0..resource_count {
-                        let resource_name_length = self
-                            .reader
-                            .read_u16::<LittleEndian>()
-                            .or_else(|_| Err("failed reading resource name"))?
-                            as usize;
+                        let resource_name_length =
+                            self.reader.read_index_u16("failed reading resource name")?;
 
-                        let resource_name = unsafe {
-                            std::str::from_utf8_unchecked(
-                                self.resolve_blob_data(field_type, resource_name_length),
-                            )
-                        };
+                        let resource_name =
+                            self.resolve_blob_str(field_type, resource_name_length)?;
 
                         let path_length = self
                             .reader
-                            .read_u32::<LittleEndian>()
-                            .or_else(|_| Err("failed reading resource path length"))?
-                            as usize;
+                            .read_index_u32("failed reading resource path length")?;
 
-                        let path = self.resolve_path(field_type, path_length);
+                        let path = self.resolve_path(field_type, path_length)?;
 
-                        resources.insert(Cow::Borrowed(resource_name), path);
+                        resources.insert(resource_name, path);
                     }
 
                     current_resource.relative_path_package_resources = Some(resources);
                 }
 
                 ResourceField::RelativeFilesystemDistributionResource => {
-                    let resource_count = self.reader.read_u32::<LittleEndian>().or_else(|_| {
-                        Err("failed reading package distribution relative path item count")
-                    })? as usize;
+                    let resource_count = self.reader.read_index_u32(
+                        "failed reading package distribution relative path item count",
+                    )?;
 
-                    let mut resources = HashMap::with_capacity(resource_count);
+                    let mut resources =
+                        HashMap::with_capacity(resource_count.min(self.reader.remaining()));
 
                     for _ in 0..resource_count {
-                        let name_length =
-                            self.reader.read_u16::<LittleEndian>().or_else(|_| {
-                                Err("failed reading package distribution metadata name")
-                            })? as usize;
+                        let name_length = self
+                            .reader
+                            .read_index_u16("failed reading package distribution metadata name")?;
 
-                        let name = unsafe {
-                            std::str::from_utf8_unchecked(
-                                self.resolve_blob_data(field_type, name_length),
-                            )
-                        };
+                        let name = self.resolve_blob_str(field_type, name_length)?;
 
-                        let path_length =
-                            self.reader.read_u32::<LittleEndian>().or_else(|_| {
-                                Err("failed reading package distribution path length")
-                            })? as usize;
+                        let path_length = self
+                            .reader
+                            .read_index_u32("failed reading package distribution path length")?;
 
-                        let path = self.resolve_path(field_type, path_length);
+                        let path = self.resolve_path(field_type, path_length)?;
 
-                        resources.insert(Cow::Borrowed(name), path);
+                        resources.insert(name, path);
                     }
 
                     current_resource.relative_path_distribution_resources = Some(resources);
                 }
+
+                ResourceField::InMemoryDataFile => {
+                    if self.version < 2 {
+                        return Err(ParseError::malformed(
+                            "data files not supported by format version",
+                            field_offset,
+                        ));
+                    }
+
+                    let l = self
+                        .reader
+                        .read_index_u64("failed reading data file length")?;
+
+                    let l = self.read_in_memory_data_length(
+                        &mut current_resource,
+                        field_type,
+                        None,
+                        l,
+                        true,
+                    )?;
+
+                    current_resource.in_memory_data_file =
+                        Some(self.resolve_blob_data(field_type, l)?);
+                }
+
+                ResourceField::RelativeFilesystemDataFile => {
+                    if self.version < 2 {
+                        return Err(ParseError::malformed(
+                            "data files not supported by format version",
+                            field_offset,
+                        ));
+                    }
+
+                    let path_length = self
+                        .reader
+                        .read_index_u32("failed reading data file relative path length")?;
+
+                    let path = self.resolve_path(field_type, path_length)?;
+
+                    current_resource.relative_path_data_file = Some(path);
+                }
             }
         }
     }
 }
 
 impl<'a> Iterator for ResourceParserIterator<'a> {
-    type Item = Result<Resource<'a, u8>, &'static str>;
+    type Item = Result<Resource<'a, u8>, ParseError>;
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.done {
@@ -457,174 +942,680 @@ impl<'a> Iterator for ResourceParserIterator<'a> {
                 Some(entry) => Some(Ok(entry)),
                 None => None,
             },
-            Err(e) => Some(Err(e)),
+            Err(e) => {
+                // State is unreliable after an error. So stop iterating.
+                self.done = true;
+
+                Some(Err(e))
+            }
         }
     }
 }
 
-pub fn load_resources<'a>(data: &'a [u8]) -> Result<ResourceParserIterator<'a>, &'static str> {
-    if data.len() < HEADER_V1.len() {
-        return Err("error reading 8 byte header");
+/// Random access to resources in a payload having a lookup index.
+///
+/// Resources are found by binary searching the lookup index by name. Only
+/// the resources index entry of a found resource is parsed.
+pub struct IndexedResources<'a> {
+    /// Parser holding state of the payload.
+    ///
+    /// It is never advanced. Instead, a parser positioned at a resource's
+    /// entry is derived from it for every lookup.
+    parser: ResourceParserIterator<'a>,
+    lookup_index: &'a [u8],
+    row_length: usize,
+    /// Decompressed payloads of compressed blob sections, keyed by resource field.
+    ///
+    /// Sections are decompressed the first time a lookup needs them and
+    /// shared by subsequent lookups.
+    decompressed_sections: Mutex<HashMap<u8, Arc<Vec<u8>>>>,
+}
+
+impl<'a> std::fmt::Debug for IndexedResources<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IndexedResources")
+            .field("len", &self.len())
+            .finish()
     }
+}
 
-    let header = &data[0..8];
+impl<'a> IndexedResources<'a> {
+    /// The number of resources in the payload.
+    pub fn len(&self) -> usize {
+        self.parser.claimed_resources_count
+    }
 
-    if header == HEADER_V1 {
-        load_resources_v1(&data[8..])
-    } else {
-        Err("unrecognized file format")
+    /// Whether the payload has no resources.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
     }
-}
 
-fn load_resources_v1<'a>(data: &'a [u8]) -> Result<ResourceParserIterator<'a>, &'static str> {
-    let mut reader = Cursor::new(data);
+    /// Read a `u32` from a row of the lookup index.
+    fn row_u32(row: &[u8], offset: usize) -> usize {
+        u32::from_le_bytes([
+            row[offset],
+            row[offset + 1],
+            row[offset + 2],
+            row[offset + 3],
+        ]) as usize
+    }
 
-    let blob_section_count = reader
-        .read_u8()
-        .or_else(|_| Err("failed reading blob section count"))?;
-    let blob_index_length = reader
-        .read_u32::<LittleEndian>()
-        .or_else(|_| Err("failed reading blob index length"))? as usize;
-    let resources_count = reader
-        .read_u32::<LittleEndian>()
-        .or_else(|_| Err("failed reading resources count"))? as usize;
-    let resources_index_length = reader
-        .read_u32::<LittleEndian>()
-        .or_else(|_| Err("failed reading resources index length"))?
-        as usize;
+    /// Run a function with the (decompressed) data of the resource names blob section.
+    ///
+    /// The section is decompressed on first use if it is compressed.
+    fn with_names_section<T>(
+        &self,
+        f: impl FnOnce(&[u8]) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        let field: u8 = ResourceField::ModuleName.into();
+
+        let state = match self.parser.blob_sections[field as usize] {
+            Some(state) => state,
+            None => {
+                return Err(ParseError::Invalid(
+                    "lookup index requires resource names blob section",
+                ))
+            }
+        };
 
-    let mut current_blob_field = None;
-    let mut current_blob_raw_payload_length = None;
-    let mut current_blob_interior_padding = None;
-    let mut blob_entry_count = 0;
-    let mut blob_sections = Vec::with_capacity(blob_section_count as usize);
+        if state.compression.is_some() {
+            let data = self.decompressed_section(field, &state)?;
+            f(&data)
+        } else {
+            // Section bounds are validated when the payload is loaded.
+            f(
+                &self.parser.data
+                    [state.start_offset..state.start_offset + state.raw_payload_length],
+            )
+        }
+    }
 
-    if blob_section_count != 0 || blob_index_length != 0 {
-        loop {
-            let field_type = reader
-                .read_u8()
-                .or_else(|_| Err("failed reading blob section field type"))?;
+    /// Obtain the decompressed payload of a compressed blob section.
+    fn decompressed_section(
+        &self,
+        field: u8,
+        state: &BlobSectionReadState,
+    ) -> Result<Arc<Vec<u8>>, ParseError> {
+        let mut sections = self.decompressed_sections.lock().unwrap();
 
-            let field_type = BlobSectionField::try_from(field_type)?;
+        if let Some(data) = sections.get(&field) {
+            return Ok(data.clone());
+        }
 
-            match field_type {
-                BlobSectionField::EndOfIndex => break,
-                BlobSectionField::StartOfEntry => {
-                    blob_entry_count += 1;
-                    current_blob_field = None;
-                    current_blob_raw_payload_length = None;
-                    current_blob_interior_padding = None;
-                }
-                BlobSectionField::EndOfEntry => {
-                    if current_blob_field.is_none() {
-                        return Err("blob resource field is required");
-                    }
-                    if current_blob_raw_payload_length.is_none() {
-                        return Err("blob raw payload length is required");
-                    }
+        let data = Arc::new(decompress_section_data(self.parser.data, field, state)?);
+        sections.insert(field, data.clone());
 
-                    blob_sections.push(BlobSection {
-                        resource_field: current_blob_field.unwrap(),
-                        raw_payload_length: current_blob_raw_payload_length.unwrap(),
-                        interior_padding: current_blob_interior_padding,
-                    });
+        Ok(data)
+    }
 
-                    current_blob_field = None;
-                    current_blob_raw_payload_length = None;
-                    current_blob_interior_padding = None;
-                }
-                BlobSectionField::ResourceFieldType => {
-                    let field = reader
-                        .read_u8()
-                        .or_else(|_| Err("failed reading blob resource field value"))?;
-                    current_blob_field = Some(field);
+    /// Resolve the name of the resource described by a lookup index row.
+    ///
+    /// `names` is the data of the resource names blob section.
+    fn row_name<'n>(&self, names: &'n [u8], row: &[u8]) -> Result<&'n [u8], ParseError> {
+        let field: u8 = ResourceField::ModuleName.into();
+        let position = self
+            .parser
+            .section_fields
+            .iter()
+            .position(|f| *f == field)
+            .ok_or(ParseError::Invalid(
+                "lookup index requires resource names blob section",
+            ))?;
+
+        let name_length = u16::from_le_bytes([row[4], row[5]]) as usize;
+        let offset = Self::row_u32(row, 6 + 4 * position);
+
+        if offset + name_length > names.len() {
+            return Err(ParseError::BlobOutOfBounds {
+                field,
+                offset,
+                length: name_length,
+                section_length: names.len(),
+            });
+        }
+
+        Ok(&names[offset..offset + name_length])
+    }
+
+    /// Find the lookup index row of a named resource.
+    fn find_row(&self, name: &str) -> Result<Option<&'a [u8]>, ParseError> {
+        let lookup_index = self.lookup_index;
+
+        self.with_names_section(|names| {
+            let mut low = 0;
+            let mut high = self.len();
+
+            while low < high {
+                let middle = low + (high - low) / 2;
+                let row = &lookup_index[middle * self.row_length..(middle + 1) * self.row_length];
+
+                match self.row_name(names, row)?.cmp(name.as_bytes()) {
+                    std::cmp::Ordering::Equal => return Ok(Some(row)),
+                    std::cmp::Ordering::Less => low = middle + 1,
+                    std::cmp::Ordering::Greater => high = middle,
                 }
-                BlobSectionField::RawPayloadLength => {
-                    let l = reader
-                        .read_u64::<LittleEndian>()
-                        .or_else(|_| Err("failed reading raw payload length"))?;
-                    current_blob_raw_payload_length = Some(l as usize);
+            }
+
+            Ok(None)
+        })
+    }
+
+    /// Obtain the names of resources starting with a prefix, in sorted order.
+    ///
+    /// Only the lookup index and resource names are read, so this is cheap
+    /// compared to parsing all resources.
+    pub fn names_with_prefix(&self, prefix: &str) -> Result<Vec<String>, ParseError> {
+        let lookup_index = self.lookup_index;
+
+        self.with_names_section(|names| {
+            let row = |i: usize| &lookup_index[i * self.row_length..(i + 1) * self.row_length];
+
+            // Find the first row whose name isn't less than the prefix.
+            let mut low = 0;
+            let mut high = self.len();
+
+            while low < high {
+                let middle = low + (high - low) / 2;
+
+                if self.row_name(names, row(middle))? < prefix.as_bytes() {
+                    low = middle + 1;
+                } else {
+                    high = middle;
                 }
-                BlobSectionField::InteriorPadding => {
-                    let padding = reader
-                        .read_u8()
-                        .or_else(|_| Err("failed reading interior padding field value"))?;
+            }
 
-                    current_blob_interior_padding = Some(match padding {
-                        0x01 => BlobInteriorPadding::None,
-                        0x02 => BlobInteriorPadding::Null,
-                        _ => return Err("invalid value for interior padding field"),
-                    });
+            let mut matches = vec![];
+
+            for i in low..self.len() {
+                let name = self.row_name(names, row(i))?;
+
+                if !name.starts_with(prefix.as_bytes()) {
+                    break;
                 }
+
+                matches.push(
+                    String::from_utf8(name.to_vec())
+                        .map_err(|_| ParseError::Invalid("resource name is not valid UTF-8"))?,
+                );
             }
-        }
+
+            Ok(matches)
+        })
     }
 
-    if blob_entry_count != blob_section_count {
-        return Err("mismatch between blob sections count");
+    /// Whether a resource with the given name exists.
+    pub fn contains(&self, name: &str) -> Result<bool, ParseError> {
+        Ok(self.find_row(name)?.is_some())
     }
 
-    // Array indexing resource field to current payload offset within that section.
-    let mut blob_offsets: [Option<BlobSectionReadState>; 256] = [None; 256];
+    /// Obtain the resource having the given name.
+    ///
+    /// Returns `Ok(None)` if no such resource exists.
+    pub fn get(&self, name: &str) -> Result<Option<Resource<'a, u8>>, ParseError> {
+        let row = match self.find_row(name)? {
+            Some(row) => row,
+            None => return Ok(None),
+        };
 
-    // Global payload offset where blobs data starts.
-    let blob_start_offset: usize =
-            // Global header.
-            1 + 4 + 4 + 4
-            + blob_index_length
-            + resources_index_length
-        ;
-    // Current offset from start of blobs data.
-    let mut current_blob_offset = 0;
+        // Position a parser at the resource's entry and blobs.
+        let mut blob_sections = self.parser.blob_sections;
 
-    for section in &blob_sections {
-        let section_start_offset = blob_start_offset + current_blob_offset;
-        blob_offsets[section.resource_field as usize] = Some(BlobSectionReadState {
-            offset: section_start_offset,
+        for (i, field) in self.parser.section_fields.iter().enumerate() {
+            if let Some(state) = blob_sections[*field as usize].as_mut() {
+                state.offset = Self::row_u32(row, 6 + 4 * i);
+            }
+        }
+
+        // Confine the parser to the resources index.
+        let entry_offset = self.parser.resources_index_offset + Self::row_u32(row, 0);
+        let mut reader = Cursor::new(*self.parser.reader.get_ref());
+        reader.set_position(entry_offset as u64);
+
+        let mut parser = ResourceParserIterator {
+            done: false,
+            version: self.parser.version,
+            data: self.parser.data,
+            reader,
+            blob_sections,
+            decompressed_sections: self.decompressed_sections.lock().unwrap().clone(),
+            claimed_resources_count: 1,
+            read_resources_count: 0,
+            global_header_length: self.parser.global_header_length,
+            payload_digest: self.parser.payload_digest,
+            resources_index_offset: self.parser.resources_index_offset,
+            section_fields: vec![],
+            lookup_index: None,
+        };
+
+        let resource = parser.parse_next()?;
+
+        // Retain sections decompressed for this resource for later lookups.
+        {
+            let mut sections = self.decompressed_sections.lock().unwrap();
+
+            for (field, data) in parser.decompressed_sections {
+                sections.entry(field).or_insert(data);
+            }
+        }
+
+        match resource {
+            Some(resource) if resource.name == name => Ok(Some(resource)),
+            _ => Err(ParseError::malformed(
+                "lookup index does not match resources index",
+                HEADER_V1.len() + entry_offset,
+            )),
+        }
+    }
+}
+
+/// Decompress the payload of a compressed blob section.
+///
+/// Returns an empty payload for uncompressed sections.
+fn decompress_section_data(
+    data: &[u8],
+    field: u8,
+    state: &BlobSectionReadState,
+) -> Result<Vec<u8>, ParseError> {
+    match state.compression {
+        Some((algorithm, decompressed_length)) => {
+            // Section bounds are validated when the payload is loaded.
+            let raw = &data[state.start_offset..state.start_offset + state.raw_payload_length];
+
+            algorithm
+                .decompress(raw, decompressed_length)
+                .map_err(|message| ParseError::Decompression { field, message })
+        }
+        None => Ok(vec![]),
+    }
+}
+
+pub fn load_resources<'a>(data: &'a [u8]) -> Result<ResourceParserIterator<'a>, ParseError> {
+    if data.len() < HEADER_V1.len() {
+        return Err(ParseError::malformed("error reading 8 byte header", 0));
+    }
+
+    let header = &data[0..8];
+
+    if header == HEADER_V1 {
+        load_resources_v1(&data[8..])
+    } else if header == HEADER_V2 {
+        load_resources_v2(&data[8..])
+    } else {
+        Err(ParseError::malformed("unrecognized file format", 0))
+    }
+}
+
+fn load_resources_v1<'a>(data: &'a [u8]) -> Result<ResourceParserIterator<'a>, ParseError> {
+    load_resources_versioned(data, 1)
+}
+
+fn load_resources_v2<'a>(data: &'a [u8]) -> Result<ResourceParserIterator<'a>, ParseError> {
+    load_resources_versioned(data, 2)
+}
+
+/// Parse the indices of a payload of the given format version.
+///
+/// `data` is the payload without the leading 8 byte header.
+fn load_resources_versioned<'a>(
+    data: &'a [u8],
+    version: u8,
+) -> Result<ResourceParserIterator<'a>, ParseError> {
+    let mut reader = Cursor::new(data);
+
+    let blob_section_count = reader.read_index_u8("failed reading blob section count")?;
+    let blob_index_length = reader.read_index_u32("failed reading blob index length")?;
+    let resources_count = reader.read_index_u32("failed reading resources count")?;
+    let resources_index_length = reader.read_index_u32("failed reading resources index length")?;
+
+    // Version 2 appends an optional payload digest to the global header.
+    let payload_digest = if version >= 2 {
+        let digest_offset = reader.offset();
+        let digest_length = reader.read_index_u8("failed reading payload digest length")? as usize;
+
+        match digest_length {
+            0 => None,
+            DIGEST_LENGTH => {
+                let start = reader.position() as usize;
+
+                if data.len() < start + DIGEST_LENGTH {
+                    return Err(reader.malformed("failed reading payload digest"));
+                }
+
+                reader.set_position((start + DIGEST_LENGTH) as u64);
+
+                Some(&data[start..start + DIGEST_LENGTH])
+            }
+            _ => {
+                return Err(ParseError::malformed(
+                    "invalid payload digest length",
+                    digest_offset,
+                ))
+            }
+        }
+    } else {
+        None
+    };
+
+    // Version 2 also declares the length of an optional lookup index.
+    let lookup_index_length = if version >= 2 {
+        reader.read_index_u32("failed reading lookup index length")?
+    } else {
+        0
+    };
+
+    let global_header_length = reader.position() as usize;
+
+    // The indices must fit in the data. Blob sections are validated below.
+    let indices_length = blob_index_length
+        .checked_add(resources_index_length)
+        .and_then(|l| l.checked_add(lookup_index_length))
+        .and_then(|l| l.checked_add(global_header_length));
+
+    match indices_length {
+        Some(length) if length <= data.len() => {}
+        _ => {
+            return Err(ParseError::malformed(
+                "indices extend beyond end of data",
+                HEADER_V1.len() + global_header_length,
+            ))
+        }
+    }
+
+    let mut current_blob_field = None;
+    let mut current_blob_raw_payload_length = None;
+    let mut current_blob_interior_padding = None;
+    let mut current_blob_compression = None;
+    let mut current_blob_decompressed_payload_length = None;
+    let mut current_blob_alignment = None;
+    let mut current_blob_entry_alignment = None;
+    let mut blob_entry_count = 0;
+    let mut blob_sections = Vec::with_capacity(blob_section_count as usize);
+
+    // Confine reading of the blob index to its declared length.
+    let blob_index_end = global_header_length + blob_index_length;
+    let mut blob_reader = Cursor::new(&data[..blob_index_end]);
+    blob_reader.set_position(global_header_length as u64);
+
+    if blob_section_count != 0 || blob_index_length != 0 {
+        loop {
+            let field_offset = blob_reader.offset();
+
+            let field_type: BlobSectionField =
+                blob_reader.read_index_enum("failed reading blob section field type")?;
+
+            let malformed = |message| ParseError::malformed(message, field_offset);
+
+            match field_type {
+                BlobSectionField::EndOfIndex => break,
+                BlobSectionField::StartOfEntry => {
+                    blob_entry_count += 1;
+                    current_blob_field = None;
+                    current_blob_raw_payload_length = None;
+                    current_blob_interior_padding = None;
+                    current_blob_compression = None;
+                    current_blob_decompressed_payload_length = None;
+                    current_blob_alignment = None;
+                    current_blob_entry_alignment = None;
+                }
+                BlobSectionField::EndOfEntry => {
+                    let resource_field = current_blob_field
+                        .ok_or_else(|| malformed("blob resource field is required"))?;
+                    let raw_payload_length = current_blob_raw_payload_length
+                        .ok_or_else(|| malformed("blob raw payload length is required"))?;
+                    if current_blob_compression.is_some()
+                        && current_blob_decompressed_payload_length.is_none()
+                    {
+                        return Err(malformed("blob decompressed payload length is required"));
+                    }
+                    if current_blob_compression.is_some() && current_blob_entry_alignment.is_some()
+                    {
+                        return Err(malformed(
+                            "blob entry alignment not supported by compressed sections",
+                        ));
+                    }
+                    if blob_sections
+                        .iter()
+                        .any(|section: &BlobSection| section.resource_field == resource_field)
+                    {
+                        return Err(malformed("duplicate blob section for resource field"));
+                    }
+
+                    blob_sections.push(BlobSection {
+                        resource_field,
+                        raw_payload_length,
+                        interior_padding: current_blob_interior_padding,
+                        compression: current_blob_compression,
+                        decompressed_payload_length: current_blob_decompressed_payload_length,
+                        alignment: current_blob_alignment,
+                        entry_alignment: current_blob_entry_alignment,
+                    });
+
+                    current_blob_field = None;
+                    current_blob_raw_payload_length = None;
+                    current_blob_interior_padding = None;
+                    current_blob_compression = None;
+                    current_blob_decompressed_payload_length = None;
+                    current_blob_alignment = None;
+                    current_blob_entry_alignment = None;
+                }
+                BlobSectionField::ResourceFieldType => {
+                    let field =
+                        blob_reader.read_index_u8("failed reading blob resource field value")?;
+                    current_blob_field = Some(field);
+                }
+                BlobSectionField::RawPayloadLength => {
+                    let l = blob_reader.read_index_u64("failed reading raw payload length")?;
+                    current_blob_raw_payload_length = Some(l);
+                }
+                BlobSectionField::InteriorPadding => {
+                    let padding =
+                        blob_reader.read_index_u8("failed reading interior padding field value")?;
+
+                    current_blob_interior_padding = Some(match padding {
+                        0x01 => BlobInteriorPadding::None,
+                        0x02 => BlobInteriorPadding::Null,
+                        _ => return Err(malformed("invalid value for interior padding field")),
+                    });
+                }
+                BlobSectionField::Compression => {
+                    if version < 2 {
+                        return Err(malformed(
+                            "blob compression not supported by format version",
+                        ));
+                    }
+
+                    current_blob_compression = Some(
+                        blob_reader.read_index_enum("failed reading compression field value")?,
+                    );
+                }
+                BlobSectionField::DecompressedPayloadLength => {
+                    if version < 2 {
+                        return Err(malformed(
+                            "blob compression not supported by format version",
+                        ));
+                    }
+
+                    let l =
+                        blob_reader.read_index_u64("failed reading decompressed payload length")?;
+                    current_blob_decompressed_payload_length = Some(l);
+                }
+                BlobSectionField::Alignment => {
+                    if version < 2 {
+                        return Err(malformed("blob alignment not supported by format version"));
+                    }
+
+                    current_blob_alignment = Some(read_alignment(&mut blob_reader)?);
+                }
+                BlobSectionField::EntryAlignment => {
+                    if version < 2 {
+                        return Err(malformed("blob alignment not supported by format version"));
+                    }
+
+                    current_blob_entry_alignment = Some(read_alignment(&mut blob_reader)?);
+                }
+            }
+        }
+    }
+
+    if blob_entry_count != blob_section_count {
+        return Err(blob_reader.malformed("mismatch between blob sections count"));
+    }
+
+    if blob_reader.position() as usize != blob_index_end {
+        return Err(blob_reader.malformed("blob index length mismatch"));
+    }
+
+    // Array indexing resource field to current payload offset within that section.
+    let mut blob_offsets: [Option<BlobSectionReadState>; 256] = [None; 256];
+
+    // Global payload offset where blobs data starts.
+    let blob_start_offset: usize =
+        global_header_length + blob_index_length + resources_index_length + lookup_index_length;
+    // Current offset from start of blobs data.
+    let mut current_blob_offset = 0;
+
+    for section in &blob_sections {
+        // Alignment is relative to the start of the packed data, which
+        // includes the magic header.
+        if let Some(alignment) = section.alignment {
+            current_blob_offset += alignment_padding(
+                HEADER_V1.len() + blob_start_offset + current_blob_offset,
+                alignment,
+            );
+        }
+
+        let section_start_offset = blob_start_offset + current_blob_offset;
+
+        // Every blob section must fit in the data. This allows blobs to be
+        // sliced from the data after they are validated against their section.
+        let section_end_offset = match section_start_offset.checked_add(section.raw_payload_length)
+        {
+            Some(offset) if offset <= data.len() => offset,
+            _ => {
+                return Err(ParseError::SectionOutOfBounds {
+                    field: section.resource_field,
+                    offset: HEADER_V1.len() + section_start_offset,
+                    length: section.raw_payload_length,
+                    data_length: HEADER_V1.len() + data.len(),
+                })
+            }
+        };
+
+        blob_offsets[section.resource_field as usize] = Some(BlobSectionReadState {
+            start_offset: section_start_offset,
+            raw_payload_length: section.raw_payload_length,
+            offset: 0,
             interior_padding: match section.interior_padding {
                 Some(padding) => padding,
                 None => BlobInteriorPadding::None,
             },
+            compression: match (section.compression, section.decompressed_payload_length) {
+                (Some(algorithm), Some(length)) => Some((algorithm, length)),
+                _ => None,
+            },
+            entry_alignment: section.entry_alignment,
         });
-        current_blob_offset += section.raw_payload_length;
+        current_blob_offset = section_end_offset - blob_start_offset;
     }
 
+    // The lookup index follows the resources index.
+    let resources_index_offset = global_header_length + blob_index_length;
+    let lookup_index_offset = resources_index_offset + resources_index_length;
+
+    let lookup_index = if lookup_index_length > 0 {
+        Some(&data[lookup_index_offset..lookup_index_offset + lookup_index_length])
+    } else {
+        None
+    };
+
+    // Confine reading of resources to the resources index.
+    let mut reader = Cursor::new(&data[..lookup_index_offset]);
+    reader.set_position(resources_index_offset as u64);
+
     Ok(ResourceParserIterator {
         done: resources_index_length == 0 || resources_count == 0,
+        version,
         data,
         reader,
         blob_sections: blob_offsets,
+        decompressed_sections: HashMap::new(),
         claimed_resources_count: resources_count,
         read_resources_count: 0,
+        global_header_length,
+        payload_digest,
+        resources_index_offset,
+        section_fields: blob_sections
+            .iter()
+            .map(|section| section.resource_field)
+            .collect(),
+        lookup_index,
     })
 }
 
+/// Read an alignment value from the blob index.
+fn read_alignment(reader: &mut Cursor<&[u8]>) -> Result<usize, ParseError> {
+    let offset = reader.offset();
+    let alignment = reader.read_index_u32("failed reading blob alignment")?;
+
+    if alignment.is_power_of_two() {
+        Ok(alignment)
+    } else {
+        Err(ParseError::malformed(
+            "blob alignment must be a power of 2",
+            offset,
+        ))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use {
         super::*,
         crate::data::{BlobInteriorPadding, Resource},
-        crate::writer::write_embedded_resources_v1,
+        crate::writer::{
+            write_embedded_resources_v1, write_embedded_resources_v2, ResourcesWriter, WriteOptions,
+        },
         std::collections::BTreeMap,
     };
 
+    /// Rewrite a version 2 payload without a payload digest or lookup index
+    /// as version 1.
+    fn downgrade_to_v1(data: &mut Vec<u8>) {
+        assert_eq!(&data[21..26], &[0, 0, 0, 0, 0]);
+        data[7] = 0x01;
+        // Version 1 doesn't have the payload digest and lookup index lengths.
+        data.drain(21..26);
+    }
+
     #[test]
     fn test_too_short_header() {
         let data = b"foo";
 
         let res = load_resources(data);
-        assert_eq!(res.err(), Some("error reading 8 byte header"));
+        assert_eq!(
+            res.err(),
+            Some(ParseError::Malformed {
+                message: "error reading 8 byte header",
+                offset: 0
+            })
+        );
     }
 
     #[test]
     fn test_unrecognized_header() {
         let data = b"pyembed\x00";
         let res = load_resources(data);
-        assert_eq!(res.err(), Some("unrecognized file format"));
+        assert_eq!(
+            res.err().map(|e| e.message()),
+            Some("unrecognized file format")
+        );
 
-        let data = b"pyembed\x02";
+        let data = b"pyembed\x03";
         let res = load_resources(data);
-        assert_eq!(res.err(), Some("unrecognized file format"));
+        assert_eq!(
+            res.err().map(|e| e.message()),
+            Some("unrecognized file format")
+        );
     }
 
     #[test]
@@ -657,7 +1648,10 @@ mod tests {
         let mut res = load_resources(data).unwrap();
         assert_eq!(
             res.next(),
-            Some(Err("mismatch between advertised index count and actual"))
+            Some(Err(ParseError::Malformed {
+                message: "mismatch between advertised index count and actual",
+                offset: 21
+            }))
         );
         assert_eq!(res.next(), None);
     }
@@ -667,7 +1661,13 @@ mod tests {
         let data =
             b"pyembed\x01\x00\x01\x00\x00\x00\x01\x00\x00\x00\x03\x00\x00\x00\x00\x01\xff\x00";
         let mut res = load_resources(data).unwrap();
-        assert_eq!(res.next(), Some(Err("resource name field is required")));
+        assert_eq!(
+            res.next(),
+            Some(Err(ParseError::Malformed {
+                message: "resource name field is required",
+                offset: 23
+            }))
+        );
         assert_eq!(res.next(), None);
     }
 
@@ -683,7 +1683,7 @@ mod tests {
 
         let resources = load_resources(&data)
             .unwrap()
-            .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+            .collect::<Result<Vec<Resource<u8>>, ParseError>>()
             .unwrap();
 
         assert_eq!(resources.len(), 1);
@@ -714,7 +1714,7 @@ mod tests {
         write_embedded_resources_v1(&[resource1, resource2], &mut data, None).unwrap();
         let resources = load_resources(&data)
             .unwrap()
-            .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+            .collect::<Result<Vec<Resource<u8>>, ParseError>>()
             .unwrap();
 
         assert_eq!(resources.len(), 2);
@@ -760,7 +1760,7 @@ mod tests {
         .unwrap();
         let resources = load_resources(&data)
             .unwrap()
-            .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+            .collect::<Result<Vec<Resource<u8>>, ParseError>>()
             .unwrap();
 
         assert_eq!(resources.len(), 2);
@@ -796,7 +1796,7 @@ mod tests {
         write_embedded_resources_v1(&[resource], &mut data, None).unwrap();
         let resources = load_resources(&data)
             .unwrap()
-            .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+            .collect::<Result<Vec<Resource<u8>>, ParseError>>()
             .unwrap();
 
         assert_eq!(resources.len(), 1);
@@ -827,7 +1827,7 @@ mod tests {
         write_embedded_resources_v1(&[resource], &mut data, None).unwrap();
         let resources = load_resources(&data)
             .unwrap()
-            .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+            .collect::<Result<Vec<Resource<u8>>, ParseError>>()
             .unwrap();
 
         assert_eq!(resources.len(), 1);
@@ -861,7 +1861,7 @@ mod tests {
         write_embedded_resources_v1(&[resource], &mut data, None).unwrap();
         let resources = load_resources(&data)
             .unwrap()
-            .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+            .collect::<Result<Vec<Resource<u8>>, ParseError>>()
             .unwrap();
 
         assert_eq!(resources.len(), 1);
@@ -895,7 +1895,7 @@ mod tests {
         write_embedded_resources_v1(&[resource], &mut data, None).unwrap();
         let resources = load_resources(&data)
             .unwrap()
-            .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+            .collect::<Result<Vec<Resource<u8>>, ParseError>>()
             .unwrap();
 
         assert_eq!(resources.len(), 1);
@@ -929,7 +1929,7 @@ mod tests {
         write_embedded_resources_v1(&[resource], &mut data, None).unwrap();
         let resources = load_resources(&data)
             .unwrap()
-            .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+            .collect::<Result<Vec<Resource<u8>>, ParseError>>()
             .unwrap();
 
         assert_eq!(resources.len(), 1);
@@ -973,7 +1973,7 @@ mod tests {
         write_embedded_resources_v1(&[resource], &mut data, None).unwrap();
         let resources = load_resources(&data)
             .unwrap()
-            .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+            .collect::<Result<Vec<Resource<u8>>, ParseError>>()
             .unwrap();
 
         assert_eq!(resources.len(), 1);
@@ -1002,7 +2002,7 @@ mod tests {
         write_embedded_resources_v1(&[resource], &mut data, None).unwrap();
         let resources = load_resources(&data)
             .unwrap()
-            .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+            .collect::<Result<Vec<Resource<u8>>, ParseError>>()
             .unwrap();
 
         assert_eq!(resources.len(), 1);
@@ -1027,7 +2027,7 @@ mod tests {
         write_embedded_resources_v1(&[resource], &mut data, None).unwrap();
         let resources = load_resources(&data)
             .unwrap()
-            .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+            .collect::<Result<Vec<Resource<u8>>, ParseError>>()
             .unwrap();
 
         assert_eq!(resources.len(), 1);
@@ -1063,7 +2063,7 @@ mod tests {
         write_embedded_resources_v1(&[resource], &mut data, None).unwrap();
         let resources = load_resources(&data)
             .unwrap()
-            .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+            .collect::<Result<Vec<Resource<u8>>, ParseError>>()
             .unwrap();
 
         assert_eq!(resources.len(), 1);
@@ -1088,7 +2088,7 @@ mod tests {
         write_embedded_resources_v1(&[resource], &mut data, None).unwrap();
         let resources = load_resources(&data)
             .unwrap()
-            .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+            .collect::<Result<Vec<Resource<u8>>, ParseError>>()
             .unwrap();
 
         assert_eq!(resources.len(), 1);
@@ -1117,7 +2117,7 @@ mod tests {
         write_embedded_resources_v1(&[resource], &mut data, None).unwrap();
         let resources = load_resources(&data)
             .unwrap()
-            .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+            .collect::<Result<Vec<Resource<u8>>, ParseError>>()
             .unwrap();
 
         assert_eq!(resources.len(), 1);
@@ -1146,7 +2146,7 @@ mod tests {
         write_embedded_resources_v1(&[resource], &mut data, None).unwrap();
         let resources = load_resources(&data)
             .unwrap()
-            .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+            .collect::<Result<Vec<Resource<u8>>, ParseError>>()
             .unwrap();
 
         assert_eq!(resources.len(), 1);
@@ -1175,7 +2175,7 @@ mod tests {
         write_embedded_resources_v1(&[resource], &mut data, None).unwrap();
         let resources = load_resources(&data)
             .unwrap()
-            .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+            .collect::<Result<Vec<Resource<u8>>, ParseError>>()
             .unwrap();
 
         assert_eq!(resources.len(), 1);
@@ -1204,7 +2204,7 @@ mod tests {
         write_embedded_resources_v1(&[resource], &mut data, None).unwrap();
         let resources = load_resources(&data)
             .unwrap()
-            .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+            .collect::<Result<Vec<Resource<u8>>, ParseError>>()
             .unwrap();
 
         assert_eq!(resources.len(), 1);
@@ -1239,7 +2239,7 @@ mod tests {
         write_embedded_resources_v1(&[resource], &mut data, None).unwrap();
         let resources = load_resources(&data)
             .unwrap()
-            .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+            .collect::<Result<Vec<Resource<u8>>, ParseError>>()
             .unwrap();
 
         assert_eq!(resources.len(), 1);
@@ -1274,7 +2274,7 @@ mod tests {
         write_embedded_resources_v1(&[resource], &mut data, None).unwrap();
         let resources = load_resources(&data)
             .unwrap()
-            .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+            .collect::<Result<Vec<Resource<u8>>, ParseError>>()
             .unwrap();
 
         assert_eq!(resources.len(), 1);
@@ -1294,8 +2294,7 @@ mod tests {
     }
 
     #[allow(clippy::cognitive_complexity)]
-    #[test]
-    fn test_all_fields() {
+    fn resource_all_fields() -> Resource<'static, u8> {
         let mut in_memory_resources = HashMap::new();
         in_memory_resources.insert(
             Cow::from("foo".to_string()),
@@ -1324,7 +2323,7 @@ mod tests {
             Cow::from(Path::new("package/resource.txt")),
         );
 
-        let resource = Resource {
+        Resource {
             flavor: ResourceFlavor::Module,
             name: Cow::from("module"),
             is_package: true,
@@ -1345,13 +2344,24 @@ mod tests {
             relative_path_extension_module_shared_library: Some(Cow::from(Path::new("em_path"))),
             relative_path_package_resources: Some(relative_path_resources),
             relative_path_distribution_resources: Some(relative_path_distribution),
-        };
+            // Data files require version 2. See test_v2_data_files().
+            in_memory_data_file: None,
+            relative_path_data_file: None,
+            in_memory_compression: None,
+            in_memory_decompressed_lengths: None,
+            content_digest: None,
+        }
+    }
+
+    #[test]
+    fn test_all_fields() {
+        let resource = resource_all_fields();
 
         let mut data = Vec::new();
         write_embedded_resources_v1(&[resource], &mut data, None).unwrap();
         let resources = load_resources(&data)
             .unwrap()
-            .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+            .collect::<Result<Vec<Resource<u8>>, ParseError>>()
             .unwrap();
 
         assert_eq!(resources.len(), 1);
@@ -1446,4 +2456,1005 @@ mod tests {
             Some(&Cow::Borrowed(Path::new("package/resource.txt")))
         );
     }
+
+    #[test]
+    fn test_v2_no_compression() {
+        let resource = resource_all_fields();
+
+        let mut data = Vec::new();
+        write_embedded_resources_v2(&[resource.clone()], &mut data, &WriteOptions::default())
+            .unwrap();
+        assert_eq!(&data[0..8], b"pyembed\x02");
+
+        let resources = load_resources(&data)
+            .unwrap()
+            .collect::<Result<Vec<Resource<u8>>, ParseError>>()
+            .unwrap();
+
+        assert_eq!(resources, vec![resource]);
+
+        // Data is borrowed from the payload when sections aren't compressed.
+        match &resources[0].in_memory_bytecode {
+            Some(Cow::Borrowed(_)) => {}
+            _ => panic!("expected borrowed bytecode"),
+        }
+    }
+
+    #[test]
+    fn test_v2_compressed_sections() {
+        let resource = resource_all_fields();
+        let resource2 = Resource {
+            flavor: ResourceFlavor::Module,
+            name: Cow::from("module2"),
+            in_memory_bytecode: Some(Cow::from(b"bytecode2".to_vec())),
+            ..Resource::default()
+        };
+
+        for padding in &[None, Some(BlobInteriorPadding::Null)] {
+            let mut options = WriteOptions::default();
+            options.interior_padding = *padding;
+            for field in 0x02..0x16 {
+                options.section_compression.insert(
+                    ResourceField::try_from(field).unwrap(),
+                    CompressionAlgorithm::Zstd,
+                );
+            }
+
+            let mut data = Vec::new();
+            write_embedded_resources_v2(
+                &[resource.clone(), resource2.clone()],
+                &mut data,
+                &options,
+            )
+            .unwrap();
+
+            let resources = load_resources(&data)
+                .unwrap()
+                .collect::<Result<Vec<Resource<u8>>, ParseError>>()
+                .unwrap();
+
+            assert_eq!(resources, vec![resource.clone(), resource2.clone()]);
+        }
+    }
+
+    #[test]
+    fn test_v2_in_memory_sections_compressed() {
+        let resource = Resource {
+            flavor: ResourceFlavor::Module,
+            name: Cow::from("foo"),
+            in_memory_source: Some(Cow::from(b"import io\n".repeat(100))),
+            ..Resource::default()
+        };
+
+        let mut options = WriteOptions::default();
+        options.compress_in_memory_sections(CompressionAlgorithm::Zstd);
+
+        let mut data = Vec::new();
+        write_embedded_resources_v2(&[resource.clone()], &mut data, &options).unwrap();
+        assert!(data.len() < 1000);
+
+        let resources = load_resources(&data)
+            .unwrap()
+            .collect::<Result<Vec<Resource<u8>>, ParseError>>()
+            .unwrap();
+
+        assert_eq!(resources, vec![resource]);
+    }
+
+    #[test]
+    fn test_v1_compressed_section() {
+        let resource = Resource {
+            flavor: ResourceFlavor::Module,
+            name: Cow::from("foo"),
+            ..Resource::default()
+        };
+
+        let mut options = WriteOptions::default();
+        options
+            .section_compression
+            .insert(ResourceField::ModuleName, CompressionAlgorithm::Zstd);
+
+        let mut data = Vec::new();
+        write_embedded_resources_v2(&[resource], &mut data, &options).unwrap();
+
+        // Version 1 doesn't know about compression.
+        downgrade_to_v1(&mut data);
+        let res = load_resources(&data);
+        assert_eq!(
+            res.err().map(|e| e.message()),
+            Some("blob compression not supported by format version")
+        );
+    }
+
+    #[test]
+    fn test_v2_in_memory_compression() {
+        let resource = resource_all_fields();
+        let resource2 = Resource {
+            flavor: ResourceFlavor::Module,
+            name: Cow::from("module2"),
+            in_memory_bytecode: Some(Cow::from(b"bytecode2".to_vec())),
+            ..Resource::default()
+        };
+
+        for compress_sections in &[false, true] {
+            let mut options = WriteOptions::default();
+            options.resource_compression = Some(CompressionAlgorithm::Zstd);
+            if *compress_sections {
+                options.compress_in_memory_sections(CompressionAlgorithm::Zstd);
+            }
+
+            let mut data = Vec::new();
+            write_embedded_resources_v2(
+                &[resource.clone(), resource2.clone()],
+                &mut data,
+                &options,
+            )
+            .unwrap();
+
+            let resources = load_resources(&data)
+                .unwrap()
+                .collect::<Result<Vec<Resource<u8>>, ParseError>>()
+                .unwrap();
+
+            assert_eq!(resources.len(), 2);
+
+            let entry = &resources[1];
+            assert_eq!(
+                entry.in_memory_compression,
+                Some(CompressionAlgorithm::Zstd)
+            );
+            assert_ne!(
+                entry.in_memory_bytecode.as_ref().unwrap().as_ref(),
+                b"bytecode2"
+            );
+            assert_eq!(
+                entry.in_memory_decompressed_length(
+                    ResourceField::InMemoryBytecode,
+                    None,
+                    entry.in_memory_bytecode.as_ref().unwrap()
+                ),
+                Ok(9)
+            );
+            assert_eq!(
+                entry
+                    .resolve_in_memory_data(
+                        ResourceField::InMemoryBytecode,
+                        None,
+                        entry.in_memory_bytecode.as_ref().unwrap()
+                    )
+                    .unwrap()
+                    .as_ref(),
+                b"bytecode2"
+            );
+
+            // The stored decompressed length is enforced.
+            let mut corrupted = entry.clone();
+            corrupted
+                .in_memory_decompressed_lengths
+                .as_mut()
+                .unwrap()
+                .insert((ResourceField::InMemoryBytecode, None), 8);
+            assert_eq!(
+                corrupted.resolve_in_memory_data(
+                    ResourceField::InMemoryBytecode,
+                    None,
+                    entry.in_memory_bytecode.as_ref().unwrap()
+                ),
+                Err("decompressed data length mismatch")
+            );
+
+            let decompressed = resources
+                .iter()
+                .map(|r| r.decompress_in_memory_data())
+                .collect::<Result<Vec<_>, &'static str>>()
+                .unwrap();
+            assert_eq!(decompressed, vec![resource.clone(), resource2.clone()]);
+        }
+    }
+
+    #[test]
+    fn test_v1_in_memory_compression() {
+        let resource = Resource {
+            flavor: ResourceFlavor::Module,
+            name: Cow::from("foo"),
+            in_memory_source: Some(Cow::from(b"source".to_vec())),
+            ..Resource::default()
+        };
+
+        let mut options = WriteOptions::default();
+        options.resource_compression = Some(CompressionAlgorithm::Zstd);
+
+        let mut data = Vec::new();
+        write_embedded_resources_v2(&[resource], &mut data, &options).unwrap();
+
+        downgrade_to_v1(&mut data);
+        let res = load_resources(&data)
+            .unwrap()
+            .collect::<Result<Vec<Resource<u8>>, ParseError>>();
+        assert_eq!(
+            res.err().map(|e| e.message()),
+            Some("in-memory compression not supported by format version")
+        );
+    }
+
+    #[test]
+    fn test_v2_content_digest() {
+        let resource = resource_all_fields();
+
+        let options = WriteOptions {
+            resource_digests: true,
+            ..WriteOptions::default()
+        };
+
+        let mut data = Vec::new();
+        write_embedded_resources_v2(&[resource.clone()], &mut data, &options).unwrap();
+
+        let resources = load_resources(&data)
+            .unwrap()
+            .collect::<Result<Vec<Resource<u8>>, ParseError>>()
+            .unwrap();
+        assert_eq!(resources.len(), 1);
+
+        let entry = &resources[0];
+        assert_eq!(
+            entry.content_digest.as_ref().unwrap().as_ref(),
+            resource.compute_content_digest().as_slice()
+        );
+        assert!(entry.verify_content_digest().is_ok());
+
+        let mut tampered = entry.clone();
+        tampered.in_memory_source = Some(Cow::from(b"tampered".to_vec()));
+        assert_eq!(
+            tampered.verify_content_digest(),
+            Err("resource content digest mismatch")
+        );
+
+        assert_eq!(
+            resource.verify_content_digest(),
+            Err("resource does not have a content digest")
+        );
+    }
+
+    #[test]
+    fn test_v2_content_digest_compressed() {
+        let resource = resource_all_fields();
+
+        let mut options = WriteOptions {
+            resource_compression: Some(CompressionAlgorithm::Zstd),
+            resource_digests: true,
+            ..WriteOptions::default()
+        };
+        options.compress_in_memory_sections(CompressionAlgorithm::Zstd);
+
+        let mut data = Vec::new();
+        write_embedded_resources_v2(&[resource], &mut data, &options).unwrap();
+
+        let resources = load_resources(&data)
+            .unwrap()
+            .collect::<Result<Vec<Resource<u8>>, ParseError>>()
+            .unwrap();
+        assert_eq!(resources.len(), 1);
+        assert!(resources[0].in_memory_compression.is_some());
+        assert!(resources[0].verify_content_digest().is_ok());
+    }
+
+    #[test]
+    fn test_v2_payload_digest() {
+        let resource = resource_all_fields();
+
+        let options = WriteOptions {
+            payload_digest: true,
+            ..WriteOptions::default()
+        };
+
+        let mut data = Vec::new();
+        write_embedded_resources_v2(&[resource.clone()], &mut data, &options).unwrap();
+
+        let resources = load_resources(&data).unwrap();
+        assert!(resources.payload_digest().is_some());
+        assert!(resources.verify_payload_digest().is_ok());
+        assert_eq!(
+            resources
+                .collect::<Result<Vec<Resource<u8>>, ParseError>>()
+                .unwrap(),
+            vec![resource]
+        );
+
+        // Corrupt the last byte of blob data.
+        let last = data.len() - 1;
+        data[last] ^= 0xff;
+
+        let resources = load_resources(&data).unwrap();
+        assert_eq!(
+            resources.verify_payload_digest(),
+            Err(ParseError::Invalid("payload digest mismatch"))
+        );
+    }
+
+    #[test]
+    fn test_v2_no_payload_digest() {
+        let mut data = Vec::new();
+        write_embedded_resources_v2(
+            &[resource_all_fields()],
+            &mut data,
+            &WriteOptions::default(),
+        )
+        .unwrap();
+
+        let resources = load_resources(&data).unwrap();
+        assert!(resources.payload_digest().is_none());
+        assert_eq!(
+            resources.verify_payload_digest(),
+            Err(ParseError::Invalid("payload does not have a digest"))
+        );
+    }
+
+    #[test]
+    fn test_v2_invalid_payload_digest_length() {
+        let mut data = Vec::new();
+        write_embedded_resources_v2(&[], &mut data, &WriteOptions::default()).unwrap();
+
+        data[21] = 0x01;
+        assert_eq!(
+            load_resources(&data).err().map(|e| e.message()),
+            Some("invalid payload digest length")
+        );
+    }
+
+    #[test]
+    fn test_v2_lookup_index() {
+        let mut resources = vec![];
+
+        for name in &["foo", "bar", "foo.bar", "baz", "a"] {
+            let mut resource = resource_all_fields();
+            resource.name = Cow::from(name.to_string());
+            resource.in_memory_source = Some(Cow::from(format!("source of {}", name).into_bytes()));
+            resources.push(resource);
+        }
+
+        let mut all_options = vec![];
+
+        for interior_padding in &[None, Some(BlobInteriorPadding::Null)] {
+            all_options.push(WriteOptions {
+                interior_padding: *interior_padding,
+                lookup_index: true,
+                ..WriteOptions::default()
+            });
+
+            let mut options = WriteOptions {
+                interior_padding: *interior_padding,
+                resource_compression: Some(CompressionAlgorithm::Zstd),
+                lookup_index: true,
+                ..WriteOptions::default()
+            };
+            options.compress_in_memory_sections(CompressionAlgorithm::Zstd);
+            options
+                .section_compression
+                .insert(ResourceField::ModuleName, CompressionAlgorithm::Zstd);
+            all_options.push(options);
+        }
+
+        for options in all_options {
+            let mut data = Vec::new();
+            write_embedded_resources_v2(&resources, &mut data, &options).unwrap();
+
+            let parser = load_resources(&data).unwrap();
+            assert!(parser.has_lookup_index());
+
+            let indexed = parser.into_indexed().unwrap();
+            assert_eq!(indexed.len(), resources.len());
+
+            for resource in &resources {
+                let found = indexed.get(&resource.name).unwrap().unwrap();
+
+                let found = if options.resource_compression.is_some() {
+                    found.decompress_in_memory_data().unwrap()
+                } else {
+                    found
+                };
+
+                assert_eq!(&found, resource);
+                assert!(indexed.contains(&resource.name).unwrap());
+            }
+
+            assert_eq!(
+                indexed.names_with_prefix("foo").unwrap(),
+                vec!["foo", "foo.bar"]
+            );
+            assert_eq!(indexed.names_with_prefix("foo.").unwrap(), vec!["foo.bar"]);
+            assert_eq!(
+                indexed.names_with_prefix("").unwrap().len(),
+                resources.len()
+            );
+            assert!(indexed.names_with_prefix("zzz").unwrap().is_empty());
+
+            assert!(indexed.get("missing").unwrap().is_none());
+            assert!(indexed.get("").unwrap().is_none());
+            assert!(!indexed.contains("zzz").unwrap());
+
+            // The iterator is unaffected by the lookup index.
+            let parsed = load_resources(&data)
+                .unwrap()
+                .collect::<Result<Vec<Resource<u8>>, ParseError>>()
+                .unwrap();
+            assert_eq!(parsed.len(), resources.len());
+        }
+    }
+
+    #[test]
+    fn test_v2_lookup_index_lazy_decompression() {
+        let resource = Resource {
+            flavor: ResourceFlavor::Module,
+            name: Cow::from("foo"),
+            in_memory_source: Some(Cow::from(b"source".to_vec())),
+            ..Resource::default()
+        };
+
+        let mut options = WriteOptions {
+            lookup_index: true,
+            ..WriteOptions::default()
+        };
+        options
+            .section_compression
+            .insert(ResourceField::ModuleName, CompressionAlgorithm::Zstd);
+        options
+            .section_compression
+            .insert(ResourceField::InMemorySource, CompressionAlgorithm::Zstd);
+
+        let mut data = Vec::new();
+        write_embedded_resources_v2(&[resource.clone()], &mut data, &options).unwrap();
+
+        let indexed = load_resources(&data).unwrap().into_indexed().unwrap();
+        assert!(indexed.decompressed_sections.lock().unwrap().is_empty());
+
+        // Lookups only need resource names.
+        let names_field: u8 = ResourceField::ModuleName.into();
+        assert!(indexed.contains("foo").unwrap());
+        assert_eq!(
+            indexed
+                .decompressed_sections
+                .lock()
+                .unwrap()
+                .keys()
+                .collect::<Vec<_>>(),
+            vec![&names_field]
+        );
+
+        assert_eq!(indexed.get("foo").unwrap(), Some(resource));
+        assert_eq!(indexed.decompressed_sections.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_v2_no_lookup_index() {
+        let mut data = Vec::new();
+        write_embedded_resources_v2(
+            &[resource_all_fields()],
+            &mut data,
+            &WriteOptions::default(),
+        )
+        .unwrap();
+
+        let parser = load_resources(&data).unwrap();
+        assert!(!parser.has_lookup_index());
+        assert_eq!(
+            parser.into_indexed().err().map(|e| e.message()),
+            Some("payload does not have a lookup index")
+        );
+    }
+
+    #[test]
+    fn test_v2_empty_lookup_index() {
+        let mut data = Vec::new();
+        write_embedded_resources_v2(
+            &[],
+            &mut data,
+            &WriteOptions {
+                lookup_index: true,
+                ..WriteOptions::default()
+            },
+        )
+        .unwrap();
+
+        // An empty lookup index is indistinguishable from no lookup index.
+        let parser = load_resources(&data).unwrap();
+        assert!(!parser.has_lookup_index());
+    }
+
+    #[test]
+    fn test_v2_data_files() {
+        let resources = vec![
+            Resource {
+                flavor: ResourceFlavor::DataFile,
+                name: Cow::from("certs/ca.pem"),
+                in_memory_data_file: Some(Cow::from(b"certificate".to_vec())),
+                ..Resource::default()
+            },
+            Resource {
+                flavor: ResourceFlavor::DataFile,
+                name: Cow::from("models/large.bin"),
+                relative_path_data_file: Some(Cow::from(Path::new("models/large.bin"))),
+                ..Resource::default()
+            },
+        ];
+
+        let mut all_options = vec![WriteOptions::default()];
+
+        for lookup_index in &[false, true] {
+            let mut options = WriteOptions {
+                resource_compression: Some(CompressionAlgorithm::Zstd),
+                resource_digests: true,
+                lookup_index: *lookup_index,
+                ..WriteOptions::default()
+            };
+            options.compress_in_memory_sections(CompressionAlgorithm::Zstd);
+            all_options.push(options);
+        }
+
+        for options in all_options {
+            let mut data = Vec::new();
+            write_embedded_resources_v2(&resources, &mut data, &options).unwrap();
+
+            let parsed = load_resources(&data)
+                .unwrap()
+                .collect::<Result<Vec<Resource<u8>>, ParseError>>()
+                .unwrap();
+
+            for resource in &parsed {
+                if options.resource_digests {
+                    resource.verify_content_digest().unwrap();
+                }
+            }
+
+            let parsed = parsed
+                .iter()
+                .map(|r| Resource {
+                    content_digest: None,
+                    ..r.decompress_in_memory_data().unwrap()
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(parsed, resources);
+
+            if options.lookup_index {
+                let indexed = load_resources(&data).unwrap().into_indexed().unwrap();
+                let found = indexed.get("certs/ca.pem").unwrap().unwrap();
+                assert_eq!(
+                    found
+                        .resolve_in_memory_data(
+                            ResourceField::InMemoryDataFile,
+                            None,
+                            found.in_memory_data_file.as_ref().unwrap()
+                        )
+                        .unwrap()
+                        .as_ref(),
+                    b"certificate"
+                );
+            }
+        }
+
+        let mut data = Vec::new();
+        assert!(write_embedded_resources_v1(&resources, &mut data, None).is_err());
+
+        write_embedded_resources_v2(&resources, &mut data, &WriteOptions::default()).unwrap();
+        downgrade_to_v1(&mut data);
+        assert_eq!(
+            load_resources(&data)
+                .unwrap()
+                .next()
+                .unwrap()
+                .err()
+                .map(|e| e.message()),
+            Some("data files not supported by format version")
+        );
+    }
+
+    #[test]
+    fn test_v2_aligned_sections() {
+        let mut resources = vec![];
+
+        for name in &["foo", "bar", "foo.bar"] {
+            let mut resource = resource_all_fields();
+            resource.name = Cow::from(name.to_string());
+            resource.in_memory_extension_module_shared_library =
+                Some(Cow::from(format!("library of {}", name).into_bytes()));
+            resources.push(resource);
+        }
+
+        for interior_padding in &[None, Some(BlobInteriorPadding::Null)] {
+            for lookup_index in &[false, true] {
+                let mut options = WriteOptions {
+                    interior_padding: *interior_padding,
+                    payload_digest: true,
+                    lookup_index: *lookup_index,
+                    section_alignment: Some(4096),
+                    ..WriteOptions::default()
+                };
+                options
+                    .entry_alignment
+                    .insert(ResourceField::InMemoryExtensionModuleSharedLibrary, 64);
+                options.entry_alignment.insert(ResourceField::ModuleName, 8);
+
+                let mut data = Vec::new();
+                write_embedded_resources_v2(&resources, &mut data, &options).unwrap();
+
+                let parser = load_resources(&data).unwrap();
+                parser.verify_payload_digest().unwrap();
+
+                for field in &parser.section_fields {
+                    let state = parser.blob_sections[*field as usize].unwrap();
+                    assert_eq!((HEADER_V2.len() + state.start_offset) % 4096, 0);
+                }
+
+                let parsed = parser
+                    .collect::<Result<Vec<Resource<u8>>, ParseError>>()
+                    .unwrap();
+                assert_eq!(parsed, resources);
+
+                for resource in &parsed {
+                    match &resource.in_memory_extension_module_shared_library {
+                        Some(Cow::Borrowed(library)) => {
+                            let offset = library.as_ptr() as usize - data.as_ptr() as usize;
+                            assert_eq!(offset % 64, 0);
+                        }
+                        _ => panic!("extension module data should be borrowed"),
+                    }
+                }
+
+                if *lookup_index {
+                    let indexed = load_resources(&data).unwrap().into_indexed().unwrap();
+                    for resource in &resources {
+                        assert_eq!(&indexed.get(&resource.name).unwrap().unwrap(), resource);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_v2_invalid_alignment() {
+        let mut data = Vec::new();
+        write_embedded_resources_v2(
+            &[Resource {
+                flavor: ResourceFlavor::Module,
+                name: Cow::from("foo"),
+                ..Resource::default()
+            }],
+            &mut data,
+            &WriteOptions {
+                section_alignment: Some(16),
+                ..WriteOptions::default()
+            },
+        )
+        .unwrap();
+
+        let alignment_field: u8 = BlobSectionField::Alignment.into();
+        let position = data
+            .windows(5)
+            .position(|w| w == [alignment_field, 16, 0, 0, 0])
+            .unwrap();
+        data[position + 1] = 3;
+
+        assert_eq!(
+            load_resources(&data).err().map(|e| e.message()),
+            Some("blob alignment must be a power of 2")
+        );
+    }
+
+    #[test]
+    fn test_v2_streaming_compressed_sections() {
+        let mut resources = vec![];
+
+        for name in &["foo", "bar", "foo.bar"] {
+            let mut resource = resource_all_fields();
+            resource.name = Cow::from(name.to_string());
+            resources.push(resource);
+        }
+
+        for interior_padding in &[None, Some(BlobInteriorPadding::Null)] {
+            let mut options = WriteOptions {
+                interior_padding: *interior_padding,
+                payload_digest: true,
+                lookup_index: true,
+                ..WriteOptions::default()
+            };
+            options.compress_in_memory_sections(CompressionAlgorithm::Zstd);
+            options
+                .section_compression
+                .insert(ResourceField::ModuleName, CompressionAlgorithm::Zstd);
+
+            let mut writer = ResourcesWriter::new(options);
+            for resource in &resources {
+                writer.add_resource(resource).unwrap();
+            }
+
+            let mut data = Vec::new();
+            writer.finish(&mut data).unwrap();
+
+            let parser = load_resources(&data).unwrap();
+            parser.verify_payload_digest().unwrap();
+
+            let parsed = parser
+                .collect::<Result<Vec<Resource<u8>>, ParseError>>()
+                .unwrap();
+            assert_eq!(parsed, resources);
+
+            let indexed = load_resources(&data).unwrap().into_indexed().unwrap();
+            for resource in &resources {
+                assert_eq!(&indexed.get(&resource.name).unwrap().unwrap(), resource);
+            }
+        }
+    }
+
+    #[test]
+    fn test_blob_out_of_bounds() {
+        let mut data = Vec::new();
+        write_embedded_resources_v2(
+            &[Resource {
+                flavor: ResourceFlavor::Module,
+                name: Cow::from("foo"),
+                in_memory_source: Some(Cow::from(b"source".to_vec())),
+                ..Resource::default()
+            }],
+            &mut data,
+            &WriteOptions::default(),
+        )
+        .unwrap();
+
+        let source_field: u8 = ResourceField::InMemorySource.into();
+        let position = data
+            .windows(5)
+            .position(|w| w == [source_field, 6, 0, 0, 0])
+            .unwrap();
+        data[position + 1] = 0xff;
+
+        assert_eq!(
+            load_resources(&data).unwrap().next(),
+            Some(Err(ParseError::BlobOutOfBounds {
+                field: source_field,
+                offset: 0,
+                length: 0xff,
+                section_length: 6,
+            }))
+        );
+    }
+
+    #[test]
+    fn test_section_out_of_bounds() {
+        let mut data = Vec::new();
+        write_embedded_resources_v2(
+            &[Resource {
+                flavor: ResourceFlavor::Module,
+                name: Cow::from("foo"),
+                in_memory_source: Some(Cow::from(b"source".to_vec())),
+                ..Resource::default()
+            }],
+            &mut data,
+            &WriteOptions::default(),
+        )
+        .unwrap();
+        data.pop();
+
+        match load_resources(&data).err() {
+            Some(ParseError::SectionOutOfBounds {
+                length,
+                data_length,
+                ..
+            }) => {
+                assert_eq!(length, 6);
+                assert_eq!(data_length, data.len());
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_invalid_utf8_name() {
+        let mut data = Vec::new();
+        write_embedded_resources_v2(
+            &[Resource {
+                flavor: ResourceFlavor::Module,
+                name: Cow::from("foo"),
+                ..Resource::default()
+            }],
+            &mut data,
+            &WriteOptions::default(),
+        )
+        .unwrap();
+
+        let last = data.len() - 1;
+        data[last] = 0xff;
+
+        assert_eq!(
+            load_resources(&data)
+                .unwrap()
+                .next()
+                .unwrap()
+                .err()
+                .map(|e| e.message()),
+            Some("blob data is not valid UTF-8")
+        );
+    }
+
+    /// A small deterministic pseudo-random number generator.
+    ///
+    /// Property tests use it to explore payloads without adding dependencies.
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, bound: usize) -> usize {
+            (self.next() % bound as u64) as usize
+        }
+
+        fn chance(&mut self) -> bool {
+            self.next() & 1 == 0
+        }
+
+        fn bytes(&mut self, max_length: usize) -> Vec<u8> {
+            let length = self.below(max_length);
+            (0..length).map(|_| self.next() as u8).collect()
+        }
+    }
+
+    fn random_resources(rng: &mut XorShift) -> Vec<Resource<'static, u8>> {
+        let count = rng.below(8);
+
+        (0..count)
+            .map(|i| {
+                let mut resource = Resource {
+                    flavor: ResourceFlavor::Module,
+                    name: Cow::Owned(format!("module{}.{}", i, rng.below(1000))),
+                    is_package: rng.chance(),
+                    ..Resource::default()
+                };
+
+                if rng.chance() {
+                    resource.in_memory_source = Some(Cow::Owned(rng.bytes(64)));
+                }
+                if rng.chance() {
+                    resource.in_memory_bytecode = Some(Cow::Owned(rng.bytes(64)));
+                }
+                if rng.chance() {
+                    let mut resources = HashMap::new();
+                    for j in 0..rng.below(4) {
+                        resources.insert(
+                            Cow::Owned(format!("resource{}", j)),
+                            Cow::Owned(rng.bytes(32)),
+                        );
+                    }
+                    resource.in_memory_package_resources = Some(resources);
+                }
+                if rng.chance() {
+                    resource.relative_path_module_source =
+                        Some(Cow::Owned(PathBuf::from(format!("lib/module{}.py", i))));
+                }
+                if rng.below(4) == 0 {
+                    resource.flavor = ResourceFlavor::DataFile;
+                    resource.in_memory_source = None;
+                    resource.in_memory_bytecode = None;
+                    resource.in_memory_package_resources = None;
+                    resource.relative_path_module_source = None;
+                    resource.in_memory_data_file = Some(Cow::Owned(rng.bytes(64)));
+                }
+
+                resource
+            })
+            .collect()
+    }
+
+    fn random_options(rng: &mut XorShift) -> WriteOptions {
+        let mut options = WriteOptions {
+            interior_padding: if rng.chance() {
+                Some(BlobInteriorPadding::Null)
+            } else {
+                None
+            },
+            payload_digest: rng.chance(),
+            lookup_index: rng.chance(),
+            ..WriteOptions::default()
+        };
+
+        if rng.chance() {
+            options.resource_compression = Some(CompressionAlgorithm::Zstd);
+        }
+
+        if rng.chance() {
+            options.compress_in_memory_sections(CompressionAlgorithm::Zstd);
+        } else if rng.chance() {
+            options
+                .entry_alignment
+                .insert(ResourceField::InMemorySource, 1 << rng.below(5));
+        }
+
+        if rng.chance() {
+            options.section_alignment = Some(1 << rng.below(8));
+        }
+
+        options
+    }
+
+    /// Parse a payload as thoroughly as possible, ignoring errors.
+    fn exercise_parser(data: &[u8]) {
+        if let Ok(parser) = load_resources(data) {
+            let _ = parser.verify_payload_digest();
+
+            let names = parser
+                .filter_map(|res| res.ok())
+                .map(|resource| resource.name.to_string())
+                .collect::<Vec<_>>();
+
+            if let Ok(indexed) = load_resources(data).unwrap().into_indexed() {
+                for name in names.iter().map(|s| s.as_str()).chain(vec!["missing"]) {
+                    let _ = indexed.get(name);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_random_round_trip() {
+        let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+
+        for _ in 0..200 {
+            let resources = random_resources(&mut rng);
+            let options = random_options(&mut rng);
+
+            let mut data = Vec::new();
+            write_embedded_resources_v2(&resources, &mut data, &options).unwrap();
+
+            let parser = load_resources(&data).unwrap();
+            if options.payload_digest {
+                parser.verify_payload_digest().unwrap();
+            }
+
+            let parsed = parser
+                .map(|res| res.unwrap().decompress_in_memory_data().unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(parsed, resources);
+
+            // An empty lookup index is indistinguishable from no lookup index.
+            if options.lookup_index && !resources.is_empty() {
+                let indexed = load_resources(&data).unwrap().into_indexed().unwrap();
+                for resource in &resources {
+                    let found = indexed.get(&resource.name).unwrap().unwrap();
+                    assert_eq!(&found.decompress_in_memory_data().unwrap(), resource);
+                }
+                assert_eq!(indexed.get("missing"), Ok(None));
+            }
+        }
+    }
+
+    #[test]
+    fn test_corrupted_payloads() {
+        let mut rng = XorShift(0x9e37_79b9_7f4a_7c15);
+
+        for _ in 0..50 {
+            let resources = random_resources(&mut rng);
+            let options = random_options(&mut rng);
+
+            let mut data = Vec::new();
+            write_embedded_resources_v2(&resources, &mut data, &options).unwrap();
+
+            // Truncated payloads.
+            for length in 0..data.len() {
+                exercise_parser(&data[0..length]);
+            }
+
+            // Payloads with random bytes changed.
+            for _ in 0..100 {
+                let mut corrupted = data.clone();
+                for _ in 0..rng.below(4) + 1 {
+                    let position = rng.below(corrupted.len());
+                    corrupted[position] = rng.next() as u8;
+                }
+
+                exercise_parser(&corrupted);
+            }
+        }
+    }
 }
@@ -0,0 +1,355 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Textual representations of packed resources data.
+
+Packed resources data is converted to serializable structures, which can
+be emitted as JSON or TOML. Binary data is represented by its length and
+SHA-256 digest rather than its content. So the representations of two
+payloads can be diffed to find which resources and fields differ.
+*/
+
+use {
+    super::data::{sha256_digest, CompressionAlgorithm, Resource, ResourceFlavor},
+    super::parser::{load_resources, ParseError},
+    anyhow::{anyhow, Context, Result},
+    serde::{Deserialize, Serialize},
+    std::borrow::Cow,
+    std::collections::{BTreeMap, HashMap},
+    std::path::Path,
+};
+
+fn hex_string(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn flavor_name(flavor: ResourceFlavor) -> &'static str {
+    match flavor {
+        ResourceFlavor::None => "none",
+        ResourceFlavor::Module => "module",
+        ResourceFlavor::BuiltinExtensionModule => "builtin-extension-module",
+        ResourceFlavor::FrozenModule => "frozen-module",
+        ResourceFlavor::Extension => "extension",
+        ResourceFlavor::SharedLibrary => "shared-library",
+        ResourceFlavor::DataFile => "data-file",
+    }
+}
+
+fn compression_name(algorithm: CompressionAlgorithm) -> &'static str {
+    match algorithm {
+        CompressionAlgorithm::Zstd => "zstd",
+    }
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+/// Summary of binary data.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct SerializedData {
+    /// Length of the data in bytes.
+    pub length: usize,
+
+    /// Hex encoded SHA-256 digest of the data.
+    pub sha256: String,
+}
+
+impl From<&[u8]> for SerializedData {
+    fn from(data: &[u8]) -> Self {
+        SerializedData {
+            length: data.len(),
+            sha256: hex_string(&sha256_digest(data)),
+        }
+    }
+}
+
+/// Serializable representation of a `Resource`.
+///
+/// Fields not set on the resource are omitted. In-memory data is summarized
+/// as stored, so it is compressed if `in_memory_compression` is set.
+///
+/// Fields holding tables are declared after all other fields because TOML
+/// requires values to precede tables.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct SerializedResource {
+    pub name: String,
+
+    pub flavor: String,
+
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub is_package: bool,
+
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub is_namespace_package: bool,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub in_memory_compression: Option<String>,
+
+    /// Hex encoded content digest.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_digest: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shared_library_dependency_names: Option<Vec<String>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relative_path_module_source: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relative_path_module_bytecode: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relative_path_module_bytecode_opt1: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relative_path_module_bytecode_opt2: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relative_path_extension_module_shared_library: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relative_path_data_file: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub in_memory_source: Option<SerializedData>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub in_memory_bytecode: Option<SerializedData>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub in_memory_bytecode_opt1: Option<SerializedData>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub in_memory_bytecode_opt2: Option<SerializedData>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub in_memory_extension_module_shared_library: Option<SerializedData>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub in_memory_shared_library: Option<SerializedData>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub in_memory_data_file: Option<SerializedData>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub in_memory_package_resources: Option<BTreeMap<String, SerializedData>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub in_memory_distribution_resources: Option<BTreeMap<String, SerializedData>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relative_path_package_resources: Option<BTreeMap<String, String>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relative_path_distribution_resources: Option<BTreeMap<String, String>>,
+}
+
+impl<'a> From<&Resource<'a, u8>> for SerializedResource {
+    fn from(resource: &Resource<'a, u8>) -> Self {
+        let data = |data: &Option<Cow<[u8]>>| data.as_ref().map(|d| SerializedData::from(&d[..]));
+        let path =
+            |path: &Option<Cow<Path>>| path.as_ref().map(|p| p.to_string_lossy().to_string());
+        let data_map = |map: &Option<HashMap<Cow<str>, Cow<[u8]>>>| {
+            map.as_ref().map(|map| {
+                map.iter()
+                    .map(|(k, v)| (k.to_string(), SerializedData::from(&v[..])))
+                    .collect()
+            })
+        };
+        let path_map = |map: &Option<HashMap<Cow<str>, Cow<Path>>>| {
+            map.as_ref().map(|map| {
+                map.iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string_lossy().to_string()))
+                    .collect()
+            })
+        };
+
+        SerializedResource {
+            name: resource.name.to_string(),
+            flavor: flavor_name(resource.flavor).to_string(),
+            is_package: resource.is_package,
+            is_namespace_package: resource.is_namespace_package,
+            in_memory_compression: resource
+                .in_memory_compression
+                .map(|algorithm| compression_name(algorithm).to_string()),
+            content_digest: resource.content_digest.as_ref().map(|d| hex_string(d)),
+            shared_library_dependency_names: resource
+                .shared_library_dependency_names
+                .as_ref()
+                .map(|names| names.iter().map(|name| name.to_string()).collect()),
+            relative_path_module_source: path(&resource.relative_path_module_source),
+            relative_path_module_bytecode: path(&resource.relative_path_module_bytecode),
+            relative_path_module_bytecode_opt1: path(&resource.relative_path_module_bytecode_opt1),
+            relative_path_module_bytecode_opt2: path(&resource.relative_path_module_bytecode_opt2),
+            relative_path_extension_module_shared_library: path(
+                &resource.relative_path_extension_module_shared_library,
+            ),
+            relative_path_data_file: path(&resource.relative_path_data_file),
+            in_memory_source: data(&resource.in_memory_source),
+            in_memory_bytecode: data(&resource.in_memory_bytecode),
+            in_memory_bytecode_opt1: data(&resource.in_memory_bytecode_opt1),
+            in_memory_bytecode_opt2: data(&resource.in_memory_bytecode_opt2),
+            in_memory_extension_module_shared_library: data(
+                &resource.in_memory_extension_module_shared_library,
+            ),
+            in_memory_shared_library: data(&resource.in_memory_shared_library),
+            in_memory_data_file: data(&resource.in_memory_data_file),
+            in_memory_package_resources: data_map(&resource.in_memory_package_resources),
+            in_memory_distribution_resources: data_map(&resource.in_memory_distribution_resources),
+            relative_path_package_resources: path_map(&resource.relative_path_package_resources),
+            relative_path_distribution_resources: path_map(
+                &resource.relative_path_distribution_resources,
+            ),
+        }
+    }
+}
+
+/// Serializable representation of packed resources data.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct SerializedResources {
+    /// Version of the packed resources format.
+    pub format_version: u8,
+
+    /// Resources, sorted by name.
+    pub resources: Vec<SerializedResource>,
+}
+
+impl SerializedResources {
+    /// Parse packed resources data into its serializable representation.
+    pub fn from_packed_resources(data: &[u8]) -> Result<Self> {
+        let parser = load_resources(data).map_err(|e| anyhow!("parsing resources: {}", e))?;
+        let format_version = parser.format_version();
+
+        let mut resources = parser
+            .map(|resource| resource.map(|resource| SerializedResource::from(&resource)))
+            .collect::<Result<Vec<_>, ParseError>>()
+            .map_err(|e| anyhow!("parsing resources: {}", e))?;
+
+        resources.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(SerializedResources {
+            format_version,
+            resources,
+        })
+    }
+
+    /// Serialize to a JSON string.
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).context("serializing resources to JSON")
+    }
+
+    /// Serialize to a TOML string.
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string(self).context("serializing resources to TOML")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::writer::{write_embedded_resources_v2, WriteOptions},
+    };
+
+    fn test_resources() -> Vec<Resource<'static, u8>> {
+        let mut package_resources = HashMap::new();
+        package_resources.insert(Cow::from("data.txt"), Cow::from(b"data".to_vec()));
+
+        let mut distribution_resources = HashMap::new();
+        distribution_resources.insert(Cow::from("METADATA"), Cow::from(Path::new("METADATA")));
+
+        vec![
+            Resource {
+                flavor: ResourceFlavor::Module,
+                name: Cow::from("foo"),
+                is_package: true,
+                in_memory_source: Some(Cow::from(b"source".to_vec())),
+                in_memory_package_resources: Some(package_resources),
+                relative_path_distribution_resources: Some(distribution_resources),
+                ..Resource::default()
+            },
+            Resource {
+                flavor: ResourceFlavor::Extension,
+                name: Cow::from("bar"),
+                relative_path_extension_module_shared_library: Some(Cow::from(Path::new("bar.so"))),
+                shared_library_dependency_names: Some(vec![Cow::from("libbaz.so")]),
+                ..Resource::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn test_to_owned_resource() {
+        let data = b"source".to_vec();
+
+        let resource = Resource {
+            flavor: ResourceFlavor::Module,
+            name: Cow::from("foo"),
+            in_memory_source: Some(Cow::Borrowed(&data[..])),
+            ..Resource::default()
+        };
+
+        let owned = resource.to_owned_resource();
+        drop(data);
+
+        assert_eq!(owned.name, "foo");
+        assert_eq!(owned.in_memory_source, Some(Cow::from(b"source".to_vec())));
+    }
+
+    #[test]
+    fn test_serialized_resource() {
+        let resources = test_resources();
+        let serialized = SerializedResource::from(&resources[0]);
+
+        assert_eq!(serialized.name, "foo");
+        assert_eq!(serialized.flavor, "module");
+        assert!(serialized.is_package);
+        assert_eq!(
+            serialized.in_memory_source,
+            Some(SerializedData::from(&b"source"[..]))
+        );
+        assert_eq!(serialized.in_memory_source.unwrap().length, 6);
+        assert_eq!(serialized.in_memory_bytecode, None);
+        assert_eq!(
+            serialized
+                .relative_path_distribution_resources
+                .unwrap()
+                .get("METADATA"),
+            Some(&"METADATA".to_string())
+        );
+    }
+
+    #[test]
+    fn test_serialize_packed_resources() -> Result<()> {
+        let resources = test_resources();
+
+        let options = WriteOptions {
+            resource_digests: true,
+            ..WriteOptions::default()
+        };
+
+        let mut data = Vec::new();
+        write_embedded_resources_v2(&resources, &mut data, &options)?;
+
+        let serialized = SerializedResources::from_packed_resources(&data)?;
+        assert_eq!(serialized.format_version, 2);
+        assert_eq!(serialized.resources.len(), 2);
+        // Resources are sorted by name.
+        assert_eq!(serialized.resources[0].name, "bar");
+        assert_eq!(serialized.resources[1].name, "foo");
+        assert!(serialized.resources[0].content_digest.is_some());
+
+        let json = serialized.to_json()?;
+        assert!(!json.contains("in_memory_bytecode"));
+        let from_json: SerializedResources = serde_json::from_str(&json)?;
+        assert_eq!(from_json, serialized);
+
+        let toml = serialized.to_toml()?;
+        let from_toml: SerializedResources = toml::from_str(&toml)?;
+        assert_eq!(from_toml, serialized);
+
+        Ok(())
+    }
+}
@@ -87,6 +87,61 @@ of different sections holding data for different fields for different resources.
 But there is no internal structure or separators: all the individual
 blobs are just laid out next to each other.
 
+## `pyembed\x02`
+
+Version 2 of the embedded resources data.
+
+Version 2 is identical to version 1 except that blob sections and the
+in-memory data of individual resources can be compressed, that the
+payload and individual resources can carry SHA-256 digests, and that the
+payload can contain a *lookup index* allowing random access to resources.
+
+The *global header* is extended by a `u8` denoting the length of the
+payload digest, `payload_digest_length`. It is either `0`, meaning no
+payload digest is present, or `32`. It is followed by `payload_digest_length`
+bytes holding the SHA-256 digest of all data following the *global header*
+(the indices and blob data). Readers can verify this digest to detect
+corruption or tampering of the payload.
+
+Following the payload digest is a `u32` denoting the length of the
+*lookup index*, `lookup_index_length`. `0` means there is no lookup index.
+
+Digests of individual resources are described by the *content digest*
+resource field. See the *Resource Field Types* section below.
+
+The *lookup index*, if present, immediately follows the *resources index*
+and precedes blob data. It allows finding a resource by name without
+parsing the resources index entries of all resources. It consists of
+`resources_count` rows of equal length. Each row consists of:
+
+* A `u32` denoting the offset of the resource's entry from the start of the
+  *resources index*.
+* A `u16` denoting the length of the resource's name.
+* For each blob section, in *blob index* order, a `u32` denoting the offset
+  of the resource's first blob within the (decompressed) blob section.
+
+Rows are sorted by the bytes of the resource name, so readers can binary
+search the lookup index. The name of the resource described by a row is
+read from the resource name blob section at the row's offset for that
+section. To parse the resource, readers seek to its entry in the
+*resources index* and read blobs starting at the row's offsets.
+
+Compressing individual resources is described by the *in-memory data
+compression* resource field. See the *Resource Field Types* section below.
+The remainder of this section describes compression of blob sections.
+
+A compressed blob section is denoted by the presence of the *compression*
+and *decompressed payload length* fields in its *blob index* entry. See the
+*Blob Field Types* section below.
+
+The *raw payload length* of a compressed blob section is the length of the
+compressed data as stored in the payload. Offsets of blobs within the section
+are relative to the decompressed data. Interior padding, if present, is
+applied before compression.
+
+Readers should defer decompressing a blob section until data within it is
+accessed.
+
 ## Blob Field Types
 
 The Blob Index allows attributing a sparse set of metadata with every blob
@@ -123,6 +178,35 @@ is assumed. If the payload data logically consists of discrete resources
 (e.g. Python package resource files), then padding applies to these
 sub-elements as well.
 
+`0x05` - Compression. This field defines the compression algorithm applied
+to the blob section. Following this `u8` is another `u8` denoting the
+algorithm. `0x01` indicates zstandard: the section data is a single zstd
+frame. The frame header may not declare the decompressed size, so readers
+should use the decompressed payload length field instead. If not present,
+the section is not compressed. Only valid in version 2 and newer.
+
+`0x06` - Decompressed payload length. This field defines the length in bytes
+of the blob section after decompression. The `u64` containing that length
+will immediately follow this `u8`. Required if the section is compressed.
+Only valid in version 2 and newer.
+
+`0x07` - Alignment. This field defines the alignment of the start of the
+blob section. The `u32` containing the alignment in bytes will immediately
+follow this `u8`. The alignment must be a power of 2. Alignment is relative
+to the start of the data, including the magic header. The section is
+preceded by NULL bytes as necessary to satisfy the alignment. This padding
+is not counted by the raw payload length of the section or any other
+section. Only valid in version 2 and newer.
+
+`0x08` - Entry alignment. This field defines the alignment of each
+individual blob within the blob section. The `u32` containing the
+alignment in bytes will immediately follow this `u8`. The alignment must be
+a power of 2. Alignment is relative to the start of the data, including the
+magic header. Each blob is preceded by NULL bytes as necessary to satisfy the
+alignment. Unlike section alignment, this padding is part of the section
+and is counted by its raw payload length. Cannot be combined with
+compression. Only valid in version 2 and newer.
+
 ## Resource Field Types
 
 The Resources Index allows attributing a sparse set of metadata
@@ -173,7 +257,11 @@ this byte.
 contains non-module resources files and the number of resources is contained in
 a `u32` that immediately follows. Following this `u32` is an array of
 `(u16, u64)` denoting the resource name and payload size for each resource
-in this package.
+in this package. Resource names are paths relative to the package. Resources
+in subdirectories have names with directory components separated by `/`,
+such as `templates/html/base.html`, on all platforms. Names can't be
+absolute or have empty, `.` or `..` components. Directories themselves aren't
+stored.
 
 `0x0c` - In-memory Python distribution resource. Defines resources accessed from
 `importlib.metadata` APIs. If encountered, the module/package contains
@@ -217,7 +305,8 @@ loadable as a shared library.
 `0x14` - Relative filesystem path to Python package resources. The number of
 resources is contained in a `u32` that immediately follows. Following this
 `u32` is an array of `(u16, u32)` denoting the resource name and filesystem
-path to each resource in this package.
+path to each resource in this package. Resource names follow the rules of
+the in-memory Python resources field.
 
 `0x15` - Relative filesystem path to Python distribution resources.
 Defines resources accessed from `importlib.metadata` APIs. If encountered,
@@ -226,6 +315,50 @@ The number of files being described is contained in a `u32` that immediately
 follows this byte. Following this `u32` is an array of `(u16, u32)` denoting
 the distribution file name and filesystem path to that distribution file.
 
+`0x16` - In-memory data compression. Declares that the in-memory data of this
+resource is compressed. A `u8` denoting the compression algorithm immediately
+follows this byte. `0x01` indicates zstandard. Only valid in version 2 and
+newer. If present, this field must precede the fields it affects.
+
+The in-memory module source (`0x06`), module bytecode (`0x07`, `0x08`,
+`0x09`), resources data (`0x0b`), distribution resource (`0x0c`), and data
+file (`0x18`) fields of a resource with compressed in-memory data have their
+data compressed independently. Each is stored as a single zstd frame. Each
+length in these fields denotes the decompressed length and is immediately
+followed by an integer of the same width holding the compressed length, which
+is the length of the data stored in the blob section. e.g. resources data is
+described by an array of `(u16, u64, u64)`. Readers only need to decompress
+data for the resources they access. Readers must reject data that doesn't
+decompress to the declared decompressed length.
+
+`0x17` - Content digest. Declares a SHA-256 digest of the resource's content.
+A `u8` denoting the length of the digest immediately follows this byte. It
+must be `32`. The digest is stored in this field's blob section. Only valid
+in version 2 and newer.
+
+The digest covers the flavor, name, package flags, in-memory compression
+algorithm, and in-memory data fields of the resource. Each value fed to
+SHA-256 is prefixed by its length as a `u64`: the flavor `u8`, the name, the
+package and namespace package flags as a single two byte value, and the
+compression algorithm `u8` (`0` if uncompressed). Then, for each present
+in-memory data field among `0x06`, `0x07`, `0x08`, `0x09`, `0x0a`, `0x0d`
+and `0x18`, in that order, the field type `u8` and the data. Then, for each present
+resources data (`0x0b`) and distribution resource (`0x0c`) field, the field
+type `u8`, the entry count as a `u64`, and the name and data of each entry,
+sorted by name. Then, if present, the shared library dependency names field
+type `u8`, the name count as a `u64`, and each name. Data is digested as
+stored, so compressed data is digested in its compressed form. Relative
+filesystem paths are not covered.
+
+`0x18` - In-memory data file. Holds the content of a data file. A `u64`
+denoting the length in bytes of the data follows. Only valid in version 2
+and newer.
+
+`0x19` - Relative filesystem path to data file. A `u32` holding the length
+in bytes of a filesystem path encoded in the platform-native file path
+encoding follows. The content of the data file will be read from a file at
+this path. Only valid in version 2 and newer.
+
 ## Resource Flavors
 
 The data format allows defining different types/flavors of resources.
@@ -253,6 +386,11 @@ are differentiated from regular Python modules.
 `0x05` - Shared library. This type represents a shared library that can be
 loaded into a process.
 
+`0x06` - Data file. This type represents application data not belonging to
+any Python package, such as configuration templates or certificates. The
+resource name is a virtual path to the file, such as `certs/ca.pem`. Data
+files are never imported. Only valid in version 2 and newer.
+
 ## Design Considerations
 
 The design of the embedded resources data format was influenced by a handful
@@ -271,6 +409,11 @@ to initialize our custom importer. In addition, the index data is read
 sequentially. Sequential I/O should always be faster than random access
 I/O.
 
+Parsing the entire *resources index* is linear in the number of resources.
+When only a fraction of resources is accessed, the optional *lookup index*
+of version 2 allows readers to only parse entries of accessed resources, at
+the cost of a fixed size row per resource.
+
 x86 is little endian, so we use little endian integers so we don't need
 to waste cycles on endian transformation.
 
@@ -288,14 +431,21 @@ reader has the data structure in memory, we don't want to require it
 to copy memory in order to reference entries. In Rust speak, we should
 be able to hold `&[u8]` references everywhere.
 
-There is no checksumming of the data because we don't want to incur
-I/O overhead to read the entire blob. It could be added as an optional
-feature.
+Version 1 has no checksumming of the data because we don't want to incur
+I/O overhead to read the entire blob. Version 2 adds optional digests. The
+payload digest requires reading the entire blob to verify. Per-resource
+digests allow verifying only the resources that are accessed.
 
-A potential area for optimization is use of general compression. Various
-fields should compress well - either in streaming mode or by utilizing
-compression dictionaries. Compression would undermine 0-copy, of course.
-But in environments where we want to optimize for size, it could be
+Version 2 supports general compression of individual blob sections.
+Various fields should compress well - either in streaming mode or by utilizing
+compression dictionaries. Compression undermines 0-copy, of course: data in a
+compressed section must be decompressed and copied before it can be
+referenced. But in environments where we want to optimize for size, it can be
 desirable.
 
+Packed resources data may be read from files at run-time, so readers must not
+trust it. Every blob section must lie within the data and every blob must lie
+within its (decompressed) blob section. Readers should validate these bounds
+and reject the data rather than reading out of bounds.
+
 */
@@ -0,0 +1,212 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Packed resources data appended to other data.
+
+Packed resources data can be appended to arbitrary data, such as an
+executable, as a *trailer*. This allows the resources of an executable to be
+replaced without rebuilding it.
+
+A trailer consists of:
+
+* Null padding, so packed resources data starts at an offset aligned to
+  `TRAILER_ALIGNMENT`.
+* Packed resources data.
+* A 24 byte footer.
+
+The footer consists of:
+
+* A little endian u64 holding the length of the data preceding the trailer.
+* A little endian u64 holding the length of the packed resources data.
+* The 8 byte magic `TRAILER_MAGIC`.
+
+Because the footer is at the end of the data, readers find packed resources
+data by reading the footer.
+*/
+
+use {
+    super::data::alignment_padding,
+    super::parser::ParseError,
+    anyhow::{anyhow, Result},
+    std::convert::TryFrom,
+};
+
+/// Magic identifying the footer of a trailer.
+pub const TRAILER_MAGIC: &[u8] = b"pyembedT";
+
+/// Length of the footer of a trailer.
+pub const TRAILER_FOOTER_LENGTH: usize = 24;
+
+/// Alignment of packed resources data in a trailer, in bytes.
+///
+/// This is the page size of common platforms. Alignment of blob sections and
+/// entries within packed resources data is preserved if the containing data
+/// is loaded at an address aligned to at least this boundary, which is the
+/// case for memory mapped files.
+pub const TRAILER_ALIGNMENT: usize = 4096;
+
+/// Describes the location of a trailer within data.
+struct TrailerLocation {
+    /// Length of the data preceding the trailer.
+    start: usize,
+    /// Offset of packed resources data.
+    resources_offset: usize,
+    /// Length of packed resources data.
+    resources_length: usize,
+}
+
+fn read_footer_u64(data: &[u8], offset: usize) -> Result<usize, ParseError> {
+    let mut value = [0u8; 8];
+    value.copy_from_slice(&data[offset..offset + 8]);
+
+    usize::try_from(u64::from_le_bytes(value)).map_err(|_| ParseError::Malformed {
+        message: "trailer footer value out of range",
+        offset,
+    })
+}
+
+fn find_trailer_location(data: &[u8]) -> Result<Option<TrailerLocation>, ParseError> {
+    if data.len() < TRAILER_FOOTER_LENGTH || !data.ends_with(TRAILER_MAGIC) {
+        return Ok(None);
+    }
+
+    let footer_offset = data.len() - TRAILER_FOOTER_LENGTH;
+    let start = read_footer_u64(data, footer_offset)?;
+    let resources_length = read_footer_u64(data, footer_offset + 8)?;
+
+    let malformed = ParseError::Malformed {
+        message: "trailer footer does not match data",
+        offset: footer_offset,
+    };
+
+    let resources_offset = footer_offset
+        .checked_sub(resources_length)
+        .ok_or(malformed)?;
+
+    if start > resources_offset {
+        return Err(malformed);
+    }
+
+    Ok(Some(TrailerLocation {
+        start,
+        resources_offset,
+        resources_length,
+    }))
+}
+
+/// Find packed resources data in a trailer.
+///
+/// Returns `Ok(None)` if the data doesn't end with a trailer. The returned
+/// packed resources data is not validated.
+pub fn find_trailer(data: &[u8]) -> Result<Option<&[u8]>, ParseError> {
+    Ok(find_trailer_location(data)?.map(|location| {
+        &data[location.resources_offset..location.resources_offset + location.resources_length]
+    }))
+}
+
+/// Obtain the data preceding a trailer.
+///
+/// Returns all data if it doesn't end with a trailer.
+pub fn strip_trailer(data: &[u8]) -> Result<&[u8], ParseError> {
+    Ok(match find_trailer_location(data)? {
+        Some(location) => &data[0..location.start],
+        None => data,
+    })
+}
+
+/// Append packed resources data to data as a trailer.
+///
+/// An existing trailer is replaced. Errors if `resources` isn't packed
+/// resources data.
+pub fn replace_trailer(data: &[u8], resources: &[u8]) -> Result<Vec<u8>> {
+    super::parser::load_resources(resources).map_err(|e| anyhow!("parsing resources: {}", e))?;
+
+    let data = strip_trailer(data).map_err(|e| anyhow!("parsing trailer: {}", e))?;
+
+    let padding = alignment_padding(data.len(), TRAILER_ALIGNMENT);
+
+    let mut res =
+        Vec::with_capacity(data.len() + padding + resources.len() + TRAILER_FOOTER_LENGTH);
+    res.extend_from_slice(data);
+    res.resize(data.len() + padding, 0);
+    res.extend_from_slice(resources);
+    res.extend_from_slice(&(data.len() as u64).to_le_bytes());
+    res.extend_from_slice(&(resources.len() as u64).to_le_bytes());
+    res.extend_from_slice(TRAILER_MAGIC);
+
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::data::{Resource, ResourceFlavor},
+        crate::writer::{write_embedded_resources_v2, WriteOptions},
+        std::borrow::Cow,
+    };
+
+    fn resources_data(source: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        write_embedded_resources_v2(
+            &[Resource {
+                flavor: ResourceFlavor::Module,
+                name: Cow::from("foo"),
+                in_memory_source: Some(Cow::from(source.to_vec())),
+                ..Resource::default()
+            }],
+            &mut data,
+            &WriteOptions::default(),
+        )
+        .unwrap();
+
+        data
+    }
+
+    #[test]
+    fn test_no_trailer() {
+        assert_eq!(find_trailer(b"").unwrap(), None);
+        assert_eq!(find_trailer(b"executable").unwrap(), None);
+        assert_eq!(strip_trailer(b"executable").unwrap(), b"executable");
+    }
+
+    #[test]
+    fn test_replace_trailer() -> Result<()> {
+        let resources = resources_data(b"original");
+        let exe = replace_trailer(b"executable", &resources)?;
+
+        let found = find_trailer(&exe)?.unwrap();
+        assert_eq!(found, resources.as_slice());
+        assert_eq!(
+            (found.as_ptr() as usize - exe.as_ptr() as usize) % TRAILER_ALIGNMENT,
+            0
+        );
+        assert_eq!(strip_trailer(&exe)?, b"executable");
+
+        let replacement = resources_data(b"replacement");
+        let exe = replace_trailer(&exe, &replacement)?;
+
+        assert_eq!(find_trailer(&exe)?.unwrap(), replacement.as_slice());
+        assert_eq!(strip_trailer(&exe)?, b"executable");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_replace_trailer_invalid_resources() {
+        assert!(replace_trailer(b"executable", b"not resources").is_err());
+    }
+
+    #[test]
+    fn test_malformed_footer() -> Result<()> {
+        let mut exe = replace_trailer(b"executable", &resources_data(b"source"))?;
+        let footer_offset = exe.len() - TRAILER_FOOTER_LENGTH;
+        exe[footer_offset + 8..footer_offset + 16].copy_from_slice(&u64::MAX.to_le_bytes());
+
+        assert!(find_trailer(&exe).is_err());
+        assert!(strip_trailer(&exe).is_err());
+
+        Ok(())
+    }
+}
@@ -5,13 +5,19 @@
 /*! Serializing of structures into packed resources blobs. */
 
 use {
-    super::data::{BlobInteriorPadding, BlobSectionField, Resource, ResourceField, HEADER_V1},
+    super::data::{
+        alignment_padding, sha256_digest, BlobInteriorPadding, BlobSectionField,
+        CompressionAlgorithm, Resource, ResourceField, ResourceFlavor, DIGEST_LENGTH, HEADER_V1,
+        HEADER_V2,
+    },
     anyhow::{anyhow, Context, Result},
     byteorder::{LittleEndian, WriteBytesExt},
-    std::collections::BTreeMap,
+    sha2::{Digest, Sha256},
+    std::collections::{BTreeMap, BTreeSet},
     std::convert::TryFrom,
-    std::io::Write,
-    std::path::Path,
+    std::fs::File,
+    std::io::{BufWriter, Seek, SeekFrom, Write},
+    std::path::{Path, PathBuf},
 };
 
 #[cfg(unix)]
@@ -52,6 +58,12 @@ struct BlobSection {
     resource_field: ResourceField,
     raw_payload_length: usize,
     interior_padding: Option<BlobInteriorPadding>,
+    /// Compression applied to the section and the decompressed payload length.
+    compression: Option<(CompressionAlgorithm, usize)>,
+    /// Alignment of the start of the section.
+    alignment: Option<usize>,
+    /// Alignment of each entry in the section.
+    entry_alignment: Option<usize>,
 }
 
 impl BlobSection {
@@ -103,12 +115,88 @@ impl BlobSection {
 
         Ok(())
     }
+
+    /// Compute length of index entry for version 2 payload format.
+    pub fn index_v2_length(&self) -> usize {
+        let mut index = self.index_v1_length();
+
+        if self.compression.is_some() {
+            // Compression field + value, decompressed length field + value.
+            index += 2 + 9;
+        }
+
+        if self.alignment.is_some() {
+            // Field + u32 value.
+            index += 5;
+        }
+
+        if self.entry_alignment.is_some() {
+            // Field + u32 value.
+            index += 5;
+        }
+
+        index
+    }
+
+    pub fn write_index_v2<W: Write>(&self, dest: &mut W) -> Result<()> {
+        dest.write_u8(BlobSectionField::StartOfEntry.into())
+            .context("writing start of index entry")?;
+
+        dest.write_u8(BlobSectionField::ResourceFieldType.into())
+            .context("writing resource field type field")?;
+        dest.write_u8(self.resource_field.into())
+            .context("writing resource field type value")?;
+
+        dest.write_u8(BlobSectionField::RawPayloadLength.into())
+            .context("writing raw payload length field")?;
+        dest.write_u64::<LittleEndian>(self.raw_payload_length as u64)
+            .context("writing raw payload length")?;
+
+        if let Some(padding) = &self.interior_padding {
+            dest.write_u8(BlobSectionField::InteriorPadding.into())
+                .context("writing interior padding field")?;
+            dest.write_u8(padding.into())
+                .context("writing interior padding value")?;
+        }
+
+        if let Some((algorithm, decompressed_length)) = &self.compression {
+            dest.write_u8(BlobSectionField::Compression.into())
+                .context("writing compression field")?;
+            dest.write_u8((*algorithm).into())
+                .context("writing compression value")?;
+
+            dest.write_u8(BlobSectionField::DecompressedPayloadLength.into())
+                .context("writing decompressed payload length field")?;
+            dest.write_u64::<LittleEndian>(*decompressed_length as u64)
+                .context("writing decompressed payload length")?;
+        }
+
+        if let Some(alignment) = self.alignment {
+            dest.write_u8(BlobSectionField::Alignment.into())
+                .context("writing alignment field")?;
+            dest.write_u32::<LittleEndian>(
+                u32::try_from(alignment).context("converting alignment to u32")?,
+            )
+            .context("writing alignment")?;
+        }
+
+        if let Some(alignment) = self.entry_alignment {
+            dest.write_u8(BlobSectionField::EntryAlignment.into())
+                .context("writing entry alignment field")?;
+            dest.write_u32::<LittleEndian>(
+                u32::try_from(alignment).context("converting entry alignment to u32")?,
+            )
+            .context("writing entry alignment")?;
+        }
+
+        dest.write_u8(BlobSectionField::EndOfEntry.into())
+            .context("writing end of index entry")?;
+
+        Ok(())
+    }
 }
 
-impl<'a, X: Clone + 'a> Resource<'a, X>
-where
-    [X]: ToOwned<Owned = Vec<X>>,
-{
+impl<'a> Resource<'a, u8> {
     /// Whether the module is meaningful.
     ///
     /// The module is meaningful if it has data attached or is a package.
@@ -130,6 +218,8 @@ where
             || self.relative_path_extension_module_shared_library.is_some()
             || self.relative_path_package_resources.is_some()
             || self.relative_path_distribution_resources.is_some()
+            || self.in_memory_data_file.is_some()
+            || self.relative_path_data_file.is_some()
     }
 
     /// Compute length of index entry for version 1 payload format.
@@ -151,20 +241,32 @@ where
             index += 1;
         }
 
+        // Compressed in-memory data has an additional compressed length
+        // following each data length.
+        let compressed = self.in_memory_compression.is_some();
+
+        if compressed {
+            index += 2;
+        }
+
+        if self.content_digest.is_some() {
+            index += 2;
+        }
+
         if self.in_memory_source.is_some() {
-            index += 5;
+            index += if compressed { 9 } else { 5 };
         }
 
         if self.in_memory_bytecode.is_some() {
-            index += 5;
+            index += if compressed { 9 } else { 5 };
         }
 
         if self.in_memory_bytecode_opt1.is_some() {
-            index += 5;
+            index += if compressed { 9 } else { 5 };
         }
 
         if self.in_memory_bytecode_opt2.is_some() {
-            index += 5;
+            index += if compressed { 9 } else { 5 };
         }
 
         if self.in_memory_extension_module_shared_library.is_some() {
@@ -175,13 +277,13 @@ where
             index += 5;
 
             // u16 + u64 for resource name and data.
-            index += 10 * resources.len();
+            index += if compressed { 18 } else { 10 } * resources.len();
         }
 
         if let Some(metadata) = &self.in_memory_distribution_resources {
             index += 5;
             // Same as resources.
-            index += 10 * metadata.len();
+            index += if compressed { 18 } else { 10 } * metadata.len();
         }
 
         if self.in_memory_shared_library.is_some() {
@@ -225,6 +327,14 @@ where
             index += 6 * metadata.len();
         }
 
+        if self.in_memory_data_file.is_some() {
+            index += if compressed { 17 } else { 9 };
+        }
+
+        if self.relative_path_data_file.is_some() {
+            index += 5;
+        }
+
         // End of index entry.
         index += 1;
 
@@ -243,6 +353,14 @@ where
             ResourceField::ModuleName => self.name.as_bytes().len(),
             ResourceField::IsPackage => 0,
             ResourceField::IsNamespacePackage => 0,
+            ResourceField::InMemoryCompression => 0,
+            ResourceField::ContentDigest => {
+                if let Some(digest) = &self.content_digest {
+                    digest.len()
+                } else {
+                    0
+                }
+            }
             ResourceField::InMemorySource => {
                 if let Some(source) = &self.in_memory_source {
                     source.len()
@@ -367,6 +485,20 @@ where
                     0
                 }
             }
+            ResourceField::InMemoryDataFile => {
+                if let Some(data) = &self.in_memory_data_file {
+                    data.len()
+                } else {
+                    0
+                }
+            }
+            ResourceField::RelativeFilesystemDataFile => {
+                if let Some(path) = &self.relative_path_data_file {
+                    path_bytes_length(path)
+                } else {
+                    0
+                }
+            }
         }
     }
 
@@ -384,6 +516,14 @@ where
             ResourceField::ModuleName => 1,
             ResourceField::IsPackage => 0,
             ResourceField::IsNamespacePackage => 0,
+            ResourceField::InMemoryCompression => 0,
+            ResourceField::ContentDigest => {
+                if self.content_digest.is_some() {
+                    1
+                } else {
+                    0
+                }
+            }
             ResourceField::InMemorySource => {
                 if self.in_memory_source.is_some() {
                     1
@@ -496,6 +636,21 @@ where
                     0
                 }
             }
+
+            ResourceField::InMemoryDataFile => {
+                if self.in_memory_data_file.is_some() {
+                    1
+                } else {
+                    0
+                }
+            }
+            ResourceField::RelativeFilesystemDataFile => {
+                if self.relative_path_data_file.is_some() {
+                    1
+                } else {
+                    0
+                }
+            }
         };
 
         let overhead = match padding {
@@ -506,6 +661,58 @@ where
         elements_count * overhead
     }
 
+    /// Write the length of compressible in-memory data as a `u32`.
+    ///
+    /// If in-memory data is compressed, the decompressed length is written
+    /// followed by the compressed length. `field` and `name` identify the data
+    /// as for `Resource::in_memory_decompressed_length()`.
+    fn write_in_memory_data_length_u32<W: Write>(
+        &self,
+        dest: &mut W,
+        field: ResourceField,
+        name: Option<&str>,
+        data: &[u8],
+    ) -> Result<()> {
+        if self.in_memory_compression.is_some() {
+            let l = self
+                .in_memory_decompressed_length(field, name, data)
+                .map_err(|e| anyhow!("resolving decompressed length: {}", e))?;
+            dest.write_u32::<LittleEndian>(
+                u32::try_from(l).context("converting decompressed length to u32")?,
+            )?;
+        }
+
+        dest.write_u32::<LittleEndian>(
+            u32::try_from(data.len()).context("converting data length to u32")?,
+        )?;
+
+        Ok(())
+    }
+
+    /// Write the length of compressible in-memory data as a `u64`.
+    ///
+    /// If in-memory data is compressed, the decompressed length is written
+    /// followed by the compressed length. `field` and `name` identify the data
+    /// as for `Resource::in_memory_decompressed_length()`.
+    fn write_in_memory_data_length_u64<W: Write>(
+        &self,
+        dest: &mut W,
+        field: ResourceField,
+        name: Option<&str>,
+        data: &[u8],
+    ) -> Result<()> {
+        if self.in_memory_compression.is_some() {
+            let l = self
+                .in_memory_decompressed_length(field, name, data)
+                .map_err(|e| anyhow!("resolving decompressed length: {}", e))?;
+            dest.write_u64::<LittleEndian>(l as u64)?;
+        }
+
+        dest.write_u64::<LittleEndian>(data.len() as u64)?;
+
+        Ok(())
+    }
+
     /// Write the version 1 index entry for a module instance.
     pub fn write_index_v1<W: Write>(&self, dest: &mut W) -> Result<()> {
         let name_len =
@@ -535,40 +742,65 @@ where
                 .context("writing is_namespace field")?;
         }
 
+        if let Some(algorithm) = self.in_memory_compression {
+            dest.write_u8(ResourceField::InMemoryCompression.into())
+                .context("writing in-memory compression field")?;
+            dest.write_u8(algorithm.into())
+                .context("writing in-memory compression value")?;
+        }
+
+        if let Some(digest) = &self.content_digest {
+            if digest.len() != DIGEST_LENGTH {
+                return Err(anyhow!("content digest has invalid length"));
+            }
+
+            dest.write_u8(ResourceField::ContentDigest.into())
+                .context("writing content digest field")?;
+            dest.write_u8(DIGEST_LENGTH as u8)
+                .context("writing content digest length")?;
+        }
+
         if let Some(source) = &self.in_memory_source {
-            let l =
-                u32::try_from(source.len()).context("converting in-memory source length to u32")?;
             dest.write_u8(ResourceField::InMemorySource.into())
                 .context("writing in-memory source length field")?;
-            dest.write_u32::<LittleEndian>(l)
+            self.write_in_memory_data_length_u32(dest, ResourceField::InMemorySource, None, source)
                 .context("writing in-memory source length")?;
         }
 
         if let Some(bytecode) = &self.in_memory_bytecode {
-            let l = u32::try_from(bytecode.len())
-                .context("converting in-memory bytecode length to u32")?;
             dest.write_u8(ResourceField::InMemoryBytecode.into())
                 .context("writing in-memory bytecode length field")?;
-            dest.write_u32::<LittleEndian>(l)
-                .context("writing in-memory bytecode length")?;
+            self.write_in_memory_data_length_u32(
+                dest,
+                ResourceField::InMemoryBytecode,
+                None,
+                bytecode,
+            )
+            .context("writing in-memory bytecode length")?;
         }
 
         if let Some(bytecode) = &self.in_memory_bytecode_opt1 {
-            let l = u32::try_from(bytecode.len())
-                .context("converting in-memory bytecode opt 1 length to u32")?;
             dest.write_u8(ResourceField::InMemoryBytecodeOpt1.into())
                 .context("writing in-memory bytecode opt 1 length field")?;
-            dest.write_u32::<LittleEndian>(l)
-                .context("writing in-memory bytecode opt 1 length")?;
+            self.write_in_memory_data_length_u32(
+                dest,
+                ResourceField::InMemoryBytecodeOpt1,
+                None,
+                bytecode,
+            )
+            .context("writing in-memory bytecode opt 1 length")?;
         }
 
         if let Some(bytecode) = &self.in_memory_bytecode_opt2 {
-            let l = u32::try_from(bytecode.len())
-                .context("converting in-memory bytecode opt 2 length to u32")?;
             dest.write_u8(ResourceField::InMemoryBytecodeOpt2.into())
                 .context("writing in-memory bytecode opt 2 field")?;
-            dest.write_u32::<LittleEndian>(l)
-                .context("writing in-memory bytecode opt 2 length")?;
+            self.write_in_memory_data_length_u32(
+                dest,
+                ResourceField::InMemoryBytecodeOpt2,
+                None,
+                bytecode,
+            )
+            .context("writing in-memory bytecode opt 2 length")?;
         }
 
         if let Some(library) = &self.in_memory_extension_module_shared_library {
@@ -593,8 +825,13 @@ where
                     .context("converting resource name length to u16")?;
                 dest.write_u16::<LittleEndian>(name_length)
                     .context("writing resource name length")?;
-                dest.write_u64::<LittleEndian>(value.len() as u64)
-                    .context("writing resource data length")?;
+                self.write_in_memory_data_length_u64(
+                    dest,
+                    ResourceField::InMemoryResourcesData,
+                    Some(name),
+                    value,
+                )
+                .context("writing resource data length")?;
             }
         }
 
@@ -611,8 +848,13 @@ where
                     .context("converting distribution name length to u16")?;
                 dest.write_u16::<LittleEndian>(name_length)
                     .context("writing distribution name length")?;
-                dest.write_u64::<LittleEndian>(value.len() as u64)
-                    .context("writing distribution data length")?;
+                self.write_in_memory_data_length_u64(
+                    dest,
+                    ResourceField::InMemoryDistributionResource,
+                    Some(name),
+                    value,
+                )
+                .context("writing distribution data length")?;
             }
         }
 
@@ -726,6 +968,22 @@ where
             }
         }
 
+        if let Some(data) = &self.in_memory_data_file {
+            dest.write_u8(ResourceField::InMemoryDataFile.into())
+                .context("writing in-memory data file field")?;
+            self.write_in_memory_data_length_u64(dest, ResourceField::InMemoryDataFile, None, data)
+                .context("writing in-memory data file length")?;
+        }
+
+        if let Some(path) = &self.relative_path_data_file {
+            let l = u32::try_from(path_bytes_length(path))
+                .context("converting data file relative path length to u32")?;
+            dest.write_u8(ResourceField::RelativeFilesystemDataFile.into())
+                .context("writing relative path data file field")?;
+            dest.write_u32::<LittleEndian>(l)
+                .context("writing relative path data file length")?;
+        }
+
         dest.write_u8(ResourceField::EndOfEntry.into())
             .or_else(|_| Err(anyhow!("error writing end of index entry")))?;
 
@@ -742,6 +1000,32 @@ pub fn write_embedded_resources_v1<W: Write>(
     dest: &mut W,
     interior_padding: Option<BlobInteriorPadding>,
 ) -> Result<()> {
+    if modules.iter().any(|m| m.in_memory_compression.is_some()) {
+        return Err(anyhow!(
+            "compressed in-memory data requires version 2 of the resources format"
+        ));
+    }
+
+    if modules.iter().any(|m| m.content_digest.is_some()) {
+        return Err(anyhow!(
+            "content digests require version 2 of the resources format"
+        ));
+    }
+
+    if modules.iter().any(|m| {
+        m.flavor == ResourceFlavor::DataFile
+            || m.in_memory_data_file.is_some()
+            || m.relative_path_data_file.is_some()
+    }) {
+        return Err(anyhow!(
+            "data files require version 2 of the resources format"
+        ));
+    }
+
+    for module in modules {
+        validate_resource(module)?;
+    }
+
     let mut blob_sections = BTreeMap::new();
 
     let mut blob_section_count = 0;
@@ -768,19 +1052,14 @@ pub fn write_embedded_resources_v1<W: Write>(
                     resource_field: field,
                     raw_payload_length: 0,
                     interior_padding,
+                    compression: None,
+                    alignment: None,
+                    entry_alignment: None,
                 })
                 .raw_payload_length += l;
         }
     };
 
-    let add_interior_padding = |dest: &mut W| -> Result<()> {
-        if interior_padding == Some(BlobInteriorPadding::Null) {
-            dest.write_all(b"\0")?;
-        }
-
-        Ok(())
-    };
-
     for module in modules {
         module_index_length += module.index_v1_length();
 
@@ -884,164 +1163,945 @@ pub fn write_embedded_resources_v1<W: Write>(
     dest.write_u8(ResourceField::EndOfIndex.into())?;
 
     // Write blob data, one field at a time.
-    for module in modules {
-        dest.write_all(module.name.as_bytes())?;
-        add_interior_padding(dest)?;
+    for field in blob_sections.keys() {
+        write_blob_section_data(modules, *field, interior_padding, None, dest)?;
     }
 
-    for module in modules {
-        if let Some(data) = &module.in_memory_source {
-            dest.write_all(data)?;
-            add_interior_padding(dest)?;
-        }
-    }
+    Ok(())
+}
 
-    for module in modules {
-        if let Some(data) = &module.in_memory_bytecode {
-            dest.write_all(data)?;
-            add_interior_padding(dest)?;
-        }
-    }
+/// Settings influencing how an embedded resources blob is written.
+#[derive(Clone, Debug)]
+pub struct WriteOptions {
+    /// Interior padding to use between entries in blob sections.
+    pub interior_padding: Option<BlobInteriorPadding>,
 
-    for module in modules {
-        if let Some(data) = &module.in_memory_bytecode_opt1 {
-            dest.write_all(data)?;
-            add_interior_padding(dest)?;
-        }
-    }
+    /// Compression algorithm to apply to the blob section of a resource field.
+    ///
+    /// Fields not present are stored without compression.
+    pub section_compression: BTreeMap<ResourceField, CompressionAlgorithm>,
 
-    for module in modules {
-        if let Some(data) = &module.in_memory_bytecode_opt2 {
-            dest.write_all(data)?;
-            add_interior_padding(dest)?;
-        }
-    }
+    /// Compression algorithm to apply to the in-memory data of each resource.
+    ///
+    /// Data for each resource is compressed independently, so readers only need
+    /// to decompress data for the resources they access. Resources whose
+    /// in-memory data is already compressed are written as-is.
+    pub resource_compression: Option<CompressionAlgorithm>,
 
-    for module in modules {
-        if let Some(data) = &module.in_memory_extension_module_shared_library {
-            dest.write_all(data)?;
-            add_interior_padding(dest)?;
-        }
-    }
+    /// Whether to compute and store a SHA-256 digest of each resource's content.
+    ///
+    /// Digests are computed after in-memory data is compressed.
+    pub resource_digests: bool,
 
-    for module in modules {
-        if let Some(resources) = &module.in_memory_package_resources {
-            for (key, value) in resources.iter() {
-                dest.write_all(key.as_bytes())?;
-                add_interior_padding(dest)?;
-                dest.write_all(value)?;
-                add_interior_padding(dest)?;
-            }
+    /// Whether to store a SHA-256 digest of the payload in the global header.
+    pub payload_digest: bool,
+
+    /// Whether to write a lookup index.
+    ///
+    /// The lookup index allows readers to find resources by name without
+    /// parsing the entire resources index.
+    pub lookup_index: bool,
+
+    /// Alignment of the start of each blob section, in bytes.
+    ///
+    /// Alignment is relative to the start of the packed resources data. So
+    /// sections are only aligned in memory if the data itself is loaded at an
+    /// address aligned to at least this boundary. Must be a power of 2.
+    pub section_alignment: Option<usize>,
+
+    /// Alignment of each entry in the blob section of a resource field, in bytes.
+    ///
+    /// Aligned entries, such as in-memory shared libraries, can be mapped or
+    /// handed to the operating system without copying. Alignment is relative
+    /// to the start of the packed resources data. Entry alignment can't be
+    /// combined with compression of the same section. Must be a power of 2.
+    pub entry_alignment: BTreeMap<ResourceField, usize>,
+
+    /// Compression level to use when compressing data.
+    pub compression_level: i32,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        WriteOptions {
+            interior_padding: None,
+            section_compression: BTreeMap::new(),
+            resource_compression: None,
+            resource_digests: false,
+            payload_digest: false,
+            lookup_index: false,
+            section_alignment: None,
+            entry_alignment: BTreeMap::new(),
+            compression_level: 3,
         }
     }
+}
 
-    for module in modules {
-        if let Some(resources) = &module.in_memory_distribution_resources {
-            for (key, value) in resources {
-                dest.write_all(key.as_bytes())?;
-                add_interior_padding(dest)?;
-                dest.write_all(value)?;
-                add_interior_padding(dest)?;
-            }
+impl WriteOptions {
+    /// Compress the blob sections holding in-memory data with an algorithm.
+    ///
+    /// Sections holding names and relative paths are small and are left
+    /// uncompressed.
+    pub fn compress_in_memory_sections(&mut self, algorithm: CompressionAlgorithm) {
+        for field in &[
+            ResourceField::InMemorySource,
+            ResourceField::InMemoryBytecode,
+            ResourceField::InMemoryBytecodeOpt1,
+            ResourceField::InMemoryBytecodeOpt2,
+            ResourceField::InMemoryExtensionModuleSharedLibrary,
+            ResourceField::InMemoryResourcesData,
+            ResourceField::InMemoryDistributionResource,
+            ResourceField::InMemorySharedLibrary,
+            ResourceField::InMemoryDataFile,
+        ] {
+            self.section_compression.insert(*field, algorithm);
         }
     }
+}
 
+/// Write an embedded resources blob, version 2.
+///
+/// Version 2 is version 1 plus support for compressing individual blob
+/// sections and the in-memory data of individual resources, for content
+/// digests, and for data files. Each section's data is assembled in memory so it can be compressed
+/// and digested before the blob index is written.
+pub fn write_embedded_resources_v2<W: Write>(
+    modules: &[Resource<u8>],
+    dest: &mut W,
+    options: &WriteOptions,
+) -> Result<()> {
     for module in modules {
-        if let Some(data) = &module.in_memory_shared_library {
-            dest.write_all(data)?;
-            add_interior_padding(dest)?;
-        }
+        validate_resource(module)?;
     }
 
+    let compressed_modules;
+    let modules = if let Some(algorithm) = options.resource_compression {
+        compressed_modules = modules
+            .iter()
+            .map(|m| m.compress_in_memory_data(algorithm, options.compression_level))
+            .collect::<Result<Vec<_>, &'static str>>()
+            .map_err(|e| anyhow!("compressing resource data: {}", e))?;
+        &compressed_modules
+    } else {
+        modules
+    };
+
+    let digested_modules;
+    let modules = if options.resource_digests {
+        digested_modules = modules
+            .iter()
+            .map(|m| m.with_content_digest())
+            .collect::<Vec<_>>();
+        &digested_modules
+    } else {
+        modules
+    };
+
+    let interior_padding = options.interior_padding;
+
+    let mut fields = BTreeSet::new();
+
+    // 1 for end of index field.
+    let mut module_index_length = 1;
+
     for module in modules {
-        if let Some(names) = &module.shared_library_dependency_names {
-            for name in names {
-                dest.write_all(name.as_bytes())?;
-                add_interior_padding(dest)?;
+        module_index_length += module.index_v1_length();
+
+        for field in BLOB_FIELDS {
+            let padding = match &interior_padding {
+                Some(padding) => *padding,
+                None => BlobInteriorPadding::None,
+            };
+
+            if module.field_blob_length(*field)
+                + module.field_blob_interior_padding_length(*field, padding)
+                > 0
+            {
+                fields.insert(*field);
             }
         }
     }
 
-    for module in modules {
-        if let Some(path) = &module.relative_path_module_source {
-            dest.write_all(&path_to_bytes(path))?;
-            add_interior_padding(dest)?;
-        }
+    let fields = fields.into_iter().collect::<Vec<_>>();
+
+    if let Some(alignment) = options.section_alignment {
+        validate_alignment(alignment)?;
     }
 
-    for module in modules {
-        if let Some(path) = &module.relative_path_module_bytecode {
-            dest.write_all(&path_to_bytes(path))?;
-            add_interior_padding(dest)?;
+    for (field, alignment) in &options.entry_alignment {
+        validate_alignment(*alignment)?;
+
+        if options.section_compression.contains_key(field) {
+            return Err(anyhow!(
+                "entry alignment can't be combined with section compression"
+            ));
         }
     }
 
-    for module in modules {
-        if let Some(path) = &module.relative_path_module_bytecode_opt1 {
-            dest.write_all(&path_to_bytes(path))?;
-            add_interior_padding(dest)?;
-        }
+    let mut blob_sections = fields
+        .iter()
+        .map(|field| BlobSection {
+            resource_field: *field,
+            raw_payload_length: 0,
+            interior_padding,
+            compression: options
+                .section_compression
+                .get(field)
+                .map(|algorithm| (*algorithm, 0)),
+            alignment: options.section_alignment,
+            entry_alignment: options.entry_alignment.get(field).copied(),
+        })
+        .collect::<Vec<_>>();
+
+    // 1 for end of index field.
+    let blob_index_length = 1 + blob_sections
+        .iter()
+        .map(|section| section.index_v2_length())
+        .sum::<usize>();
+
+    // Offset into resources index + name length + offset into each blob section.
+    let lookup_index_length = if options.lookup_index {
+        modules.len() * (4 + 2 + 4 * fields.len())
+    } else {
+        0
+    };
+
+    // Offset from the start of data, which alignment is relative to.
+    let mut offset = HEADER_V2.len()
+        + global_header_v2_length(options.payload_digest)
+        + blob_index_length
+        + module_index_length
+        + lookup_index_length;
+
+    let mut section_offsets = Vec::with_capacity(fields.len());
+    let mut blob_datas = Vec::with_capacity(fields.len());
+
+    for section in blob_sections.iter_mut() {
+        let padding = match section.alignment {
+            Some(alignment) => alignment_padding(offset, alignment),
+            None => 0,
+        };
+        offset += padding;
+
+        let mut data = Vec::new();
+        section_offsets.push(write_blob_section_data(
+            modules,
+            section.resource_field,
+            interior_padding,
+            section.entry_alignment.map(|alignment| (alignment, offset)),
+            &mut data,
+        )?);
+
+        let data = if let Some((algorithm, decompressed_length)) = section.compression.as_mut() {
+            let compressed = algorithm
+                .compress(&data, options.compression_level)
+                .map_err(|e| anyhow!("compressing blob section: {}", e))?;
+            *decompressed_length = data.len();
+            compressed
+        } else {
+            data
+        };
+
+        section.raw_payload_length = data.len();
+        offset += data.len();
+
+        blob_datas.push((padding, data));
     }
 
-    for module in modules {
-        if let Some(path) = &module.relative_path_module_bytecode_opt2 {
-            dest.write_all(&path_to_bytes(path))?;
-            add_interior_padding(dest)?;
-        }
+    let lookup_index = if options.lookup_index {
+        lookup_index_v2(modules, &section_offsets)?
+    } else {
+        vec![]
+    };
+
+    // Everything following the global header is assembled in memory so
+    // it can be digested.
+    let mut payload = Vec::new();
+
+    // Write the blob index.
+    for section in &blob_sections {
+        section.write_index_v2(&mut payload)?;
     }
+    payload.write_u8(ResourceField::EndOfIndex.into())?;
 
+    // Write the resources index.
     for module in modules {
-        if let Some(path) = &module.relative_path_extension_module_shared_library {
-            dest.write_all(&path_to_bytes(path))?;
-            add_interior_padding(dest)?;
-        }
+        module.write_index_v1(&mut payload)?;
     }
+    payload.write_u8(ResourceField::EndOfIndex.into())?;
 
-    for module in modules {
-        if let Some(resources) = &module.relative_path_package_resources {
-            for (key, path) in resources.iter() {
-                dest.write_all(key.as_bytes())?;
-                add_interior_padding(dest)?;
-                dest.write_all(&path_to_bytes(path))?;
-                add_interior_padding(dest)?;
-            }
-        }
+    payload.write_all(&lookup_index)?;
+
+    for (padding, data) in blob_datas {
+        payload.write_all(&vec![0; padding])?;
+        payload.write_all(&data)?;
     }
 
-    for module in modules {
-        if let Some(resources) = &module.relative_path_distribution_resources {
-            for (key, path) in resources {
-                dest.write_all(key.as_bytes())?;
-                add_interior_padding(dest)?;
-                dest.write_all(&path_to_bytes(path))?;
-                add_interior_padding(dest)?;
-            }
-        }
+    dest.write_all(HEADER_V2)?;
+
+    dest.write_u8(
+        u8::try_from(blob_sections.len()).context("converting blob section count to u8")?,
+    )?;
+    dest.write_u32::<LittleEndian>(blob_index_length as u32)?;
+    dest.write_u32::<LittleEndian>(modules.len() as u32)?;
+    dest.write_u32::<LittleEndian>(module_index_length as u32)?;
+
+    if options.payload_digest {
+        dest.write_u8(DIGEST_LENGTH as u8)?;
+        dest.write_all(&sha256_digest(&payload))?;
+    } else {
+        dest.write_u8(0)?;
     }
 
+    dest.write_u32::<LittleEndian>(
+        u32::try_from(lookup_index.len()).context("converting lookup index length to u32")?,
+    )?;
+
+    dest.write_all(&payload)?;
+
     Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use {super::*, crate::data::ResourceFlavor, std::borrow::Cow};
+/// Build the lookup index for a version 2 payload.
+///
+/// `section_offsets` holds the offset of each resource's data in each blob
+/// section, in blob index order.
+fn lookup_index_v2(modules: &[Resource<u8>], section_offsets: &[Vec<usize>]) -> Result<Vec<u8>> {
+    let mut entry_offset = 0;
+    let mut rows = Vec::with_capacity(modules.len());
 
-    #[test]
-    fn test_write_empty() -> Result<()> {
-        let mut data = Vec::new();
-        write_embedded_resources_v1(&[], &mut data, None)?;
+    for (i, module) in modules.iter().enumerate() {
+        let offsets = section_offsets
+            .iter()
+            .map(|offsets| offsets[i])
+            .collect::<Vec<_>>();
 
-        let mut expected: Vec<u8> = b"pyembed\x01".to_vec();
-        // Number of blob sections.
-        expected.write_u8(0)?;
-        // Length of blob index (end of index marker).
-        expected.write_u32::<LittleEndian>(1)?;
-        // Number of modules.
-        expected.write_u32::<LittleEndian>(0)?;
-        // Lenght of index (end of index marker).
-        expected.write_u32::<LittleEndian>(1)?;
-        // End of index for blob and modules.
-        expected.write_u8(0)?;
+        let row = lookup_index_row_v2(entry_offset, &module.name, &offsets)?;
+
+        entry_offset += module.index_v1_length();
+
+        rows.push((module.name.as_bytes(), row));
+    }
+
+    sorted_lookup_index_rows(rows)
+}
+
+/// Sort lookup index rows by name and concatenate them.
+///
+/// Lookups binary search the index by name, so names must be unique.
+fn sorted_lookup_index_rows(mut rows: Vec<(&[u8], Vec<u8>)>) -> Result<Vec<u8>> {
+    rows.sort_by(|a, b| a.0.cmp(b.0));
+
+    if let Some(pair) = rows.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+        return Err(anyhow!(
+            "duplicate resource name in lookup index: {}",
+            String::from_utf8_lossy(pair[0].0)
+        ));
+    }
+
+    Ok(rows.into_iter().flat_map(|(_, row)| row).collect())
+}
+
+/// Compute the length of the global header of a version 2 payload.
+///
+/// This excludes the magic header.
+fn global_header_v2_length(payload_digest: bool) -> usize {
+    // Blob section count, blob index length, resources count, resources
+    // index length, digest length, lookup index length.
+    let length = 1 + 4 + 4 + 4 + 1 + 4;
+
+    if payload_digest {
+        length + DIGEST_LENGTH
+    } else {
+        length
+    }
+}
+
+/// Validate properties of a resource not enforced by its type.
+fn validate_resource(resource: &Resource<u8>) -> Result<()> {
+    resource
+        .validate_package_resource_names()
+        .map_err(|e| anyhow!("resource {}: {}", resource.name, e))
+}
+
+/// Ensure an alignment value can be represented in a blob index.
+fn validate_alignment(alignment: usize) -> Result<()> {
+    if !alignment.is_power_of_two() || u32::try_from(alignment).is_err() {
+        Err(anyhow!("alignment must be a power of 2 fitting in a u32"))
+    } else {
+        Ok(())
+    }
+}
+
+/// Build a single row of the lookup index for a version 2 payload.
+///
+/// `section_offsets` are the offsets of the resource's data in each blob
+/// section, in blob index order.
+fn lookup_index_row_v2(
+    entry_offset: usize,
+    name: &str,
+    section_offsets: &[usize],
+) -> Result<Vec<u8>> {
+    let mut row = Vec::with_capacity(4 + 2 + 4 * section_offsets.len());
+
+    row.write_u32::<LittleEndian>(
+        u32::try_from(entry_offset).context("converting resources index offset to u32")?,
+    )?;
+    row.write_u16::<LittleEndian>(u16::try_from(name.len()).context("converting name to u16")?)?;
+
+    for offset in section_offsets {
+        row.write_u32::<LittleEndian>(
+            u32::try_from(*offset).context("converting blob section offset to u32")?,
+        )?;
+    }
+
+    Ok(row)
+}
+
+/// Create a temporary file, optionally in a specific directory.
+fn temp_file(temp_dir: Option<&Path>) -> Result<File> {
+    if let Some(temp_dir) = temp_dir {
+        tempfile::tempfile_in(temp_dir).context("creating temporary file")
+    } else {
+        tempfile::tempfile().context("creating temporary file")
+    }
+}
+
+/// Incrementally writes an embedded resources blob, version 2.
+///
+/// Unlike `write_embedded_resources_v2()`, resources are added one at a time
+/// and don't need to be held in memory. As each resource is added, its blob
+/// data is appended to a temporary file for each blob section. Only the
+/// resources index and lookup index entries are retained in memory. The
+/// blob is emitted by `finish()`.
+///
+/// Output is identical to `write_embedded_resources_v2()` given the same
+/// resources and options, except for compressed blob sections. These are
+/// compressed as a stream, which may produce different bytes.
+#[derive(Debug)]
+pub struct ResourcesWriter {
+    options: WriteOptions,
+
+    /// Directory to create temporary files in.
+    ///
+    /// If not set, the system's temporary directory is used.
+    temp_dir: Option<PathBuf>,
+
+    /// Temporary files holding the uncompressed data of each blob section.
+    sections: BTreeMap<ResourceField, BufWriter<File>>,
+
+    /// Length of data written to each blob section.
+    section_lengths: BTreeMap<ResourceField, usize>,
+
+    resources_count: usize,
+
+    /// The resources index, without its end of index marker.
+    resources_index: Vec<u8>,
+
+    /// Name, resources index offset and blob section offsets of each resource.
+    ///
+    /// Blob section offsets are in `BLOB_FIELDS` order.
+    lookup_entries: Vec<(String, usize, Vec<usize>)>,
+}
+
+impl ResourcesWriter {
+    /// Create a writer storing temporary data in the system's temporary directory.
+    pub fn new(options: WriteOptions) -> Self {
+        ResourcesWriter {
+            options,
+            temp_dir: None,
+            sections: BTreeMap::new(),
+            section_lengths: BTreeMap::new(),
+            resources_count: 0,
+            resources_index: vec![],
+            lookup_entries: vec![],
+        }
+    }
+
+    /// Create a writer storing temporary data in the specified directory.
+    pub fn new_in(options: WriteOptions, temp_dir: &Path) -> Self {
+        ResourcesWriter {
+            temp_dir: Some(temp_dir.to_path_buf()),
+            ..ResourcesWriter::new(options)
+        }
+    }
+
+    /// The number of resources added so far.
+    pub fn resources_count(&self) -> usize {
+        self.resources_count
+    }
+
+    /// Add a resource.
+    ///
+    /// Resources are written in the order they are added.
+    pub fn add_resource(&mut self, resource: &Resource<u8>) -> Result<()> {
+        if !self.options.entry_alignment.is_empty() {
+            return Err(anyhow!(
+                "entry alignment is not supported when writing incrementally"
+            ));
+        }
+
+        validate_resource(resource)?;
+
+        let compressed;
+        let resource = if let Some(algorithm) = self.options.resource_compression {
+            compressed = resource
+                .compress_in_memory_data(algorithm, self.options.compression_level)
+                .map_err(|e| anyhow!("compressing resource data: {}", e))?;
+            &compressed
+        } else {
+            resource
+        };
+
+        let digested;
+        let resource = if self.options.resource_digests {
+            digested = resource.with_content_digest();
+            &digested
+        } else {
+            resource
+        };
+
+        if self.options.lookup_index {
+            let section_offsets = BLOB_FIELDS
+                .iter()
+                .map(|field| *self.section_lengths.get(field).unwrap_or(&0))
+                .collect();
+
+            self.lookup_entries.push((
+                resource.name.to_string(),
+                self.resources_index.len(),
+                section_offsets,
+            ));
+        }
+
+        resource.write_index_v1(&mut self.resources_index)?;
+
+        let interior_padding = self.options.interior_padding;
+        let padding = match &interior_padding {
+            Some(padding) => *padding,
+            None => BlobInteriorPadding::None,
+        };
+
+        for field in BLOB_FIELDS {
+            let length = resource.field_blob_length(*field)
+                + resource.field_blob_interior_padding_length(*field, padding);
+
+            if length == 0 {
+                continue;
+            }
+
+            if !self.sections.contains_key(field) {
+                let file = temp_file(self.temp_dir.as_deref())?;
+                self.sections.insert(*field, BufWriter::new(file));
+            }
+
+            let dest = self.sections.get_mut(field).unwrap();
+            write_blob_section_data(
+                std::slice::from_ref(resource),
+                *field,
+                interior_padding,
+                None,
+                dest,
+            )
+            .context("writing blob section data")?;
+
+            *self.section_lengths.entry(*field).or_insert(0) += length;
+        }
+
+        self.resources_count += 1;
+
+        Ok(())
+    }
+
+    /// Finish writing, emitting the blob to a writer.
+    pub fn finish<W: Write>(self, dest: &mut W) -> Result<()> {
+        let options = &self.options;
+        let interior_padding = options.interior_padding;
+
+        if let Some(alignment) = options.section_alignment {
+            validate_alignment(alignment)?;
+        }
+
+        let mut blob_sections = Vec::with_capacity(self.sections.len());
+        let mut blob_datas = Vec::with_capacity(self.sections.len());
+
+        for (field, writer) in self.sections {
+            let mut file = writer
+                .into_inner()
+                .map_err(|e| anyhow!("flushing blob section data: {}", e.error()))?;
+            file.seek(SeekFrom::Start(0))?;
+
+            let length = self.section_lengths[&field];
+
+            let mut section = BlobSection {
+                resource_field: field,
+                raw_payload_length: length,
+                interior_padding,
+                compression: None,
+                alignment: options.section_alignment,
+                entry_alignment: None,
+            };
+
+            let file = if let Some(algorithm) = options.section_compression.get(&field) {
+                let mut compressed = temp_file(self.temp_dir.as_deref())?;
+                algorithm
+                    .compress_stream(&mut file, &mut compressed, options.compression_level)
+                    .map_err(|e| anyhow!("compressing blob section: {}", e))?;
+
+                section.compression = Some((*algorithm, length));
+                section.raw_payload_length = usize::try_from(compressed.metadata()?.len())
+                    .context("converting compressed blob section length to usize")?;
+
+                compressed.seek(SeekFrom::Start(0))?;
+                compressed
+            } else {
+                file
+            };
+
+            blob_sections.push(section);
+            blob_datas.push(file);
+        }
+
+        let lookup_index = if options.lookup_index {
+            // Offsets of each resource's data in the emitted blob sections.
+            let positions = blob_sections
+                .iter()
+                .map(|section| {
+                    BLOB_FIELDS
+                        .iter()
+                        .position(|field| *field == section.resource_field)
+                        .unwrap()
+                })
+                .collect::<Vec<_>>();
+
+            let mut rows = Vec::with_capacity(self.lookup_entries.len());
+
+            for (name, entry_offset, offsets) in &self.lookup_entries {
+                let section_offsets = positions.iter().map(|i| offsets[*i]).collect::<Vec<_>>();
+
+                rows.push((
+                    name.as_bytes(),
+                    lookup_index_row_v2(*entry_offset, name, &section_offsets)?,
+                ));
+            }
+
+            sorted_lookup_index_rows(rows)?
+        } else {
+            vec![]
+        };
+
+        // 1 for end of index field.
+        let blob_index_length = 1 + blob_sections
+            .iter()
+            .map(|section| section.index_v2_length())
+            .sum::<usize>();
+
+        // Everything in the payload but the blob data is assembled in memory.
+        let mut indices = Vec::new();
+
+        for section in &blob_sections {
+            section.write_index_v2(&mut indices)?;
+        }
+        indices.write_u8(ResourceField::EndOfIndex.into())?;
+
+        indices.write_all(&self.resources_index)?;
+        indices.write_u8(ResourceField::EndOfIndex.into())?;
+
+        indices.write_all(&lookup_index)?;
+
+        // Zero padding preceding each blob section.
+        let mut offset =
+            HEADER_V2.len() + global_header_v2_length(options.payload_digest) + indices.len();
+        let paddings = blob_sections
+            .iter()
+            .map(|section| {
+                let padding = match section.alignment {
+                    Some(alignment) => alignment_padding(offset, alignment),
+                    None => 0,
+                };
+                offset += padding + section.raw_payload_length;
+
+                padding
+            })
+            .collect::<Vec<_>>();
+
+        dest.write_all(HEADER_V2)?;
+
+        dest.write_u8(
+            u8::try_from(blob_sections.len()).context("converting blob section count to u8")?,
+        )?;
+        dest.write_u32::<LittleEndian>(blob_index_length as u32)?;
+        dest.write_u32::<LittleEndian>(
+            u32::try_from(self.resources_count).context("converting resources count to u32")?,
+        )?;
+        dest.write_u32::<LittleEndian>(
+            u32::try_from(self.resources_index.len() + 1)
+                .context("converting resources index length to u32")?,
+        )?;
+
+        if options.payload_digest {
+            // Blob data is read twice: once to digest it and once to emit it.
+            let mut hasher = Sha256::new();
+            hasher.input(&indices);
+
+            for (file, padding) in blob_datas.iter_mut().zip(&paddings) {
+                hasher.input(vec![0; *padding]);
+                std::io::copy(file, &mut hasher).context("digesting blob section data")?;
+                file.seek(SeekFrom::Start(0))?;
+            }
+
+            dest.write_u8(DIGEST_LENGTH as u8)?;
+            dest.write_all(&hasher.result())?;
+        } else {
+            dest.write_u8(0)?;
+        }
+
+        dest.write_u32::<LittleEndian>(
+            u32::try_from(lookup_index.len()).context("converting lookup index length to u32")?,
+        )?;
+
+        dest.write_all(&indices)?;
+
+        for (file, padding) in blob_datas.iter_mut().zip(&paddings) {
+            dest.write_all(&vec![0; *padding])?;
+            std::io::copy(file, dest).context("writing blob section data")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Resource fields that store data in blob sections, in blob section order.
+const BLOB_FIELDS: &[ResourceField] = &[
+    ResourceField::ModuleName,
+    ResourceField::ContentDigest,
+    ResourceField::InMemorySource,
+    ResourceField::InMemoryBytecode,
+    ResourceField::InMemoryBytecodeOpt1,
+    ResourceField::InMemoryBytecodeOpt2,
+    ResourceField::InMemoryExtensionModuleSharedLibrary,
+    ResourceField::InMemoryResourcesData,
+    ResourceField::InMemoryDistributionResource,
+    ResourceField::InMemorySharedLibrary,
+    ResourceField::SharedLibraryDependencyNames,
+    ResourceField::RelativeFilesystemModuleSource,
+    ResourceField::RelativeFilesystemModuleBytecode,
+    ResourceField::RelativeFilesystemModuleBytecodeOpt1,
+    ResourceField::RelativeFilesystemModuleBytecodeOpt2,
+    ResourceField::RelativeFilesystemExtensionModuleSharedLibrary,
+    ResourceField::RelativeFilesystemPackageResources,
+    ResourceField::RelativeFilesystemDistributionResource,
+    ResourceField::InMemoryDataFile,
+    ResourceField::RelativeFilesystemDataFile,
+];
+
+/// Writes the entries of a blob section, applying interior padding and alignment.
+struct BlobEntryWriter<'a, W: Write> {
+    dest: &'a mut W,
+    interior_padding: Option<BlobInteriorPadding>,
+    /// Alignment of entries and offset of the section from the start of data.
+    entry_alignment: Option<(usize, usize)>,
+    /// Number of bytes written to the section so far.
+    position: usize,
+}
+
+impl<'a, W: Write> BlobEntryWriter<'a, W> {
+    /// The position the next entry will be written at.
+    fn next_entry_position(&self) -> usize {
+        match self.entry_alignment {
+            Some((alignment, section_offset)) => {
+                self.position + alignment_padding(section_offset + self.position, alignment)
+            }
+            None => self.position,
+        }
+    }
+
+    fn write_entry(&mut self, data: &[u8]) -> Result<()> {
+        let position = self.next_entry_position();
+        self.dest.write_all(&vec![0; position - self.position])?;
+        self.position = position;
+
+        self.dest.write_all(data)?;
+        self.position += data.len();
+
+        if self.interior_padding == Some(BlobInteriorPadding::Null) {
+            self.dest.write_all(b"\0")?;
+            self.position += 1;
+        }
+
+        Ok(())
+    }
+}
+
+/// Write the blob section data for a single resource field.
+///
+/// `entry_alignment` holds the alignment of each entry and the offset of the
+/// section from the start of data, if entries are aligned.
+///
+/// Returns the offset of each resource's data within the section.
+#[allow(clippy::cognitive_complexity)]
+fn write_blob_section_data<W: Write>(
+    modules: &[Resource<u8>],
+    field: ResourceField,
+    interior_padding: Option<BlobInteriorPadding>,
+    entry_alignment: Option<(usize, usize)>,
+    dest: &mut W,
+) -> Result<Vec<usize>> {
+    let mut writer = BlobEntryWriter {
+        dest,
+        interior_padding,
+        entry_alignment,
+        position: 0,
+    };
+
+    let mut offsets = Vec::with_capacity(modules.len());
+
+    for module in modules {
+        offsets.push(writer.next_entry_position());
+
+        match field {
+            ResourceField::ModuleName => {
+                writer.write_entry(module.name.as_bytes())?;
+            }
+            ResourceField::ContentDigest => {
+                if let Some(digest) = &module.content_digest {
+                    writer.write_entry(digest)?;
+                }
+            }
+            ResourceField::InMemorySource => {
+                if let Some(data) = &module.in_memory_source {
+                    writer.write_entry(data)?;
+                }
+            }
+            ResourceField::InMemoryBytecode => {
+                if let Some(data) = &module.in_memory_bytecode {
+                    writer.write_entry(data)?;
+                }
+            }
+            ResourceField::InMemoryBytecodeOpt1 => {
+                if let Some(data) = &module.in_memory_bytecode_opt1 {
+                    writer.write_entry(data)?;
+                }
+            }
+            ResourceField::InMemoryBytecodeOpt2 => {
+                if let Some(data) = &module.in_memory_bytecode_opt2 {
+                    writer.write_entry(data)?;
+                }
+            }
+            ResourceField::InMemoryExtensionModuleSharedLibrary => {
+                if let Some(data) = &module.in_memory_extension_module_shared_library {
+                    writer.write_entry(data)?;
+                }
+            }
+            ResourceField::InMemoryResourcesData => {
+                if let Some(resources) = &module.in_memory_package_resources {
+                    for (key, value) in resources.iter() {
+                        writer.write_entry(key.as_bytes())?;
+                        writer.write_entry(value)?;
+                    }
+                }
+            }
+            ResourceField::InMemoryDistributionResource => {
+                if let Some(resources) = &module.in_memory_distribution_resources {
+                    for (key, value) in resources {
+                        writer.write_entry(key.as_bytes())?;
+                        writer.write_entry(value)?;
+                    }
+                }
+            }
+            ResourceField::InMemorySharedLibrary => {
+                if let Some(data) = &module.in_memory_shared_library {
+                    writer.write_entry(data)?;
+                }
+            }
+            ResourceField::SharedLibraryDependencyNames => {
+                if let Some(names) = &module.shared_library_dependency_names {
+                    for name in names {
+                        writer.write_entry(name.as_bytes())?;
+                    }
+                }
+            }
+            ResourceField::RelativeFilesystemModuleSource => {
+                if let Some(path) = &module.relative_path_module_source {
+                    writer.write_entry(&path_to_bytes(path))?;
+                }
+            }
+            ResourceField::RelativeFilesystemModuleBytecode => {
+                if let Some(path) = &module.relative_path_module_bytecode {
+                    writer.write_entry(&path_to_bytes(path))?;
+                }
+            }
+            ResourceField::RelativeFilesystemModuleBytecodeOpt1 => {
+                if let Some(path) = &module.relative_path_module_bytecode_opt1 {
+                    writer.write_entry(&path_to_bytes(path))?;
+                }
+            }
+            ResourceField::RelativeFilesystemModuleBytecodeOpt2 => {
+                if let Some(path) = &module.relative_path_module_bytecode_opt2 {
+                    writer.write_entry(&path_to_bytes(path))?;
+                }
+            }
+            ResourceField::RelativeFilesystemExtensionModuleSharedLibrary => {
+                if let Some(path) = &module.relative_path_extension_module_shared_library {
+                    writer.write_entry(&path_to_bytes(path))?;
+                }
+            }
+            ResourceField::RelativeFilesystemPackageResources => {
+                if let Some(resources) = &module.relative_path_package_resources {
+                    for (key, path) in resources.iter() {
+                        writer.write_entry(key.as_bytes())?;
+                        writer.write_entry(&path_to_bytes(path))?;
+                    }
+                }
+            }
+            ResourceField::RelativeFilesystemDistributionResource => {
+                if let Some(resources) = &module.relative_path_distribution_resources {
+                    for (key, path) in resources {
+                        writer.write_entry(key.as_bytes())?;
+                        writer.write_entry(&path_to_bytes(path))?;
+                    }
+                }
+            }
+            ResourceField::InMemoryDataFile => {
+                if let Some(data) = &module.in_memory_data_file {
+                    writer.write_entry(data)?;
+                }
+            }
+            ResourceField::RelativeFilesystemDataFile => {
+                if let Some(path) = &module.relative_path_data_file {
+                    writer.write_entry(&path_to_bytes(path))?;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(offsets)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        std::{borrow::Cow, collections::HashMap},
+    };
+
+    #[test]
+    fn test_write_empty() -> Result<()> {
+        let mut data = Vec::new();
+        write_embedded_resources_v1(&[], &mut data, None)?;
+
+        let mut expected: Vec<u8> = b"pyembed\x01".to_vec();
+        // Number of blob sections.
+        expected.write_u8(0)?;
+        // Length of blob index (end of index marker).
+        expected.write_u32::<LittleEndian>(1)?;
+        // Number of modules.
+        expected.write_u32::<LittleEndian>(0)?;
+        // Lenght of index (end of index marker).
+        expected.write_u32::<LittleEndian>(1)?;
+        // End of index for blob and modules.
+        expected.write_u8(0)?;
         expected.write_u8(0)?;
 
         assert_eq!(data, expected);
@@ -1092,4 +2152,331 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_write_v2_compressed_module_name() -> Result<()> {
+        let mut data = Vec::new();
+        let module = Resource {
+            flavor: ResourceFlavor::Module,
+            name: Cow::Owned("foo".to_string()),
+            ..Resource::default()
+        };
+
+        let mut options = WriteOptions::default();
+        options
+            .section_compression
+            .insert(ResourceField::ModuleName, CompressionAlgorithm::Zstd);
+
+        write_embedded_resources_v2(&[module], &mut data, &options)?;
+
+        let compressed = CompressionAlgorithm::Zstd
+            .compress(b"foo", 3)
+            .map_err(|e| anyhow!(e))?;
+
+        let mut expected: Vec<u8> = b"pyembed\x02".to_vec();
+        // Number of blob sections.
+        expected.write_u8(1)?;
+        // Length of blob index. Start of entry, field type, field value, length field, length,
+        // compression field, compression value, decompressed length field, decompressed length,
+        // end of entry, end of index.
+        expected.write_u32::<LittleEndian>(1 + 1 + 1 + 1 + 8 + 1 + 1 + 1 + 8 + 1 + 1)?;
+        // Number of modules.
+        expected.write_u32::<LittleEndian>(1)?;
+        // Length of index.
+        expected.write_u32::<LittleEndian>(1 + 1 + 1 + 1 + 2 + 1 + 1)?;
+        // Length of payload digest.
+        expected.write_u8(0)?;
+        // Length of lookup index.
+        expected.write_u32::<LittleEndian>(0)?;
+        // Blobs index.
+        expected.write_u8(BlobSectionField::StartOfEntry.into())?;
+        expected.write_u8(BlobSectionField::ResourceFieldType.into())?;
+        expected.write_u8(ResourceField::ModuleName.into())?;
+        expected.write_u8(BlobSectionField::RawPayloadLength.into())?;
+        expected.write_u64::<LittleEndian>(compressed.len() as u64)?;
+        expected.write_u8(BlobSectionField::Compression.into())?;
+        expected.write_u8(CompressionAlgorithm::Zstd.into())?;
+        expected.write_u8(BlobSectionField::DecompressedPayloadLength.into())?;
+        expected.write_u64::<LittleEndian>(b"foo".len() as u64)?;
+        expected.write_u8(BlobSectionField::EndOfEntry.into())?;
+        expected.write_u8(BlobSectionField::EndOfIndex.into())?;
+        // Module index.
+        expected.write_u8(ResourceField::StartOfEntry.into())?;
+        expected.write_u8(ResourceField::Flavor.into())?;
+        expected.write_u8(ResourceFlavor::Module.into())?;
+        expected.write_u8(ResourceField::ModuleName.into())?;
+        expected.write_u16::<LittleEndian>(b"foo".len() as u16)?;
+        expected.write_u8(ResourceField::EndOfEntry.into())?;
+        expected.write_u8(ResourceField::EndOfIndex.into())?;
+        expected.write_all(&compressed)?;
+
+        assert_eq!(data, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_v1_compressed_resource() -> Result<()> {
+        let module = Resource {
+            flavor: ResourceFlavor::Module,
+            name: Cow::Owned("foo".to_string()),
+            in_memory_source: Some(Cow::Owned(b"source".to_vec())),
+            ..Resource::default()
+        }
+        .compress_in_memory_data(CompressionAlgorithm::Zstd, 3)
+        .map_err(|e| anyhow!(e))?;
+
+        let mut data = Vec::new();
+        assert!(write_embedded_resources_v1(&[module], &mut data, None).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_v2_payload_digest() -> Result<()> {
+        let module = Resource {
+            flavor: ResourceFlavor::Module,
+            name: Cow::Owned("foo".to_string()),
+            ..Resource::default()
+        };
+
+        let options = WriteOptions {
+            payload_digest: true,
+            ..WriteOptions::default()
+        };
+
+        let mut data = Vec::new();
+        write_embedded_resources_v2(&[module], &mut data, &options)?;
+
+        // Magic header + 13 byte global header precedes the digest length.
+        assert_eq!(data[21] as usize, DIGEST_LENGTH);
+        assert_eq!(
+            &data[22..22 + DIGEST_LENGTH],
+            // The lookup index length follows the digest.
+            sha256_digest(&data[22 + DIGEST_LENGTH + 4..]).as_slice()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_v1_content_digest() -> Result<()> {
+        let module = Resource {
+            flavor: ResourceFlavor::Module,
+            name: Cow::Owned("foo".to_string()),
+            ..Resource::default()
+        }
+        .with_content_digest();
+
+        let mut data = Vec::new();
+        assert!(write_embedded_resources_v1(&[module], &mut data, None).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_package_resource_names() -> Result<()> {
+        let package = |name: &str| {
+            let mut resources = HashMap::new();
+            resources.insert(Cow::from(name.to_string()), Cow::from(b"data".to_vec()));
+
+            Resource {
+                flavor: ResourceFlavor::Module,
+                name: Cow::from("foo"),
+                is_package: true,
+                in_memory_package_resources: Some(resources),
+                ..Resource::default()
+            }
+        };
+
+        let mut data = Vec::new();
+        write_embedded_resources_v2(
+            &[package("templates/html/base.html")],
+            &mut data,
+            &WriteOptions::default(),
+        )?;
+
+        let resources = crate::parser::load_resources(&data)
+            .map_err(|e| anyhow!(e))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| anyhow!(e))?;
+        assert!(resources[0]
+            .in_memory_package_resources
+            .as_ref()
+            .unwrap()
+            .contains_key("templates/html/base.html"));
+
+        for name in &[
+            "",
+            "/base.html",
+            "templates/",
+            "templates//base.html",
+            "./base.html",
+            "../base.html",
+            "templates\\base.html",
+        ] {
+            let mut data = Vec::new();
+
+            assert!(write_embedded_resources_v1(&[package(name)], &mut data, None).is_err());
+            assert!(write_embedded_resources_v2(
+                &[package(name)],
+                &mut data,
+                &WriteOptions::default()
+            )
+            .is_err());
+            assert!(ResourcesWriter::new(WriteOptions::default())
+                .add_resource(&package(name))
+                .is_err());
+        }
+
+        Ok(())
+    }
+
+    fn streaming_test_resources() -> Vec<Resource<'static, u8>> {
+        let mut resources = HashMap::new();
+        resources.insert(Cow::from("resource.txt"), Cow::from(b"resource".to_vec()));
+
+        vec![
+            Resource {
+                flavor: ResourceFlavor::Module,
+                name: Cow::from("foo"),
+                is_package: true,
+                in_memory_source: Some(Cow::from(b"source".to_vec())),
+                in_memory_bytecode: Some(Cow::from(b"bytecode".to_vec())),
+                in_memory_package_resources: Some(resources),
+                ..Resource::default()
+            },
+            Resource {
+                flavor: ResourceFlavor::Module,
+                name: Cow::from("bar"),
+                relative_path_module_source: Some(Cow::from(Path::new("bar.py"))),
+                ..Resource::default()
+            },
+            Resource {
+                flavor: ResourceFlavor::Module,
+                name: Cow::from("foo.baz"),
+                in_memory_bytecode: Some(Cow::from(b"bytecode".to_vec())),
+                ..Resource::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn test_streaming_empty() -> Result<()> {
+        let options = WriteOptions::default();
+
+        let mut expected = Vec::new();
+        write_embedded_resources_v2(&[], &mut expected, &options)?;
+
+        let writer = ResourcesWriter::new(options);
+        assert_eq!(writer.resources_count(), 0);
+
+        let mut data = Vec::new();
+        writer.finish(&mut data)?;
+
+        assert_eq!(data, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_streaming_matches_v2() -> Result<()> {
+        let resources = streaming_test_resources();
+
+        let mut all_options = vec![
+            WriteOptions::default(),
+            WriteOptions {
+                interior_padding: Some(BlobInteriorPadding::Null),
+                ..WriteOptions::default()
+            },
+            WriteOptions {
+                resource_compression: Some(CompressionAlgorithm::Zstd),
+                ..WriteOptions::default()
+            },
+        ];
+
+        for options in all_options.iter_mut() {
+            options.resource_digests = true;
+            options.payload_digest = true;
+            options.lookup_index = true;
+        }
+
+        all_options.push(WriteOptions::default());
+        all_options.push(WriteOptions {
+            payload_digest: true,
+            lookup_index: true,
+            section_alignment: Some(4096),
+            ..WriteOptions::default()
+        });
+
+        for options in all_options {
+            let mut expected = Vec::new();
+            write_embedded_resources_v2(&resources, &mut expected, &options)?;
+
+            let mut writer = ResourcesWriter::new(options);
+            for resource in &resources {
+                writer.add_resource(resource)?;
+            }
+            assert_eq!(writer.resources_count(), resources.len());
+
+            let mut data = Vec::new();
+            writer.finish(&mut data)?;
+
+            assert_eq!(data, expected);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_v2_invalid_alignment() {
+        let mut data = Vec::new();
+
+        let options = WriteOptions {
+            section_alignment: Some(3),
+            ..WriteOptions::default()
+        };
+        assert!(write_embedded_resources_v2(&[], &mut data, &options).is_err());
+        assert!(ResourcesWriter::new(options).finish(&mut data).is_err());
+
+        let mut options = WriteOptions::default();
+        options
+            .entry_alignment
+            .insert(ResourceField::InMemorySource, 16);
+        options
+            .section_compression
+            .insert(ResourceField::InMemorySource, CompressionAlgorithm::Zstd);
+        assert!(write_embedded_resources_v2(&[], &mut data, &options).is_err());
+
+        let mut options = WriteOptions::default();
+        options
+            .entry_alignment
+            .insert(ResourceField::InMemorySource, 16);
+        let mut writer = ResourcesWriter::new(options);
+        assert!(writer.add_resource(&streaming_test_resources()[0]).is_err());
+    }
+
+    #[test]
+    fn test_write_v2_lookup_index_duplicate_names() -> Result<()> {
+        let resource = streaming_test_resources()[0].clone();
+        let resources = vec![resource.clone(), resource.clone()];
+
+        let options = WriteOptions {
+            lookup_index: true,
+            ..WriteOptions::default()
+        };
+
+        let mut data = Vec::new();
+        assert!(write_embedded_resources_v2(&resources, &mut data, &options).is_err());
+
+        let mut writer = ResourcesWriter::new(options);
+        writer.add_resource(&resource)?;
+        writer.add_resource(&resource)?;
+        assert!(writer.finish(&mut data).is_err());
+
+        // Duplicates are allowed when no lookup index is written.
+        write_embedded_resources_v2(&resources, &mut data, &WriteOptions::default())?;
+
+        Ok(())
+    }
 }